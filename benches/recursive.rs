@@ -11,12 +11,33 @@ pub fn clone_repo<P: AsRef<Path>>(url: &str, path: P) {
     let status = Command::new("git")
         .arg("clone")
         .arg(url)
-        .arg(&path)
+        .arg(path)
         .status()
         .expect("Failed to get status");
     println!("Exit status {}", status);
 }
 
+#[cfg(feature = "rayon")]
+fn copy_dir_all_par_bridge(from: &Path, to: &Path) {
+    use rayon::prelude::*;
+
+    walkdir::WalkDir::new(from)
+        .into_iter()
+        .par_bridge()
+        .for_each(|entry| {
+            let entry = entry.unwrap();
+            let new_path = to.join(entry.path().strip_prefix(from).unwrap());
+            if entry.file_type().is_dir() {
+                std::fs::create_dir_all(&new_path).unwrap();
+            } else {
+                std::fs::create_dir_all(new_path.parent().unwrap()).unwrap();
+                if entry.file_type().is_file() {
+                    std::fs::copy(entry.path(), &new_path).unwrap();
+                }
+            }
+        });
+}
+
 fn bench_on_repo<M: criterion::measurement::Measurement>(
     dir: &TestDir,
     url: &str,
@@ -51,6 +72,41 @@ fn bench_on_repo<M: criterion::measurement::Measurement>(
         )
     });
 
+    // what `copy_dir_all_par` did before it had its own walker
+    #[cfg(feature = "rayon")]
+    group.bench_function(format!("multi threaded walkdir par_bridge {}", url), |b| {
+        b.iter_batched(
+            setup,
+            |_| copy_dir_all_par_bridge(&from, &to),
+            BatchSize::PerIteration,
+        )
+    });
+
+    #[cfg(feature = "rayon")]
+    {
+        let copy = || {
+            setup();
+            more_fs::copy_dir_all(&from, &to).unwrap();
+        };
+        group.bench_function(format!("remove_dir_all {}", url), |b| {
+            b.iter_batched(
+                copy,
+                |_| more_fs::remove_dir_all(&to).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+        group.bench_function(format!("remove_dir_all_par {}", url), |b| {
+            b.iter_batched(
+                copy,
+                |_| more_fs::remove_dir_all_par(&to).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+        group.bench_function(format!("disk_usage {}", url), |b| {
+            b.iter(|| more_fs::disk_usage(&from).unwrap())
+        });
+    }
+
     let mut fs_extra_copy_opt = fs_extra::dir::CopyOptions::new();
     fs_extra_copy_opt.copy_inside = true;
 
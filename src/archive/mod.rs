@@ -0,0 +1,327 @@
+/*!
+Packing directory trees into archives and unpacking them again. Tar archives are enabled with the
+`tar` feature flag and zip archives with the `zip` feature flag. With the `rayon` feature the files
+of a zip archive can be compressed in parallel with [`zip_dir_par`].
+
+The archives are read and written with the same walker as [`copy_dir_all`](crate::copy_dir_all).
+Which entries are packed and which metadata is restored is set with [`ArchiveOptions`].
+Archives can come from anywhere, so unpacking never writes outside of the destination: entries with
+absolute paths or `..` components, symlinks that point outside of the destination, also by going
+through symlinks that were unpacked before, and entries that would be written through a symlink
+are rejected with [`Error::PathEscape`].
+*/
+
+#[cfg(feature = "tar")]
+mod tar;
+#[cfg(feature = "zip")]
+mod zip;
+
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::Operation;
+use crate::vfs::{DirEntry, FileSystem, RealFs};
+use crate::{Error, Result, SpecialFiles};
+
+/// How many symlinks are followed while checking the target of a single symlink, the same limit
+/// as Linux
+const MAX_SYMLINKS: usize = 40;
+
+#[cfg(feature = "tar")]
+pub use self::tar::{pack_dir, pack_dir_with, unpack_dir, unpack_dir_with};
+#[cfg(feature = "zip")]
+pub use self::zip::{unzip_dir, unzip_dir_with, zip_dir, zip_dir_with, ZipCompression};
+#[cfg(all(feature = "zip", feature = "rayon"))]
+pub use self::zip::{zip_dir_par, zip_dir_par_with};
+
+type Filter = Arc<dyn Fn(&DirEntry) -> bool + Send + Sync>;
+
+/// Options for the `_with` variants of the archive functions.
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use more_fs::{pack_dir_with, ArchiveOptions};
+///
+/// let archive = File::create("from_directory.tar").unwrap();
+/// pack_dir_with(
+///     "from_directory",
+///     archive,
+///     ArchiveOptions::new().filter(|entry| entry.path().file_name() != Some("target".as_ref())),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ArchiveOptions {
+    filter: Option<Filter>,
+    special_files: SpecialFiles,
+    mtime: bool,
+    special_bits: bool,
+    #[cfg(feature = "zip")]
+    compression: ZipCompression,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        ArchiveOptions {
+            filter: None,
+            special_files: SpecialFiles::default(),
+            mtime: true,
+            special_bits: false,
+            #[cfg(feature = "zip")]
+            compression: ZipCompression::default(),
+        }
+    }
+}
+
+impl fmt::Debug for ArchiveOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ArchiveOptions");
+        debug
+            .field("filter", &self.filter.is_some())
+            .field("special_files", &self.special_files)
+            .field("mtime", &self.mtime)
+            .field("special_bits", &self.special_bits);
+        #[cfg(feature = "zip")]
+        debug.field("compression", &self.compression);
+        debug.finish()
+    }
+}
+
+impl ArchiveOptions {
+    /// Creates the default options, these behave exactly like the functions without options
+    pub fn new() -> ArchiveOptions {
+        ArchiveOptions::default()
+    }
+
+    /// Only packs the entries for which `filter` returns true. The contents of a directory that
+    /// is filtered out are skipped as well. The root is never passed to the filter
+    pub fn filter(
+        &mut self,
+        filter: impl Fn(&DirEntry) -> bool + Send + Sync + 'static,
+    ) -> &mut ArchiveOptions {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// What happens to fifos, sockets and device files, both in the tree that is packed and in
+    /// the archive that is unpacked. [`SpecialFiles::Recreate`] stores and unpacks them, except
+    /// for sockets which no archive can hold and device files which need privileges to be
+    /// unpacked. Defaults to [`SpecialFiles::Error`]
+    pub fn special_files(&mut self, special_files: SpecialFiles) -> &mut ArchiveOptions {
+        self.special_files = special_files;
+        self
+    }
+
+    /// Restores the modification times stored in the archive when unpacking. Defaults to true
+    pub fn mtime(&mut self, mtime: bool) -> &mut ArchiveOptions {
+        self.mtime = mtime;
+        self
+    }
+
+    /// Restores the setuid, setgid and sticky bits when unpacking. Archives can come from
+    /// anywhere, so by default only the read, write and execute bits are restored
+    pub fn special_bits(&mut self, special_bits: bool) -> &mut ArchiveOptions {
+        self.special_bits = special_bits;
+        self
+    }
+
+    /// How the files of a zip archive are compressed. Defaults to [`ZipCompression::default`]
+    #[cfg(feature = "zip")]
+    pub fn compression(&mut self, compression: ZipCompression) -> &mut ArchiveOptions {
+        self.compression = compression;
+        self
+    }
+}
+
+/// Walks `from` with the same walker as [`copy_dir_all`](crate::copy_dir_all), skipping the
+/// root itself and everything that the filter leaves out
+fn walk<'a>(
+    from: &Path,
+    options: &'a ArchiveOptions,
+) -> impl Iterator<Item = Result<DirEntry>> + 'a {
+    let fs: &'static RealFs = &RealFs;
+    let mut excluded: Option<PathBuf> = None;
+
+    fs.walk(from).filter(move |entry| {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => return true,
+        };
+        if entry.depth() == 0
+            || excluded
+                .as_ref()
+                .is_some_and(|dir| entry.path().starts_with(dir))
+        {
+            return false;
+        }
+
+        let keep = options.filter.as_ref().is_none_or(|filter| filter(entry));
+        if !keep && entry.file_type().is_dir() {
+            excluded = Some(entry.path().to_path_buf());
+        }
+        keep
+    })
+}
+
+/// Whether a fifo, socket or device file is packed or unpacked, according to
+/// [`ArchiveOptions::special_files`]. `storable` is false for the ones the archive can't hold
+fn keep_special(
+    path: &Path,
+    file_type: crate::vfs::FileType,
+    storable: bool,
+    options: &ArchiveOptions,
+) -> Result<bool> {
+    match options.special_files {
+        SpecialFiles::Error => Err(Error::SpecialFile {
+            path: path.to_path_buf(),
+            file_type,
+        }),
+        SpecialFiles::Skip => Ok(false),
+        SpecialFiles::Recreate => Ok(storable),
+    }
+}
+
+/// Turns the path of an archive entry into a relative path below `root`. Leading `./` components
+/// are removed
+fn entry_path(path: &Path, root: &Path) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => (),
+            Component::Prefix(_) | Component::RootDir | Component::ParentDir => {
+                return Err(Error::PathEscape {
+                    path: path.to_path_buf(),
+                    root: root.to_path_buf(),
+                })
+            }
+        }
+    }
+
+    Ok(relative)
+}
+
+/// Makes sure that none of the existing parents of `root/relative` are symlinks, otherwise an
+/// entry could be written through the symlink to anywhere
+fn check_parents(root: &Path, relative: &Path) -> Result<()> {
+    let mut current = root.to_path_buf();
+
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        current.push(component);
+
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(Error::PathEscape {
+                    path: root.join(relative),
+                    root: root.to_path_buf(),
+                })
+            }
+            Ok(_) => (),
+            // everything below will be created by us
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => {
+                return Err(Error::IoExt {
+                    source: e,
+                    path: current,
+                    operation: Operation::Metadata,
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes sure that a symlink at `root/relative` pointing to `target` stays inside of `root`.
+///
+/// The target is resolved against what was already unpacked: symlinks on the way are followed
+/// and a `..` may only leave a directory that exists. A name that doesn't exist yet could be
+/// unpacked as a symlink later, so `s/..` is only allowed when `s` is a real directory
+fn check_symlink_target(root: &Path, relative: &Path, target: &Path) -> Result<()> {
+    let escape = || Error::PathEscape {
+        path: root.join(relative),
+        root: root.to_path_buf(),
+    };
+    // the target is resolved relative to the directory containing the symlink
+    let mut resolved = root.join(relative.parent().unwrap_or(relative));
+    let mut depth = relative.components().count().saturating_sub(1);
+
+    let mut pending: VecDeque<_> = components(target).ok_or_else(escape)?;
+    let mut followed = 0;
+    while let Some(name) = pending.pop_front() {
+        let name = match name {
+            Some(name) => name,
+            None => {
+                depth = depth.checked_sub(1).ok_or_else(escape)?;
+                if !fs::symlink_metadata(&resolved).is_ok_and(|metadata| metadata.is_dir()) {
+                    return Err(escape());
+                }
+                resolved.pop();
+                continue;
+            }
+        };
+
+        resolved.push(&name);
+        depth += 1;
+        let metadata = match fs::symlink_metadata(&resolved) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(io_ext(e, &resolved, Operation::Metadata)),
+        };
+        if metadata.file_type().is_symlink() {
+            followed += 1;
+            if followed > MAX_SYMLINKS {
+                return Err(escape());
+            }
+            let link =
+                fs::read_link(&resolved).map_err(|e| io_ext(e, &resolved, Operation::ReadLink))?;
+            resolved.pop();
+            depth -= 1;
+            let mut link = components(&link).ok_or_else(escape)?;
+            link.append(&mut pending);
+            pending = link;
+        }
+    }
+
+    Ok(())
+}
+
+/// The components of a relative symlink target, `None` stands for `..`. Absolute targets are
+/// `None` as a whole
+fn components(path: &Path) -> Option<VecDeque<Option<OsString>>> {
+    let mut components = VecDeque::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push_back(Some(name.to_os_string())),
+            Component::ParentDir => components.push_back(None),
+            Component::CurDir => (),
+            Component::Prefix(_) | Component::RootDir => return None,
+        }
+    }
+
+    Some(components)
+}
+
+fn ignore_not_found(e: Error) -> Result<()> {
+    if e.io_error_kind() == io::ErrorKind::NotFound {
+        Ok(())
+    } else {
+        Err(e)
+    }
+}
+
+fn io_ext(source: io::Error, path: &Path, operation: Operation) -> Error {
+    Error::IoExt {
+        source,
+        path: path.to_path_buf(),
+        operation,
+    }
+}
@@ -0,0 +1,271 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use ::tar::{Archive, Builder, EntryType, Header};
+
+use super::{
+    check_parents, check_symlink_target, entry_path, ignore_not_found, io_ext, keep_special, walk,
+    ArchiveOptions,
+};
+use crate::error::Operation;
+use crate::trace;
+use crate::utils::change_dir;
+#[cfg(unix)]
+use crate::vfs::make_special;
+use crate::vfs::{FileType, RealFs};
+use crate::{check_path_copy_dir_all, create_dir_all, Result};
+
+/// Packs a directory tree into a tar archive written to `writer`. The paths in the archive are
+/// relative to `from`, symlinks are stored as symlinks. Returns the writer so that a compressing
+/// writer can be finished afterwards.
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let archive = File::create("from_directory.tar").unwrap();
+/// more_fs::pack_dir("from_directory", archive).unwrap();
+/// ```
+pub fn pack_dir<W: Write>(from: impl AsRef<Path>, writer: W) -> Result<W> {
+    pack_dir_with(from, writer, &ArchiveOptions::new())
+}
+
+/// The same as [`pack_dir`] but with options
+pub fn pack_dir_with<W: Write>(
+    from: impl AsRef<Path>,
+    writer: W,
+    options: &ArchiveOptions,
+) -> Result<W> {
+    let from = from.as_ref();
+
+    let span = trace::operation!("pack_dir", from = %from.display());
+    trace::traced(span, || {
+        check_path_copy_dir_all(&RealFs, from)?;
+
+        let mut builder = Builder::new(writer);
+        builder.follow_symlinks(false);
+
+        for entry in walk(from, options) {
+            let entry = entry?;
+            let path = entry.path();
+            let name = change_dir(from, "", path)?;
+            trace::entry!(path = %path.display(), "pack");
+
+            let file_type = entry.file_type();
+            if file_type.is_special() {
+                let storable = cfg!(unix) && special_entry_type(file_type).is_some();
+                if keep_special(path, file_type, storable, options)? {
+                    append_special(&mut builder, path, &name, file_type)
+                        .map_err(|e| io_ext(e, path, Operation::Pack))?;
+                }
+                continue;
+            }
+
+            builder
+                .append_path_with_name(path, &name)
+                .map_err(|e| io_ext(e, path, Operation::Pack))?;
+        }
+
+        builder
+            .into_inner()
+            .map_err(|e| io_ext(e, from, Operation::Pack))
+    })
+}
+
+/// Unpacks a tar archive read from `reader` into the directory `to`, creating it if it does not
+/// exist. Permissions without the setuid, setgid and sticky bits and modification times are
+/// restored.
+///
+/// Entries that would end up outside of `to` fail with [`Error::PathEscape`](crate::Error::PathEscape) before anything is
+/// written for them.
+pub fn unpack_dir<R: Read>(reader: R, to: impl AsRef<Path>) -> Result<()> {
+    unpack_dir_with(reader, to, &ArchiveOptions::new())
+}
+
+/// The same as [`unpack_dir`] but with options
+pub fn unpack_dir_with<R: Read>(
+    reader: R,
+    to: impl AsRef<Path>,
+    options: &ArchiveOptions,
+) -> Result<()> {
+    let to = to.as_ref();
+
+    let span = trace::operation!("unpack_dir", to = %to.display());
+    trace::traced(span, || {
+        create_dir_all(to)?;
+
+        let mut archive = Archive::new(reader);
+        // the tar crate only keeps the special bits when permissions are "preserved"
+        archive.set_preserve_permissions(options.special_bits);
+        archive.set_preserve_mtime(options.mtime);
+
+        for entry in archive
+            .entries()
+            .map_err(|e| io_ext(e, to, Operation::Unpack))?
+        {
+            let mut entry = entry.map_err(|e| io_ext(e, to, Operation::Unpack))?;
+            let raw_path = entry
+                .path()
+                .map_err(|e| io_ext(e, to, Operation::Unpack))?
+                .into_owned();
+            let relative = entry_path(&raw_path, to)?;
+
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+
+            let target = to.join(&relative);
+            trace::entry!(path = %target.display(), "unpack");
+            let entry_type = entry.header().entry_type();
+            let special = special_type(entry_type);
+            if let Some(file_type) = special {
+                if !keep_special(&target, file_type, true, options)? {
+                    continue;
+                }
+            }
+
+            check_parents(to, &relative)?;
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+
+            if let Some(file_type) = special {
+                // the tar crate would unpack them as empty regular files
+                unpack_special(entry.header(), &target, file_type, options)?;
+                continue;
+            }
+
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                let link_name = entry
+                    .link_name()
+                    .map_err(|e| io_ext(e, &target, Operation::Unpack))?
+                    .ok_or_else(|| io_ext(missing_link_name(), &target, Operation::Unpack))?
+                    .into_owned();
+
+                if entry_type.is_hard_link() {
+                    // hard links point to another entry of the archive
+                    let source = entry_path(&link_name, to)?;
+                    check_parents(to, &source)?;
+                    // a link to a symlink is a copy of it, its target has to stay inside from
+                    // the new place as well
+                    if crate::symlink_metadata(to.join(&source))
+                        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+                    {
+                        let target = crate::read_link(to.join(&source))?;
+                        check_symlink_target(to, &relative, &target)?;
+                    }
+                    crate::remove_file(&target).or_else(ignore_not_found)?;
+                    std::fs::hard_link(to.join(&source), &target)
+                        .map_err(|e| io_ext(e, &target, Operation::Unpack))?;
+                    continue;
+                }
+
+                check_symlink_target(to, &relative, &link_name)?;
+            }
+
+            entry
+                .unpack(&target)
+                .map_err(|e| io_ext(e, &target, Operation::Unpack))?;
+        }
+
+        Ok(())
+    })
+}
+
+/// The type of the special file that a tar entry creates
+fn special_type(entry_type: EntryType) -> Option<FileType> {
+    match entry_type {
+        EntryType::Fifo => Some(FileType::Fifo),
+        EntryType::Char => Some(FileType::CharDevice),
+        EntryType::Block => Some(FileType::BlockDevice),
+        _ => None,
+    }
+}
+
+/// Creates a fifo or device file. Device files need privileges, without them they are skipped
+#[cfg(unix)]
+fn unpack_special(
+    header: &Header,
+    target: &Path,
+    file_type: FileType,
+    options: &ArchiveOptions,
+) -> Result<()> {
+    let mode = header
+        .mode()
+        .map_err(|e| io_ext(e, target, Operation::Unpack))?;
+    let mode = mode & if options.special_bits { 0o7777 } else { 0o777 };
+    let device = match (header.device_major(), header.device_minor()) {
+        (Ok(Some(major)), Ok(Some(minor))) => {
+            let (major, minor) = (u64::from(major), u64::from(minor));
+            ((major & 0xffff_f000) << 32)
+                | ((major & 0xfff) << 8)
+                | ((minor & 0xffff_ff00) << 12)
+                | (minor & 0xff)
+        }
+        _ => 0,
+    };
+
+    crate::remove_file(target).or_else(ignore_not_found)?;
+    match make_special(target, file_type, mode, device) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => result.map_err(|e| io_ext(e, target, Operation::Unpack)),
+    }
+}
+
+#[cfg(not(unix))]
+fn unpack_special(
+    _header: &Header,
+    _target: &Path,
+    _file_type: FileType,
+    _options: &ArchiveOptions,
+) -> Result<()> {
+    Ok(())
+}
+
+/// The tar entry type of a special file, sockets and unknown types can't be stored
+fn special_entry_type(file_type: FileType) -> Option<EntryType> {
+    match file_type {
+        FileType::Fifo => Some(EntryType::Fifo),
+        FileType::CharDevice => Some(EntryType::Char),
+        FileType::BlockDevice => Some(EntryType::Block),
+        _ => None,
+    }
+}
+
+/// Appends a fifo or device file. The tar crate itself stores them under their full path
+/// instead of the name in the archive
+#[cfg(unix)]
+fn append_special<W: Write>(
+    builder: &mut Builder<W>,
+    path: &Path,
+    name: &Path,
+    file_type: FileType,
+) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::symlink_metadata(path)?;
+    let mut header = Header::new_gnu();
+    header.set_metadata(&metadata);
+    header.set_size(0);
+    if let Some(entry_type) = special_entry_type(file_type) {
+        header.set_entry_type(entry_type);
+    }
+    // the same split of the device number into major and minor as glibc
+    let device = metadata.rdev();
+    header.set_device_major((((device >> 32) & 0xffff_f000) | ((device >> 8) & 0xfff)) as u32)?;
+    header.set_device_minor((((device >> 12) & 0xffff_ff00) | (device & 0xff)) as u32)?;
+    builder.append_data(&mut header, name, io::empty())
+}
+
+#[cfg(not(unix))]
+fn append_special<W: Write>(
+    _builder: &mut Builder<W>,
+    _path: &Path,
+    _name: &Path,
+    _file_type: FileType,
+) -> io::Result<()> {
+    unreachable!("special files are never stored without unix")
+}
+
+fn missing_link_name() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "link entry without a target")
+}
@@ -0,0 +1,462 @@
+use std::convert::TryFrom;
+use std::fs::{self, File};
+#[cfg(feature = "rayon")]
+use std::io::Cursor;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ::zip::write::SimpleFileOptions;
+use ::zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::{
+    check_parents, check_symlink_target, entry_path, ignore_not_found, io_ext, keep_special, walk,
+    ArchiveOptions,
+};
+use crate::error::Operation;
+use crate::trace;
+use crate::utils::change_dir;
+use crate::vfs::{DirEntry, FileType, RealFs};
+use crate::{check_path_copy_dir_all, create_dir_all, Error, Result};
+
+/// How the files of a zip archive are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZipCompression {
+    /// Store the files without compressing them
+    Stored,
+    /// Deflate with a level from 0 to 9, this is what every zip tool can read
+    Deflate(i64),
+    /// Zstandard with a level from 1 to 22
+    Zstd(i64),
+}
+
+impl Default for ZipCompression {
+    fn default() -> Self {
+        ZipCompression::Deflate(6)
+    }
+}
+
+/// How many files every thread compresses before the results are written to the archive. This
+/// bounds the amount of compressed data that is kept in memory
+#[cfg(feature = "rayon")]
+const FILES_PER_THREAD: usize = 4;
+
+/// Files up to this size are compressed in memory by [`zip_dir_par`], larger ones are compressed
+/// while they are written to the archive. Together with [`FILES_PER_THREAD`] this caps the memory
+/// at 64 MiB per thread
+#[cfg(feature = "rayon")]
+const MAX_IN_MEMORY: u64 = 16 * 1024 * 1024;
+
+/// An entry of the archive that is ready to be written
+enum Prepared {
+    Dir {
+        name: String,
+        options: SimpleFileOptions,
+    },
+    Symlink {
+        name: String,
+        target: String,
+        options: SimpleFileOptions,
+    },
+    /// A file that is compressed while it is written to the archive
+    File {
+        name: String,
+        path: PathBuf,
+        options: SimpleFileOptions,
+    },
+    /// An archive containing only the compressed file
+    #[cfg(feature = "rayon")]
+    Compressed(Cursor<Vec<u8>>),
+    /// A special file, zip archives can't hold them
+    Skipped,
+}
+
+/// Packs a directory tree into a zip archive written to `writer`, compressed with
+/// [`ZipCompression::default`]. The unix permissions of every entry are stored in the archive
+/// and symlinks are stored as symlinks. Returns the writer.
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// let archive = File::create("from_directory.zip").unwrap();
+/// more_fs::zip_dir("from_directory", archive).unwrap();
+/// ```
+pub fn zip_dir<W: Write + Seek>(from: impl AsRef<Path>, writer: W) -> Result<W> {
+    zip_dir_with(from, writer, &ArchiveOptions::new())
+}
+
+/// The same as [`zip_dir`] but with options, the compression is set with
+/// [`ArchiveOptions::compression`]. Zip archives can't hold fifos, sockets and device files, so
+/// [`SpecialFiles::Recreate`](crate::SpecialFiles::Recreate) leaves them out like
+/// [`SpecialFiles::Skip`](crate::SpecialFiles::Skip)
+pub fn zip_dir_with<W: Write + Seek>(
+    from: impl AsRef<Path>,
+    writer: W,
+    options: &ArchiveOptions,
+) -> Result<W> {
+    let from = from.as_ref();
+
+    let span = trace::operation!("zip_dir", from = %from.display());
+    trace::traced(span, || {
+        check_path_copy_dir_all(&RealFs, from)?;
+
+        let mut zip = ZipWriter::new(writer);
+        for entry in walk(from, options) {
+            let entry = entry?;
+            trace::entry!(path = %entry.path().display(), "zip");
+            let prepared = prepare(from, &entry, options)?;
+            write_prepared(&mut zip, prepared, entry.path())?;
+        }
+
+        zip.finish()
+            .map_err(|e| zip_error(e, from, Operation::Pack))
+    })
+}
+
+/// The same as [`zip_dir`] but the files are compressed in parallel
+#[cfg(feature = "rayon")]
+pub fn zip_dir_par<W: Write + Seek>(from: impl AsRef<Path>, writer: W) -> Result<W> {
+    zip_dir_par_with(from, writer, &ArchiveOptions::new())
+}
+
+/// The same as [`zip_dir_with`] but the files are compressed in parallel. Every file is
+/// compressed into its own small archive in memory which is then copied into the real archive
+/// without compressing it again. Files larger than 16 MiB are compressed while they are written
+/// instead, so every thread holds at most 64 MiB of compressed data
+#[cfg(feature = "rayon")]
+pub fn zip_dir_par_with<W: Write + Seek>(
+    from: impl AsRef<Path>,
+    writer: W,
+    options: &ArchiveOptions,
+) -> Result<W> {
+    let from = from.as_ref();
+
+    let span = trace::operation!("zip_dir_par", from = %from.display());
+    trace::traced(span, || {
+        check_path_copy_dir_all(&RealFs, from)?;
+        let entries = walk(from, options).collect::<Result<Vec<_>>>()?;
+
+        let mut zip = ZipWriter::new(writer);
+        let scope = trace::Scope::current();
+        let chunk_size = rayon::current_num_threads() * FILES_PER_THREAD;
+        for chunk in entries.chunks(chunk_size) {
+            let prepared = chunk
+                .par_iter()
+                .map(|entry| {
+                    scope.in_scope(|| {
+                        trace::entry!(path = %entry.path().display(), "zip");
+                        prepare(from, entry, options).and_then(compress)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            for (prepared, entry) in prepared.into_iter().zip(chunk) {
+                write_prepared(&mut zip, prepared, entry.path())?;
+            }
+        }
+
+        zip.finish()
+            .map_err(|e| zip_error(e, from, Operation::Pack))
+    })
+}
+
+fn prepare(from: &Path, entry: &DirEntry, archive_options: &ArchiveOptions) -> Result<Prepared> {
+    let path = entry.path();
+    let file_type = entry.file_type();
+    if file_type.is_special() {
+        keep_special(path, file_type, false, archive_options)?;
+        return Ok(Prepared::Skipped);
+    }
+
+    let name = entry_name(&change_dir(from, "", path)?);
+    let metadata = fs::symlink_metadata(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::Metadata,
+    })?;
+    let options = file_options(&metadata, archive_options.compression);
+
+    let prepared = match file_type {
+        FileType::Dir => Prepared::Dir { name, options },
+        FileType::Symlink => {
+            let target = fs::read_link(path).map_err(|e| io_ext(e, path, Operation::Pack))?;
+            Prepared::Symlink {
+                name,
+                target: entry_name(&target),
+                options,
+            }
+        }
+        _ => Prepared::File {
+            name,
+            path: path.to_path_buf(),
+            options,
+        },
+    };
+
+    Ok(prepared)
+}
+
+/// Compresses a file that isn't too large into its own archive in memory
+#[cfg(feature = "rayon")]
+fn compress(prepared: Prepared) -> Result<Prepared> {
+    let (name, path, options) = match prepared {
+        Prepared::File {
+            name,
+            path,
+            options,
+        } => (name, path, options),
+        prepared => return Ok(prepared),
+    };
+    let mut file = File::open(&path).map_err(|e| io_ext(e, &path, Operation::Pack))?;
+    let len = file
+        .metadata()
+        .map_err(|e| io_ext(e, &path, Operation::Metadata))?
+        .len();
+    if len > MAX_IN_MEMORY {
+        return Ok(Prepared::File {
+            name,
+            path,
+            options,
+        });
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(name, options)
+        .map_err(|e| zip_error(e, &path, Operation::Pack))?;
+    io::copy(&mut file, &mut zip).map_err(|e| io_ext(e, &path, Operation::Pack))?;
+    let compressed = zip
+        .finish()
+        .map_err(|e| zip_error(e, &path, Operation::Pack))?;
+    Ok(Prepared::Compressed(compressed))
+}
+
+fn write_prepared<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    prepared: Prepared,
+    path: &Path,
+) -> Result<()> {
+    match prepared {
+        Prepared::Dir { name, options } => zip.add_directory(name, options),
+        Prepared::Symlink {
+            name,
+            target,
+            options,
+        } => zip.add_symlink(name, target, options),
+        Prepared::File {
+            name,
+            path,
+            options,
+        } => {
+            let mut file = File::open(&path).map_err(|e| io_ext(e, &path, Operation::Pack))?;
+            zip.start_file(name, options)
+                .map_err(|e| zip_error(e, &path, Operation::Pack))?;
+            return io::copy(&mut file, zip)
+                .map(drop)
+                .map_err(|e| io_ext(e, &path, Operation::Pack));
+        }
+        #[cfg(feature = "rayon")]
+        Prepared::Compressed(compressed) => {
+            ZipArchive::new(compressed).and_then(|compressed| zip.merge_archive(compressed))
+        }
+        Prepared::Skipped => Ok(()),
+    }
+    .map_err(|e| zip_error(e, path, Operation::Pack))
+}
+
+fn file_options(metadata: &fs::Metadata, compression: ZipCompression) -> SimpleFileOptions {
+    let (method, level) = match compression {
+        ZipCompression::Stored => (CompressionMethod::Stored, None),
+        ZipCompression::Deflate(level) => (CompressionMethod::Deflated, Some(level)),
+        ZipCompression::Zstd(level) => (CompressionMethod::Zstd, Some(level)),
+    };
+
+    let mut options = SimpleFileOptions::default()
+        .compression_method(method)
+        .compression_level(level)
+        .large_file(metadata.len() >= u64::from(u32::MAX))
+        .unix_permissions(unix_mode(metadata));
+    if let Some(modified) = metadata.modified().ok().and_then(zip_time) {
+        options = options.last_modified_time(modified);
+    }
+    options
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn unix_mode(metadata: &fs::Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+/// Zip archives always use `/` as the separator
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Converts a time to the calendar time in UTC that is stored in zip archives
+fn zip_time(time: SystemTime) -> Option<DateTime> {
+    let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let time = OffsetDateTime::from_unix_timestamp(i64::try_from(secs).ok()?).ok()?;
+    DateTime::try_from(PrimitiveDateTime::new(time.date(), time.time())).ok()
+}
+
+/// The inverse of [`zip_time`]
+fn system_time(time: DateTime) -> Option<SystemTime> {
+    let time = PrimitiveDateTime::try_from(time).ok()?;
+    Some(time.assume_utc().into())
+}
+
+/// Unpacks a zip archive into the directory `to`, creating it if it does not exist. The unix
+/// permissions stored in the archive without the setuid, setgid and sticky bits are restored on
+/// unix, as well as the modification times. Directories get theirs after everything inside them
+/// was written.
+///
+/// Entries that would end up outside of `to` fail with [`Error::PathEscape`] before anything is
+/// written for them.
+pub fn unzip_dir<R: Read + Seek>(reader: R, to: impl AsRef<Path>) -> Result<()> {
+    unzip_dir_with(reader, to, &ArchiveOptions::new())
+}
+
+/// The same as [`unzip_dir`] but with options
+pub fn unzip_dir_with<R: Read + Seek>(
+    reader: R,
+    to: impl AsRef<Path>,
+    options: &ArchiveOptions,
+) -> Result<()> {
+    let to = to.as_ref();
+
+    let span = trace::operation!("unzip_dir", to = %to.display());
+    trace::traced(span, || {
+        create_dir_all(to)?;
+
+        let mut archive =
+            ZipArchive::new(reader).map_err(|e| zip_error(e, to, Operation::Unpack))?;
+        let mut dirs = Vec::new();
+        for index in 0..archive.len() {
+            let mut file = archive
+                .by_index(index)
+                .map_err(|e| zip_error(e, to, Operation::Unpack))?;
+            let relative = entry_path(Path::new(file.name()), to)?;
+
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+
+            let target = to.join(&relative);
+            trace::entry!(path = %target.display(), "unzip");
+            check_parents(to, &relative)?;
+
+            if file.is_dir() {
+                create_dir_all(&target)?;
+                let modified = file.last_modified().and_then(system_time);
+                dirs.push((target, file.unix_mode(), modified));
+                continue;
+            }
+
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+            // never write through something that is already there
+            crate::remove_file(&target).or_else(ignore_not_found)?;
+
+            if file.is_symlink() {
+                let mut link = String::new();
+                file.read_to_string(&mut link)
+                    .map_err(|e| io_ext(e, &target, Operation::Unpack))?;
+                check_symlink_target(to, &relative, Path::new(&link))?;
+                symlink(Path::new(&link), &target)?;
+                continue;
+            }
+
+            let mut out = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&target)
+                .map_err(|e| io_ext(e, &target, Operation::Unpack))?;
+            io::copy(&mut file, &mut out).map_err(|e| io_ext(e, &target, Operation::Unpack))?;
+            if let Some(modified) = file.last_modified().and_then(system_time) {
+                if options.mtime {
+                    out.set_modified(modified)
+                        .map_err(|e| io_ext(e, &target, Operation::Unpack))?;
+                }
+            }
+            set_mode(&target, file.unix_mode(), options)?;
+        }
+
+        // writing the contents changes the time of a directory and a read only directory can't
+        // be filled, so they are finished afterwards from the deepest up
+        dirs.sort_by_key(|(dir, _, _)| std::cmp::Reverse(dir.components().count()));
+        for (dir, mode, modified) in dirs {
+            if let Some(modified) = modified.filter(|_| options.mtime) {
+                set_dir_modified(&dir, modified).map_err(|e| io_ext(e, &dir, Operation::Unpack))?;
+            }
+            set_mode(&dir, mode, options)?;
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>, options: &ArchiveOptions) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mask = if options.special_bits { 0o7777 } else { 0o777 };
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode & mask))
+            .map_err(|e| io_ext(e, path, Operation::Unpack)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>, _options: &ArchiveOptions) -> Result<()> {
+    Ok(())
+}
+
+/// A directory is opened to change its time, which needs extra flags on windows
+fn set_dir_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_WRITE_ATTRIBUTES and FILE_FLAG_BACKUP_SEMANTICS
+        options.access_mode(0x100).custom_flags(0x0200_0000);
+    }
+    #[cfg(not(windows))]
+    options.read(true);
+
+    options.open(path)?.set_modified(modified)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, path: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, path).map_err(|e| io_ext(e, path, Operation::Unpack))
+}
+
+/// Symlinks need special privileges on windows, so they are unpacked as a file containing the
+/// target like git does
+#[cfg(not(unix))]
+fn symlink(target: &Path, path: &Path) -> Result<()> {
+    fs::write(path, target.to_string_lossy().as_bytes())
+        .map_err(|e| io_ext(e, path, Operation::Unpack))
+}
+
+fn zip_error(source: ::zip::result::ZipError, path: &Path, operation: Operation) -> Error {
+    io_ext(source.into(), path, operation)
+}
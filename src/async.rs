@@ -0,0 +1,301 @@
+/*!
+Async versions of the functions in this crate, enabled with the `tokio` feature flag.
+
+Filesystem operations are blocking on every major platform, so these functions run the work on
+tokio's blocking thread pool instead of blocking the executor. The recursive functions copy many
+files at once, bounded by [`CopyOptions::concurrency`](crate::CopyOptions::concurrency).
+
+Dropping one of the returned futures stops the work cleanly: the directory walk ends, files that
+are waiting for their turn are never copied and only the copies that are already running are
+finished. The errors are the same [`Error`](crate::Error) type as the rest of the crate.
+
+```no_run
+# async fn run() -> more_fs::Result<()> {
+use more_fs::r#async::copy_dir_all;
+
+copy_dir_all("from_directory", "to_directory").await?;
+# Ok(())
+# }
+```
+*/
+
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::{self, JoinError, JoinSet};
+use walkdir::WalkDir;
+
+use crate::trace::{self, Scope};
+use crate::utils::change_dir;
+use crate::vfs::FileType;
+use crate::{CopyOptions, LockMode, RealFs, Report, Result};
+
+/// How many files are copied at once if [`CopyOptions::concurrency`] was not set
+const DEFAULT_CONCURRENCY: usize = 64;
+
+/// How many walked entries can wait in the channel before the walker has to wait
+const WALK_BUFFER: usize = 256;
+
+/// Runs a blocking function on tokio's blocking thread pool, in the span of the caller
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let scope = Scope::current();
+    unwrap_join(task::spawn_blocking(move || scope.in_scope(f)).await)
+}
+
+/// Our blocking tasks are never aborted, so the only possible join error is a panic that we
+/// propagate to the caller
+fn unwrap_join<T>(res: std::result::Result<Result<T>, JoinError>) -> Result<T> {
+    match res {
+        Ok(res) => res,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Async version of [`crate::copy`]
+pub async fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+    let (from, to) = owned(from, to);
+    blocking(move || crate::copy(from, to)).await
+}
+
+/// Async version of [`crate::copy_with`]
+pub async fn copy_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    let (from, to) = owned(from, to);
+    let options = options.clone();
+    blocking(move || crate::copy_with(from, to, &options)).await
+}
+
+/// Async version of [`crate::copy_create`]
+pub async fn copy_create(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+    copy_create_with(from, to, &CopyOptions::new()).await
+}
+
+/// Async version of [`crate::copy_create_with`]
+pub async fn copy_create_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    let (from, to) = owned(from, to);
+    let options = options.clone();
+    blocking(move || crate::copy_create_with(from, to, &options)).await
+}
+
+/// Async version of [`crate::move_file`]
+pub async fn move_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+    move_file_with(from, to, &CopyOptions::new()).await
+}
+
+/// Async version of [`crate::move_file_with`]
+pub async fn move_file_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    let (from, to) = owned(from, to);
+    let options = options.clone();
+    blocking(move || crate::move_file_with(from, to, &options)).await
+}
+
+/// Async version of [`crate::remove_file`]
+pub async fn remove_file(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || crate::remove_file(path)).await
+}
+
+/// Async version of [`crate::remove_dir_all`]
+pub async fn remove_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || crate::remove_dir_all(path)).await
+}
+
+/// Async version of [`crate::create_dir`]
+pub async fn create_dir(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || crate::create_dir(path)).await
+}
+
+/// Async version of [`crate::create_dir_all`]
+pub async fn create_dir_all(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || crate::create_dir_all(path)).await
+}
+
+/// Async version of [`crate::move_dir_all`]
+pub async fn move_dir_all(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+    move_dir_all_with(from, to, &CopyOptions::new()).await
+}
+
+/// Async version of [`crate::move_dir_all_with`]
+pub async fn move_dir_all_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
+    let (from, to) = owned(from, to);
+
+    let span = trace::operation!("move_dir_all", from = %from.display(), to = %to.display());
+    trace::traced_async(span, async {
+        let _lock = lock_tree(&from, &to, LockMode::Exclusive, options).await?;
+        let mut report = copy_dir_all_with(&from, to, &options.without_lock()).await?;
+        let start = Instant::now();
+        let (from, moved) = (from.to_path_buf(), report.clone());
+        blocking(move || {
+            trace::phase!("remove").in_scope(|| crate::remove_moved(&RealFs, &from, &moved))
+        })
+        .await?;
+        report.timings.remove = start.elapsed();
+
+        Ok(report)
+    })
+    .await
+}
+
+/// Async version of [`crate::copy_dir_all`]
+pub async fn copy_dir_all(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+    copy_dir_all_with(from, to, &CopyOptions::new()).await
+}
+
+/// Async version of [`crate::copy_dir_all_with`]. Directories are created in the order they are
+/// walked, while the files are copied concurrently
+pub async fn copy_dir_all_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
+    let (from, to) = owned(from, to);
+    let span = trace::operation!("copy_dir_all", from = %from.display(), to = %to.display());
+    trace::traced_async(span, async {
+        let _lock = lock_tree(&from, &to, LockMode::Shared, options).await?;
+        let options = Arc::new(options.clone());
+
+        let preflight = {
+            let (from, to, preflight) = (from.clone(), to.clone(), options.preflight);
+            blocking(move || {
+                crate::check_copy_dir_all(&RealFs, &from, &to)?;
+                let start = Instant::now();
+                if preflight {
+                    trace::phase!("preflight")
+                        .in_scope(|| crate::preflight::preflight(&from, &to))?;
+                }
+                Ok(start.elapsed())
+            })
+            .await?
+        };
+        let start = Instant::now();
+
+        let (sender, mut receiver) = mpsc::channel(WALK_BUFFER);
+        {
+            let from = from.clone();
+            // the walk stops as soon as the receiver is dropped
+            task::spawn_blocking(move || {
+                for entry in WalkDir::new(from) {
+                    if sender.blocking_send(entry).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let semaphore = Arc::new(Semaphore::new(
+            options.concurrency.unwrap_or(DEFAULT_CONCURRENCY),
+        ));
+        // dropping the join set aborts every copy that has not started yet
+        let mut copies = JoinSet::new();
+        let mut dirs = Vec::new();
+
+        let copy = async {
+            let mut report = Report::default();
+            while let Some(entry) = receiver.recv().await {
+                let entry = entry?;
+                let path = entry.path().to_path_buf();
+                let new_path = change_dir(&from, &to, &path)?;
+
+                if entry.file_type().is_dir() {
+                    // the files inside of this directory are only sent after it, so it has to exist
+                    // before we continue
+                    {
+                        let (new_path, options) = (new_path.clone(), Arc::clone(&options));
+                        blocking(move || crate::create_dir_merging(&RealFs, &new_path, &options))
+                            .await?;
+                    }
+                    trace::entry!(path = %new_path.display(), "mkdir");
+                    report.dirs += 1;
+                    dirs.push((path, new_path));
+                } else {
+                    let permit = Arc::clone(&semaphore)
+                        .acquire_owned()
+                        .await
+                        .expect("the semaphore is never closed");
+                    let options = Arc::clone(&options);
+                    let file_type = FileType::from(entry.file_type());
+                    let scope = Scope::current();
+                    copies.spawn_blocking(move || {
+                        let _permit = permit;
+                        let mut report = Report::default();
+                        scope.in_scope(|| {
+                            crate::copy_or_create(
+                                &RealFs,
+                                file_type,
+                                path,
+                                new_path,
+                                &options,
+                                &mut report,
+                            )
+                        })?;
+                        Ok(report)
+                    });
+                }
+
+                // surface errors early instead of after the whole tree was walked
+                while let Some(res) = copies.try_join_next() {
+                    report = report.merge(unwrap_join(res)?);
+                }
+            }
+
+            while let Some(res) = copies.join_next().await {
+                report = report.merge(unwrap_join(res)?);
+            }
+            Ok::<_, crate::Error>(report)
+        };
+        let mut report = trace::instrument(trace::phase!("copy"), copy).await?;
+        report.timings.preflight = preflight;
+        report.timings.copy = start.elapsed();
+
+        let start = Instant::now();
+        blocking(move || {
+            trace::phase!("finish").in_scope(|| crate::finish_dirs(&RealFs, &to, &dirs, &options))
+        })
+        .await?;
+        report.timings.finish = start.elapsed();
+
+        Ok(report)
+    })
+    .await
+}
+
+/// Takes the locks of [`CopyOptions::lock`] on a blocking thread, the destination is always
+/// locked exclusive
+async fn lock_tree(
+    from: &Path,
+    to: &Path,
+    from_mode: LockMode,
+    options: &CopyOptions,
+) -> Result<crate::lock::TreeLock> {
+    let (from, to, options) = (from.to_path_buf(), to.to_path_buf(), options.clone());
+    blocking(move || crate::lock::lock_tree(&options, &[(&from, from_mode)], Some(&to))).await
+}
+
+fn owned(from: impl AsRef<Path>, to: impl AsRef<Path>) -> (PathBuf, PathBuf) {
+    (from.as_ref().to_path_buf(), to.as_ref().to_path_buf())
+}
@@ -0,0 +1,92 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::options::{Backup, CopyOptions};
+use crate::vfs::FileSystem;
+
+/// The suffix of simple backups if none is set, the same as GNU `cp`
+const DEFAULT_SUFFIX: &str = "~";
+
+/// Renames the file at `to` out of the way according to [`CopyOptions::backup`] before it is
+/// overwritten by `from`. Directories and missing files are left alone, and so is a destination
+/// that is the source itself, the copy reports that
+pub(crate) fn backup<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: &Path,
+    to: &Path,
+    options: &CopyOptions,
+) -> Result<()> {
+    if options.backup == Backup::None {
+        return Ok(());
+    }
+    match fs.metadata(to) {
+        Ok(metadata) if !metadata.is_dir() => (),
+        _ => return Ok(()),
+    }
+    if let (Ok(from), Ok(to)) = (fs.canonicalize(from), fs.canonicalize(to)) {
+        if from == to {
+            return Ok(());
+        }
+    }
+
+    // only numbered backups need to look at the other files of the directory
+    let backup = match options.backup {
+        Backup::None => unreachable!(),
+        Backup::Simple => simple(to, options),
+        Backup::Numbered | Backup::Existing => match highest_numbered(fs, to)? {
+            None if options.backup == Backup::Existing => simple(to, options),
+            highest => numbered(to, highest.unwrap_or(0) + 1),
+        },
+    };
+    fs.rename(to, &backup)
+}
+
+fn simple(path: &Path, options: &CopyOptions) -> PathBuf {
+    let suffix = options
+        .backup_suffix
+        .as_deref()
+        .unwrap_or_else(|| DEFAULT_SUFFIX.as_ref());
+    with_suffix(path, suffix.to_os_string())
+}
+
+/// The highest number of the existing numbered backups of `path`, like 7 for `name.~7~`
+fn highest_numbered<F: FileSystem + ?Sized>(fs: &F, path: &Path) -> Result<Option<u64>> {
+    let name = match path.file_name() {
+        Some(name) => name.as_encoded_bytes(),
+        None => return Ok(None),
+    };
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let highest = fs
+        .read_dir(parent)?
+        .iter()
+        .filter_map(|entry| {
+            // names that aren't valid unicode still have to match exactly
+            let number = entry
+                .as_encoded_bytes()
+                .strip_prefix(name)?
+                .strip_prefix(b".~")?
+                .strip_suffix(b"~")?;
+            // `parse` would also take a sign
+            if !number.iter().all(u8::is_ascii_digit) {
+                return None;
+            }
+            std::str::from_utf8(number).ok()?.parse().ok()
+        })
+        .max();
+    Ok(highest)
+}
+
+fn numbered(path: &Path, n: u64) -> PathBuf {
+    with_suffix(path, format!(".~{}~", n).into())
+}
+
+fn with_suffix(path: &Path, suffix: OsString) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use more_fs::vfs::FileType;
+use more_fs::{Error, Operation, Result};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::relative;
+
+/// The differences between two trees, the paths are relative to their roots. Symlinks are
+/// followed, the same way the copy functions copy their targets
+#[derive(Serialize)]
+pub struct Diff {
+    /// Entries that are only in the left tree. The contents of a missing directory are not listed
+    pub only_left: Vec<PathBuf>,
+    /// Entries that are only in the right tree
+    pub only_right: Vec<PathBuf>,
+    /// Entries that are in both trees but have a different type, length or contents
+    pub changed: Vec<PathBuf>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.only_left.is_empty() && self.only_right.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn print(&self) {
+        for path in &self.only_left {
+            println!("- {}", path.display());
+        }
+        for path in &self.only_right {
+            println!("+ {}", path.display());
+        }
+        for path in &self.changed {
+            println!("~ {}", path.display());
+        }
+    }
+}
+
+pub fn diff(left: &Path, right: &Path) -> Result<Diff> {
+    let mut diff = Diff {
+        only_left: Vec::new(),
+        only_right: Vec::new(),
+        changed: Vec::new(),
+    };
+
+    let mut walk = WalkDir::new(left)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        let path = relative(left, entry.path())?;
+        let other = right.join(path);
+
+        let other_type = match more_fs::metadata(&other) {
+            Ok(metadata) => FileType::from(metadata.file_type()),
+            Err(e) if e.io_error_kind() == io::ErrorKind::NotFound => {
+                if entry.file_type().is_dir() {
+                    walk.skip_current_dir();
+                }
+                diff.only_left.push(path.to_path_buf());
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let file_type = FileType::from(entry.file_type());
+        if file_type != other_type {
+            if file_type.is_dir() {
+                walk.skip_current_dir();
+            }
+            diff.changed.push(path.to_path_buf());
+        } else if differs(file_type, entry.path(), &other)? {
+            diff.changed.push(path.to_path_buf());
+        }
+    }
+
+    let mut walk = WalkDir::new(right)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        let path = relative(right, entry.path())?;
+
+        if left.join(path).metadata().is_err() {
+            if entry.file_type().is_dir() {
+                walk.skip_current_dir();
+            }
+            diff.only_right.push(path.to_path_buf());
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Compares two entries of the same type. Directories and special files are always the same
+fn differs(file_type: FileType, left: &Path, right: &Path) -> Result<bool> {
+    if !file_type.is_file() {
+        return Ok(false);
+    }
+    if more_fs::metadata(left)?.len() != more_fs::metadata(right)?.len() {
+        return Ok(true);
+    }
+
+    same_contents(left, right)
+        .map(|same| !same)
+        .map_err(|e| Error::IoExtMulti {
+            source: e,
+            from: left.to_path_buf(),
+            to: right.to_path_buf(),
+            operation: Operation::Read,
+        })
+}
+
+fn same_contents(left: &Path, right: &Path) -> io::Result<bool> {
+    let mut left = BufReader::new(fs::File::open(left)?);
+    let mut right = BufReader::new(fs::File::open(right)?);
+
+    loop {
+        let (a, b) = (left.fill_buf()?, right.fill_buf()?);
+        if a.is_empty() || b.is_empty() {
+            return Ok(a.is_empty() && b.is_empty());
+        }
+        let amount = a.len().min(b.len());
+        if a[..amount] != b[..amount] {
+            return Ok(false);
+        }
+        left.consume(amount);
+        right.consume(amount);
+    }
+}
@@ -0,0 +1,534 @@
+//! The `more-fs` command line tool, a thin layer over the parallel functions of the library.
+//!
+//! Every command prints a short summary, or with `--json` the report of the operation. Errors
+//! are printed to stderr and mapped to an exit code, checkout [`exit_code`].
+
+mod diff;
+mod progress;
+mod sync;
+
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use indicatif::HumanBytes;
+use more_fs::vfs::FileType;
+use more_fs::{
+    ConflictAction, CopyOptions, DiskUsage, DuOptions, Durability, Error, Parallelism, Report,
+    Result, SpecialFiles,
+};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0   success, for diff the trees are the same
+  1   diff found differences
+  2   invalid arguments
+  64  the destination is inside the source or the same file, or a path is not a directory
+  65  a special file or a path outside of the root was found
+  66  a path does not exist
+  70  internal error, like a result that can't be printed as JSON
+  73  the preflight checks failed or a conflict aborted the copy
+  74  any other I/O error
+  75  a lock was held by someone else
+  77  permission denied";
+
+#[derive(Parser)]
+#[command(name = "more-fs", version, about = "Fast recursive file operations", after_help = EXIT_CODES)]
+struct Cli {
+    /// Print the report as JSON instead of a summary
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Don't show a progress bar
+    #[arg(long, global = true)]
+    no_progress: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Copy a file or a directory tree
+    Cp {
+        from: PathBuf,
+        to: PathBuf,
+        /// What to do with files that already exist at the destination
+        #[arg(long, value_enum)]
+        conflict: Option<Conflict>,
+        #[command(flatten)]
+        copy: CopyArgs,
+    },
+    /// Move a file or a directory tree, it is copied if it can't be renamed
+    Mv {
+        from: PathBuf,
+        to: PathBuf,
+        /// What to do with files that already exist at the destination
+        #[arg(long, value_enum)]
+        conflict: Option<Conflict>,
+        #[command(flatten)]
+        copy: CopyArgs,
+    },
+    /// Remove a file or a directory tree
+    Rm {
+        path: PathBuf,
+        /// Only count what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Copy the files of a tree that are new or changed. A file is copied again when its size
+    /// changed or the source is newer than the copy
+    Sync {
+        from: PathBuf,
+        to: PathBuf,
+        /// Also remove everything from the destination that is not in the source
+        #[arg(long)]
+        delete: bool,
+        #[command(flatten)]
+        copy: CopyArgs,
+    },
+    /// Show how much space a tree takes up
+    Du {
+        path: PathBuf,
+        /// Show the sum of the file lengths instead of the space on disk
+        #[arg(long)]
+        apparent_size: bool,
+        /// Skip directories on other filesystems
+        #[arg(short = 'x', long)]
+        one_file_system: bool,
+        /// Only count entries up to this many levels below the path
+        #[arg(long)]
+        max_depth: Option<usize>,
+        /// How many of the largest files to list
+        #[arg(long, default_value_t = 10)]
+        largest: usize,
+    },
+    /// Compare two trees by file type, length and contents. Symlinks are followed
+    Diff { left: PathBuf, right: PathBuf },
+}
+
+#[derive(Args)]
+struct CopyArgs {
+    /// Only count what would be copied
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Check the destination and the source before anything is written
+    #[arg(long)]
+    preflight: bool,
+
+    /// Sync the files and directories to disk before finishing
+    #[arg(long)]
+    sync: bool,
+
+    /// What to do with fifos, sockets and device files
+    #[arg(long, value_enum, default_value_t = Special::Error)]
+    special_files: Special,
+
+    /// The amount of threads, by default one per cpu
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+impl CopyArgs {
+    fn options(&self) -> CopyOptions {
+        let mut options = CopyOptions::new();
+        options
+            .preflight(self.preflight)
+            .special_files(match self.special_files {
+                Special::Error => SpecialFiles::Error,
+                Special::Skip => SpecialFiles::Skip,
+                Special::Recreate => SpecialFiles::Recreate,
+            });
+        if self.sync {
+            options.durability(Durability::FilesAndDirs);
+        }
+        if let Some(threads) = self.threads {
+            options.parallelism(Parallelism::Threads(threads));
+        }
+        options
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Special {
+    Error,
+    Skip,
+    Recreate,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Conflict {
+    Overwrite,
+    Skip,
+    KeepNewer,
+    Rename,
+    Abort,
+}
+
+impl From<Conflict> for ConflictAction {
+    fn from(conflict: Conflict) -> ConflictAction {
+        match conflict {
+            Conflict::Overwrite => ConflictAction::Overwrite,
+            Conflict::Skip => ConflictAction::Skip,
+            Conflict::KeepNewer => ConflictAction::KeepNewer,
+            Conflict::Rename => ConflictAction::Rename,
+            Conflict::Abort => ConflictAction::Abort,
+        }
+    }
+}
+
+/// What `rm` removed or would remove
+#[derive(Serialize)]
+struct Removed {
+    files: u64,
+    dirs: u64,
+    symlinks: u64,
+    bytes: u64,
+}
+
+/// Why a command failed
+enum Failure {
+    /// An operation of the library failed
+    Fs(Error),
+    /// The result could not be printed as JSON, which is a bug
+    Json(serde_json::Error),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Failure {
+        Failure::Fs(e)
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(&cli) {
+        Ok(code) => code,
+        Err(Failure::Fs(e)) => {
+            eprintln!("more-fs: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+        Err(Failure::Json(e)) => {
+            eprintln!("more-fs: failed to serialize the result: {}", e);
+            ExitCode::from(70)
+        }
+    }
+}
+
+fn run(cli: &Cli) -> std::result::Result<ExitCode, Failure> {
+    let show_progress = !cli.no_progress && std::io::stderr().is_terminal();
+
+    match &cli.command {
+        Command::Cp {
+            from,
+            to,
+            conflict,
+            copy,
+        } => {
+            let report = if copy.dry_run {
+                plan(from)?
+            } else {
+                transfer(from, to, *conflict, copy, show_progress, false)?
+            };
+            print(cli.json, &report, |report| {
+                print_report(if copy.dry_run { "would copy" } else { "copied" }, report)
+            })?;
+        }
+        Command::Mv {
+            from,
+            to,
+            conflict,
+            copy,
+        } => {
+            let report = if copy.dry_run {
+                plan(from)?
+            } else {
+                transfer(from, to, *conflict, copy, show_progress, true)?
+            };
+            print(cli.json, &report, |report| {
+                print_report(if copy.dry_run { "would move" } else { "moved" }, report)
+            })?;
+        }
+        Command::Rm { path, dry_run } => {
+            let usage = more_fs::disk_usage_with(path, DuOptions::new().largest(0))?;
+            if !dry_run {
+                let spinner = progress::spinner(show_progress, "removing");
+                if more_fs::symlink_metadata(path)?.is_dir() {
+                    more_fs::remove_dir_all_par(path)?;
+                } else {
+                    more_fs::remove_file(path)?;
+                }
+                spinner.finish_and_clear();
+            }
+            let removed = Removed {
+                files: usage.files,
+                dirs: usage.dirs,
+                symlinks: usage.symlinks,
+                bytes: usage.apparent_size,
+            };
+            print(cli.json, &removed, |removed| {
+                println!(
+                    "{} {} files, {} directories, {} symlinks, {}",
+                    if *dry_run { "would remove" } else { "removed" },
+                    removed.files,
+                    removed.dirs,
+                    removed.symlinks,
+                    HumanBytes(removed.bytes)
+                )
+            })?;
+        }
+        Command::Sync {
+            from,
+            to,
+            delete,
+            copy,
+        } => {
+            let synced = if copy.dry_run {
+                sync::plan(from, to, *delete)?
+            } else {
+                let mut options = copy.options();
+                let bar = progress::bytes(show_progress, from)?;
+                progress::track(&bar, &mut options);
+                let synced = sync::sync(from, to, *delete, &mut options)?;
+                bar.finish_and_clear();
+                synced
+            };
+            print(cli.json, &synced, |synced| {
+                print_report(
+                    if copy.dry_run { "would copy" } else { "copied" },
+                    &synced.report,
+                );
+                println!(
+                    "{} unchanged, {} {}",
+                    synced.unchanged,
+                    synced.removed.len(),
+                    if copy.dry_run { "to remove" } else { "removed" }
+                );
+            })?;
+        }
+        Command::Du {
+            path,
+            apparent_size,
+            one_file_system,
+            max_depth,
+            largest,
+        } => {
+            let mut options = DuOptions::new();
+            options.one_file_system(*one_file_system).largest(*largest);
+            if let Some(depth) = max_depth {
+                options.max_depth(*depth);
+            }
+            let spinner = progress::spinner(show_progress, "counting");
+            let usage = more_fs::disk_usage_with(path, &options)?;
+            spinner.finish_and_clear();
+            print(cli.json, &usage, |usage| {
+                print_usage(path, usage, *apparent_size)
+            })?;
+        }
+        Command::Diff { left, right } => {
+            let diff = diff::diff(left, right)?;
+            let same = diff.is_empty();
+            print(cli.json, &diff, |diff| diff.print())?;
+            if !same {
+                return Ok(ExitCode::from(1));
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Copies or moves a file or a directory tree with the parallel functions
+fn transfer(
+    from: &Path,
+    to: &Path,
+    conflict: Option<Conflict>,
+    copy: &CopyArgs,
+    show_progress: bool,
+    remove: bool,
+) -> Result<Report> {
+    let metadata = more_fs::symlink_metadata(from)?;
+    if !metadata.is_dir() {
+        check_single_file(from, &metadata, conflict, copy)?;
+    }
+
+    let mut options = copy.options();
+    if let Some(conflict) = conflict {
+        let action = ConflictAction::from(conflict);
+        options.on_conflict(move |_, _, _| action);
+    }
+    let bar = progress::bytes(show_progress, from)?;
+    progress::track(&bar, &mut options);
+
+    let report = if metadata.is_dir() {
+        if remove {
+            more_fs::move_dir_all_par_with(from, to, &options)?
+        } else {
+            more_fs::copy_dir_all_par_with(from, to, &options)?
+        }
+    } else {
+        let bytes = if remove {
+            more_fs::move_file_with(from, to, &options)?
+        } else {
+            more_fs::copy_with(from, to, &options)?
+        };
+        bar.inc(bytes);
+        Report {
+            files: 1,
+            bytes,
+            ..Report::default()
+        }
+    };
+    bar.finish_and_clear();
+    Ok(report)
+}
+
+/// Conflicts and special files are only handled by the recursive functions, so `--conflict` and
+/// `--special-files` are rejected for a single file instead of being ignored. A single special
+/// file fails like it does inside of a tree, copying it would block on a fifo
+fn check_single_file(
+    from: &Path,
+    metadata: &std::fs::Metadata,
+    conflict: Option<Conflict>,
+    copy: &CopyArgs,
+) -> Result<()> {
+    let flag = match (conflict, copy.special_files) {
+        (Some(_), _) => Some("--conflict"),
+        (None, Special::Skip | Special::Recreate) => Some("--special-files"),
+        (None, Special::Error) => None,
+    };
+    if let Some(flag) = flag {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("{} only applies to directories", flag),
+            )
+            .exit();
+    }
+
+    let file_type = FileType::from(metadata.file_type());
+    if file_type.is_special() {
+        return Err(Error::SpecialFile {
+            path: from.to_path_buf(),
+            file_type,
+        });
+    }
+    Ok(())
+}
+
+/// What a copy or move of `from` would do, as far as it can be known without writing anything.
+/// Symlinks are counted with the length of their targets because their targets are copied
+fn plan(from: &Path) -> Result<Report> {
+    let mut report = Report::default();
+
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let file_type = FileType::from(entry.file_type());
+        if file_type.is_dir() {
+            report.dirs += 1;
+            continue;
+        }
+        if file_type.is_special() {
+            continue;
+        }
+
+        report.bytes += more_fs::metadata(entry.path())?.len();
+        if file_type.is_symlink() {
+            report.symlinks += 1;
+        } else {
+            report.files += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// The path of an entry that was found by walking `root`, relative to `root`
+fn relative<'a>(root: &Path, path: &'a Path) -> Result<&'a Path> {
+    path.strip_prefix(root).map_err(|e| Error::StripPrefix {
+        target: path.to_path_buf(),
+        strip: root.to_path_buf(),
+        source: e,
+    })
+}
+
+fn print<T: Serialize>(
+    json: bool,
+    value: &T,
+    summary: impl FnOnce(&T),
+) -> std::result::Result<(), Failure> {
+    if !json {
+        summary(value);
+        return Ok(());
+    }
+
+    let json = serde_json::to_string_pretty(value).map_err(Failure::Json)?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn print_report(verb: &str, report: &Report) {
+    println!(
+        "{} {} files, {} directories, {} symlinks, {}",
+        verb,
+        report.files,
+        report.dirs,
+        report.symlinks,
+        HumanBytes(report.bytes)
+    );
+    if !report.skipped.is_empty() {
+        println!("skipped {} entries", report.skipped.len());
+    }
+    let total = report.timings.total();
+    if total > Duration::ZERO {
+        println!("took {:.2?}", total);
+    }
+}
+
+fn print_usage(path: &Path, usage: &DiskUsage, apparent_size: bool) {
+    let size = |apparent: u64, allocated: u64| {
+        let size = if apparent_size { apparent } else { allocated };
+        HumanBytes(size).to_string()
+    };
+    for child in &usage.children {
+        let size = size(child.apparent_size, child.allocated_size);
+        println!("{:>12}  {}", size, child.path.display());
+    }
+    let size = size(usage.apparent_size, usage.allocated_size);
+    println!("{:>12}  {}", size, path.display());
+    println!(
+        "{} files, {} directories, {} symlinks, {} others",
+        usage.files, usage.dirs, usage.symlinks, usage.others
+    );
+    if !usage.largest.is_empty() {
+        println!("largest files:");
+        for (path, len) in &usage.largest {
+            println!("{:>12}  {}", HumanBytes(*len).to_string(), path.display());
+        }
+    }
+}
+
+/// Maps an error to an exit code. The codes follow `sysexits.h`, they are listed in the help
+fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::Recover { operation, .. } => exit_code(operation),
+        Error::IoExt { .. } | Error::IoExtMulti { .. } | Error::WalkDir { .. } => {
+            match error.io_error_kind() {
+                std::io::ErrorKind::NotFound => 66,
+                std::io::ErrorKind::PermissionDenied => 77,
+                _ => 74,
+            }
+        }
+        Error::NotDirectory { .. }
+        | Error::DestinationInsideSource { .. }
+        | Error::SameFile { .. } => 64,
+        Error::PathEscape { .. } | Error::SpecialFile { .. } => 65,
+        Error::StripPrefix { .. } => 70,
+        Error::Preflight { .. } | Error::Conflict { .. } => 73,
+        Error::Locked { .. } => 75,
+    }
+}
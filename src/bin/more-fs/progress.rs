@@ -0,0 +1,41 @@
+use std::path::Path;
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use more_fs::{CopyOptions, DuOptions, Result};
+
+/// A bar that counts the copied bytes of `from`. The size of the tree is only measured if the
+/// bar is shown
+pub fn bytes(enabled: bool, from: &Path) -> Result<ProgressBar> {
+    if !enabled {
+        return Ok(ProgressBar::hidden());
+    }
+
+    let total = more_fs::disk_usage_with(from, DuOptions::new().largest(0))?.apparent_size;
+    let bar = ProgressBar::new(total).with_style(
+        ProgressStyle::with_template(
+            "{elapsed_precise} [{wide_bar}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+        )
+        .expect("the template is valid")
+        .progress_chars("=> "),
+    );
+    bar.enable_steady_tick(Duration::from_millis(100));
+    Ok(bar)
+}
+
+/// A spinner for the operations that can't tell how far they are
+pub fn spinner(enabled: bool, message: &'static str) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+
+    let spinner = ProgressBar::new_spinner().with_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+/// Moves the bar forward with every file that is copied
+pub fn track(bar: &ProgressBar, options: &mut CopyOptions) {
+    let bar = bar.clone();
+    options.on_progress(move |_, bytes| bar.inc(bytes));
+}
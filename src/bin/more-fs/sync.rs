@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use more_fs::vfs::{FileType, Metadata};
+use more_fs::{ConflictAction, CopyOptions, Report, Result, SkipReason};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::relative;
+
+/// What `sync` copied and removed, or would copy and remove
+#[derive(Serialize)]
+pub struct Synced {
+    #[serde(flatten)]
+    pub report: Report,
+    /// The amount of files that were already up to date
+    pub unchanged: u64,
+    /// The entries of the destination that are not in the source, only with `--delete`
+    pub removed: Vec<PathBuf>,
+}
+
+pub fn sync(from: &Path, to: &Path, delete: bool, options: &mut CopyOptions) -> Result<Synced> {
+    options.on_conflict(|_, source, existing| {
+        if needs_copy(source, existing) {
+            ConflictAction::Overwrite
+        } else {
+            ConflictAction::Skip
+        }
+    });
+    let report = more_fs::copy_dir_all_par_with(from, to, options)?;
+    let unchanged = report
+        .skipped
+        .iter()
+        .filter(|skipped| skipped.reason == SkipReason::Conflict)
+        .count() as u64;
+
+    let removed = if delete {
+        extraneous(from, to)?
+    } else {
+        Vec::new()
+    };
+    for path in &removed {
+        if more_fs::symlink_metadata(path)?.is_dir() {
+            more_fs::remove_dir_all_par(path)?;
+        } else {
+            more_fs::remove_file(path)?;
+        }
+    }
+
+    Ok(Synced {
+        report,
+        unchanged,
+        removed,
+    })
+}
+
+/// Finds out what [`sync`] would do without writing anything
+pub fn plan(from: &Path, to: &Path, delete: bool) -> Result<Synced> {
+    let mut report = Report::default();
+    let mut unchanged = 0;
+
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let target = to.join(relative(from, entry.path())?);
+
+        if entry.file_type().is_dir() {
+            if !target.is_dir() {
+                report.dirs += 1;
+            }
+            continue;
+        }
+        let source = Metadata::from(more_fs::metadata(entry.path())?);
+        if source.file_type().is_special() {
+            continue;
+        }
+        match more_fs::metadata(&target).map(Metadata::from) {
+            Ok(existing) if !needs_copy(&source, &existing) => unchanged += 1,
+            _ => {
+                if FileType::from(entry.file_type()).is_symlink() {
+                    report.symlinks += 1;
+                } else {
+                    report.files += 1;
+                }
+                report.bytes += source.len();
+            }
+        }
+    }
+
+    let removed = if delete && to.exists() {
+        extraneous(from, to)?
+    } else {
+        Vec::new()
+    };
+    Ok(Synced {
+        report,
+        unchanged,
+        removed,
+    })
+}
+
+/// A file is copied again if its length changed or the source was modified after the copy was
+/// made. The copies don't keep the times of their sources, so comparing the times for equality
+/// would copy everything every time
+fn needs_copy(source: &Metadata, existing: &Metadata) -> bool {
+    source.len() != existing.len() || source.modified() > existing.modified()
+}
+
+/// The entries of `to` that are not in `from`. Only the topmost entry of a missing directory is
+/// returned
+fn extraneous(from: &Path, to: &Path) -> Result<Vec<PathBuf>> {
+    let mut extraneous = Vec::new();
+    let mut walk = WalkDir::new(to).min_depth(1).into_iter();
+
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        let source = from.join(relative(to, entry.path())?);
+
+        if source.symlink_metadata().is_err() {
+            if entry.file_type().is_dir() {
+                walk.skip_current_dir();
+            }
+            extraneous.push(entry.into_path());
+        }
+    }
+    Ok(extraneous)
+}
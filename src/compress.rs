@@ -0,0 +1,458 @@
+/*!
+Copying directory trees while compressing or decompressing every file on the way.
+
+[`copy_dir_all_compressed`] works like [`copy_dir_all`](crate::copy_dir_all) but every regular
+file is compressed into `name.zst` or `name.gz` in the destination. The inverse,
+[`copy_dir_all_decompressed`], restores the original names. Zstandard is enabled with the `zstd`
+feature flag and gzip with the `gzip` feature flag. Both have `_par` versions with the `rayon`
+feature and `_with` versions that take the same [`CopyOptions`] as the other copy functions.
+
+```no_run
+use more_fs::compress::{copy_dir_all_compressed, Codec};
+
+let report = copy_dir_all_compressed("logs", "archived_logs", Codec::Zstd(3)).unwrap();
+println!("{} bytes became {}", report.original_bytes, report.compressed_bytes);
+```
+*/
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "rayon")]
+use std::sync::Mutex;
+
+use crate::error::Operation;
+#[cfg(feature = "rayon")]
+use crate::parallel;
+use crate::utils::change_dir;
+use crate::vfs::{FileSystem, FileType, RealFs};
+use crate::{backup, lock, preflight, trace};
+use crate::{check_copy_dir_all, CopyOptions, Error, LockMode, Report, Result};
+use crate::{Conflict, Skipped, SpecialFile};
+
+/// A compression format and its level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Zstandard with a level from 1 to 22, files get the `.zst` extension
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    /// Gzip with a level from 0 to 9, files get the `.gz` extension
+    #[cfg(feature = "gzip")]
+    Gzip(u32),
+}
+
+impl Codec {
+    /// The extension that is appended to compressed files, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(_) => "zst",
+            #[cfg(feature = "gzip")]
+            Codec::Gzip(_) => "gz",
+        }
+    }
+
+    /// Finds the codec of a compressed file by its extension, using the default level
+    fn from_path(path: &Path) -> Option<Codec> {
+        match path.extension()?.to_str()? {
+            #[cfg(feature = "zstd")]
+            "zst" => Some(Codec::Zstd(zstd::DEFAULT_COMPRESSION_LEVEL)),
+            #[cfg(feature = "gzip")]
+            "gz" => Some(Codec::Gzip(flate2::Compression::default().level())),
+            _ => None,
+        }
+    }
+
+    fn encode(self, reader: &mut impl Read, writer: impl Write) -> io::Result<u64> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(level) => {
+                let mut encoder = zstd::Encoder::new(writer, level)?;
+                let copied = io::copy(reader, &mut encoder)?;
+                encoder.finish()?.flush()?;
+                Ok(copied)
+            }
+            #[cfg(feature = "gzip")]
+            Codec::Gzip(level) => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(writer, flate2::Compression::new(level));
+                let copied = io::copy(reader, &mut encoder)?;
+                encoder.finish()?.flush()?;
+                Ok(copied)
+            }
+        }
+    }
+
+    fn decode(self, reader: impl Read, writer: &mut impl Write) -> io::Result<u64> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(_) => io::copy(&mut zstd::Decoder::new(reader)?, writer),
+            #[cfg(feature = "gzip")]
+            Codec::Gzip(_) => io::copy(&mut flate2::read::MultiGzDecoder::new(reader), writer),
+        }
+    }
+}
+
+/// What happened during a compressing or decompressing copy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompressReport {
+    /// The amount of files that were compressed or decompressed
+    pub files: u64,
+    /// The size of the files before compressing or after decompressing
+    pub original_bytes: u64,
+    /// The size of the compressed files
+    pub compressed_bytes: u64,
+    /// Every fifo, socket and device file that was found, checkout
+    /// [`CopyOptions::special_files`]
+    pub special_files: Vec<SpecialFile>,
+    /// Every file whose destination already existed, checkout [`CopyOptions::on_conflict`]
+    pub conflicts: Vec<Conflict>,
+    /// Every entry of the source that was left out of the copy
+    pub skipped: Vec<Skipped>,
+}
+
+impl CompressReport {
+    /// The compressed size divided by the original size. Returns 1 if nothing was copied
+    pub fn ratio(&self) -> f64 {
+        if self.original_bytes == 0 {
+            1.0
+        } else {
+            self.compressed_bytes as f64 / self.original_bytes as f64
+        }
+    }
+
+    fn add(&mut self, other: CompressReport) {
+        self.files += other.files;
+        self.original_bytes += other.original_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+        self.special_files.extend(other.special_files);
+        self.conflicts.extend(other.conflicts);
+        self.skipped.extend(other.skipped);
+    }
+
+    /// The lists of the report of an entry that was copied as it is, only compressed files are
+    /// counted
+    fn copied(report: Report) -> CompressReport {
+        CompressReport {
+            special_files: report.special_files,
+            conflicts: report.conflicts,
+            skipped: report.skipped,
+            ..CompressReport::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Compress(Codec),
+    Decompress,
+}
+
+/// Recursively copies `from` to `to`, compressing every file into `name.<extension>`.
+/// Directories, symlinks and special files are copied like with
+/// [`copy_dir_all`](crate::copy_dir_all)
+pub fn copy_dir_all_compressed(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    codec: Codec,
+) -> Result<CompressReport> {
+    copy_dir_all_compressed_with(from, to, codec, &CopyOptions::new())
+}
+
+/// The same as [`copy_dir_all_compressed`] but with options. Conflicts and backups are decided
+/// for the name with the extension
+pub fn copy_dir_all_compressed_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    codec: Codec,
+    options: &CopyOptions,
+) -> Result<CompressReport> {
+    transform_dir_all(
+        from.as_ref(),
+        to.as_ref(),
+        Direction::Compress(codec),
+        options,
+    )
+}
+
+/// Recursively copies `from` to `to`, decompressing every file with a known extension and
+/// removing the extension. Files without a known extension are copied as is.
+pub fn copy_dir_all_decompressed(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+) -> Result<CompressReport> {
+    copy_dir_all_decompressed_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`copy_dir_all_decompressed`] but with options
+pub fn copy_dir_all_decompressed_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<CompressReport> {
+    transform_dir_all(from.as_ref(), to.as_ref(), Direction::Decompress, options)
+}
+
+/// The same as [`copy_dir_all_compressed`] but in parallel
+#[cfg(feature = "rayon")]
+pub fn copy_dir_all_compressed_par(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    codec: Codec,
+) -> Result<CompressReport> {
+    copy_dir_all_compressed_par_with(from, to, codec, &CopyOptions::new())
+}
+
+/// The same as [`copy_dir_all_compressed_with`] but in parallel
+#[cfg(feature = "rayon")]
+pub fn copy_dir_all_compressed_par_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    codec: Codec,
+    options: &CopyOptions,
+) -> Result<CompressReport> {
+    transform_dir_all_par(
+        from.as_ref(),
+        to.as_ref(),
+        Direction::Compress(codec),
+        options,
+    )
+}
+
+/// The same as [`copy_dir_all_decompressed`] but in parallel
+#[cfg(feature = "rayon")]
+pub fn copy_dir_all_decompressed_par(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+) -> Result<CompressReport> {
+    copy_dir_all_decompressed_par_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`copy_dir_all_decompressed_with`] but in parallel
+#[cfg(feature = "rayon")]
+pub fn copy_dir_all_decompressed_par_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<CompressReport> {
+    transform_dir_all_par(from.as_ref(), to.as_ref(), Direction::Decompress, options)
+}
+
+fn transform_dir_all(
+    from: &Path,
+    to: &Path,
+    direction: Direction,
+    options: &CopyOptions,
+) -> Result<CompressReport> {
+    let span = trace::operation!(
+        "copy_dir_all_compressed",
+        from = %from.display(),
+        to = %to.display(),
+        ?direction
+    );
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(from, LockMode::Shared)], Some(to))?;
+        check_copy_dir_all(&RealFs, from, to)?;
+        if options.preflight {
+            preflight::preflight(from, to)?;
+        }
+
+        let mut report = CompressReport::default();
+        let mut dirs = Vec::new();
+        for entry in RealFs.walk(from) {
+            let entry = entry?;
+            let new_path = change_dir(from, to, entry.path())?;
+
+            report.add(transform_entry(
+                entry.file_type(),
+                entry.path(),
+                &new_path,
+                direction,
+                options,
+            )?);
+            if entry.file_type().is_dir() {
+                dirs.push((entry.path().to_path_buf(), new_path));
+            }
+        }
+        crate::finish_dirs(&RealFs, to, &dirs, options)?;
+
+        Ok(report)
+    })
+}
+
+#[cfg(feature = "rayon")]
+fn transform_dir_all_par(
+    from: &Path,
+    to: &Path,
+    direction: Direction,
+    options: &CopyOptions,
+) -> Result<CompressReport> {
+    let span = trace::operation!(
+        "copy_dir_all_compressed_par",
+        from = %from.display(),
+        to = %to.display(),
+        ?direction
+    );
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(from, LockMode::Shared)], Some(to))?;
+        check_copy_dir_all(&RealFs, from, to)?;
+        if options.preflight {
+            preflight::preflight(from, to)?;
+        }
+
+        let dirs = Mutex::new(Vec::new());
+        let report = parallel::install(&options.parallelism, from, to, || {
+            crate::walk::walk_par(
+                from,
+                |entry, report: &mut CompressReport| {
+                    let new_path = change_dir(from, to, entry.path())?;
+
+                    // the walker visits a directory before its contents
+                    report.add(transform_entry(
+                        entry.file_type(),
+                        entry.path(),
+                        &new_path,
+                        direction,
+                        options,
+                    )?);
+                    if entry.file_type().is_dir() {
+                        let dir = (entry.path().to_path_buf(), new_path);
+                        dirs.lock().unwrap().push((entry.depth(), dir));
+                    }
+
+                    Ok(true)
+                },
+                |mut a, b| {
+                    a.add(b);
+                    a
+                },
+            )
+        })?;
+
+        // finished in reverse, so the deepest directories come first
+        let mut dirs = dirs.into_inner().unwrap();
+        dirs.sort_by_key(|(depth, _)| *depth);
+        let dirs: Vec<_> = dirs.into_iter().map(|(_, dir)| dir).collect();
+        crate::finish_dirs(&RealFs, to, &dirs, options)?;
+
+        Ok(report)
+    })
+}
+
+/// Compresses or decompresses a regular file, everything else is copied like
+/// [`copy_dir_all`](crate::copy_dir_all) does
+fn transform_entry(
+    file_type: FileType,
+    from: &Path,
+    to: &Path,
+    direction: Direction,
+    options: &CopyOptions,
+) -> Result<CompressReport> {
+    let mut copied = Report::default();
+    if !file_type.is_file() {
+        crate::copy_or_create(&RealFs, file_type, from, to, options, &mut copied)?;
+        return Ok(CompressReport::copied(copied));
+    }
+
+    let (to, codec) = match direction {
+        Direction::Compress(codec) => (
+            with_extension(to.to_path_buf(), codec.extension()),
+            Some(codec),
+        ),
+        Direction::Decompress => match Codec::from_path(to) {
+            Some(codec) => (to.with_extension(""), Some(codec)),
+            None => (to.to_path_buf(), None),
+        },
+    };
+    let codec = match codec {
+        Some(codec) => codec,
+        None => {
+            crate::copy_or_create(&RealFs, file_type, from, &to, options, &mut copied)?;
+            return Ok(CompressReport::copied(copied));
+        }
+    };
+    let to = match crate::resolve_conflict(&RealFs, from, &to, options, &mut copied)? {
+        Some(to) => to,
+        None => return Ok(CompressReport::copied(copied)),
+    };
+    backup::backup(&RealFs, from, &to, options)?;
+
+    let mut report = transform_file(from, &to, codec, direction)?;
+    report.add(CompressReport::copied(copied));
+    trace::entry!(
+        from = %from.display(),
+        to = %to.display(),
+        bytes = report.compressed_bytes,
+        "compress"
+    );
+    if options.durability.sync_files() {
+        RealFs.sync_file(&to)?;
+    }
+    if let Some(hook) = &options.on_progress {
+        hook(from, report.original_bytes);
+    }
+    Ok(report)
+}
+
+fn transform_file(
+    from: &Path,
+    to: &Path,
+    codec: Codec,
+    direction: Direction,
+) -> Result<CompressReport> {
+    let io_ext_multi = |source| Error::IoExtMulti {
+        source,
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        operation: match direction {
+            Direction::Compress(_) => Operation::Compress,
+            Direction::Decompress => Operation::Decompress,
+        },
+    };
+
+    let source = File::open(from).map_err(io_ext_multi)?;
+    let permissions = source.metadata().map_err(io_ext_multi)?.permissions();
+    let destination = File::create(to).map_err(io_ext_multi)?;
+
+    let mut reader = BufReader::new(source);
+    let mut writer = BufWriter::new(destination);
+    let (original_bytes, compressed_bytes) = match direction {
+        Direction::Compress(_) => {
+            let original = codec
+                .encode(&mut reader, &mut writer)
+                .map_err(io_ext_multi)?;
+            (original, written_len(writer).map_err(io_ext_multi)?)
+        }
+        Direction::Decompress => {
+            let compressed = reader.get_ref().metadata().map_err(io_ext_multi)?.len();
+            let original = codec
+                .decode(&mut reader, &mut writer)
+                .map_err(io_ext_multi)?;
+            writer.flush().map_err(io_ext_multi)?;
+            (original, compressed)
+        }
+    };
+    fs::set_permissions(to, permissions).map_err(io_ext_multi)?;
+
+    Ok(CompressReport {
+        files: 1,
+        original_bytes,
+        compressed_bytes,
+        ..CompressReport::default()
+    })
+}
+
+fn written_len(writer: BufWriter<File>) -> io::Result<u64> {
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(file.metadata()?.len())
+}
+
+/// Appends an extension instead of replacing the existing one
+fn with_extension(path: PathBuf, extension: &str) -> PathBuf {
+    let mut name = OsString::from(path);
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
@@ -0,0 +1,410 @@
+/*!
+Finding identical files and replacing them with links to a single copy.
+
+[`find_duplicates`] walks any amount of directories and groups the regular files that have the same
+contents. Candidates are narrowed down first by their size, then by a hash of their first block
+and only then by a hash of their whole contents, so most files are never read completely. Every
+step runs in parallel with [`rayon`].
+
+The groups can be passed to [`dedupe`] to make all files of a group share the same data.
+
+```no_run
+use more_fs::dedupe::{dedupe, find_duplicates, DedupeMethod};
+
+let groups = find_duplicates(&["target/debug", "target/release"]).unwrap();
+let freed = dedupe(&groups, DedupeMethod::Hardlink).unwrap();
+println!("freed {} bytes", freed);
+```
+*/
+
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Operation, Result};
+use crate::utils::same_file;
+use crate::vfs::FileType;
+use crate::{trace, walk};
+use rayon::prelude::*;
+
+/// How many bytes at the start of a file are hashed before the whole file is hashed
+const PARTIAL_LEN: u64 = 4096;
+
+/// Files that have the same contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    size: u64,
+    paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// The size of every file in the group
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The paths of the files, sorted. There are always at least two
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// The amount of bytes that would be freed if all files of the group shared their data
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// How [`dedupe`] makes duplicates share their data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupeMethod {
+    /// Replace every duplicate with a hard link to the first file of the group. Writing to one
+    /// of the files afterwards changes all of them
+    Hardlink,
+    /// Replace every duplicate with a copy on write clone of the first file of the group. The
+    /// files stay independent. Only supported on linux filesystems that can clone files, like
+    /// btrfs and xfs
+    Reflink,
+    /// Ask the filesystem to share the extents of the files with `FIDEDUPERANGE`. The kernel
+    /// compares the contents itself and the files are never replaced. Only supported on linux
+    /// filesystems that can clone files
+    DedupeRange,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+}
+
+/// Finds the regular files below `roots` that have the same contents. Symlinks are not followed,
+/// empty files are ignored and on unix files that are already hard links of each other are only
+/// reported once. The groups are sorted by the amount of bytes they waste, largest first.
+pub fn find_duplicates<P: AsRef<Path>>(
+    roots: impl IntoIterator<Item = P>,
+) -> Result<Vec<DuplicateGroup>> {
+    let span = trace::operation!("find_duplicates");
+    trace::traced(span, || {
+        let mut by_size: HashMap<u64, Vec<Candidate>> = HashMap::new();
+        for candidate in candidates(roots)? {
+            by_size.entry(candidate.size).or_default().push(candidate);
+        }
+        let groups = by_size
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .collect();
+
+        let groups = regroup(groups, |candidate| {
+            hash_file(&candidate.path, Some(PARTIAL_LEN))
+        })?;
+        let groups = regroup(groups, |candidate| {
+            // the partial hash already covered small files
+            if candidate.size <= PARTIAL_LEN {
+                Ok(None)
+            } else {
+                hash_file(&candidate.path, None).map(Some)
+            }
+        })?;
+
+        let mut groups: Vec<_> = groups
+            .into_iter()
+            .map(|group| {
+                let size = group[0].size;
+                let mut paths: Vec<_> = group.into_iter().map(|candidate| candidate.path).collect();
+                paths.sort();
+                DuplicateGroup { size, paths }
+            })
+            .collect();
+        groups.sort_unstable_by(|a, b| b.wasted().cmp(&a.wasted()).then(a.paths.cmp(&b.paths)));
+
+        Ok(groups)
+    })
+}
+
+fn candidates<P: AsRef<Path>>(roots: impl IntoIterator<Item = P>) -> Result<Vec<Candidate>> {
+    let mut found = Vec::new();
+    for root in roots {
+        found.extend(walk::walk_par(
+            root.as_ref(),
+            |entry, found: &mut Vec<(Candidate, fs::Metadata)>| {
+                if entry.file_type() == FileType::File {
+                    let metadata =
+                        fs::symlink_metadata(entry.path()).map_err(|e| Error::IoExt {
+                            source: e,
+                            path: entry.path().to_path_buf(),
+                            operation: Operation::Metadata,
+                        })?;
+                    if metadata.len() > 0 {
+                        let candidate = Candidate {
+                            path: entry.path().to_path_buf(),
+                            size: metadata.len(),
+                        };
+                        found.push((candidate, metadata));
+                    }
+                }
+                Ok(true)
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+        )?);
+    }
+    // the walk finds the files in any order, sorting keeps the same hard link every time
+    found.sort_unstable_by(|a, b| a.0.path.cmp(&b.0.path));
+
+    #[cfg(unix)]
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for (candidate, _metadata) in found {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            if !seen.insert((_metadata.dev(), _metadata.ino())) {
+                continue;
+            }
+        }
+
+        candidates.push(candidate);
+    }
+
+    Ok(candidates)
+}
+
+/// Splits every group by `key`, dropping the files that are left without a duplicate
+fn regroup<K, F>(groups: Vec<Vec<Candidate>>, key: F) -> Result<Vec<Vec<Candidate>>>
+where
+    K: Hash + Eq + Send,
+    F: Fn(&Candidate) -> Result<K> + Sync,
+{
+    let groups = groups
+        .into_par_iter()
+        .map(|group| {
+            let keyed = group
+                .into_par_iter()
+                .map(|candidate| Ok((key(&candidate)?, candidate)))
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut by_key: HashMap<K, Vec<Candidate>> = HashMap::new();
+            for (key, candidate) in keyed {
+                by_key.entry(key).or_default().push(candidate);
+            }
+            Ok(by_key
+                .into_values()
+                .filter(|group| group.len() > 1)
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(groups.into_iter().flatten().collect())
+}
+
+/// Hashes the first `limit` bytes of a file or the whole file
+fn hash_file(path: &Path, limit: Option<u64>) -> Result<blake3::Hash> {
+    let hash = || -> io::Result<blake3::Hash> {
+        let file = File::open(path)?;
+        let mut hasher = blake3::Hasher::new();
+        match limit {
+            Some(limit) => io::copy(&mut file.take(limit), &mut hasher)?,
+            None => io::copy(&mut BufReader::new(file), &mut hasher)?,
+        };
+        Ok(hasher.finalize())
+    };
+
+    hash().map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::Read,
+    })
+}
+
+/// Makes the files of every group share their data, keeping the first file of a group. Before a
+/// file is replaced it is compared byte by byte with the first file again, files that changed
+/// since they were found are left alone. Returns the amount of bytes that were freed.
+pub fn dedupe(groups: &[DuplicateGroup], method: DedupeMethod) -> Result<u64> {
+    let span = trace::operation!("dedupe", groups = groups.len(), ?method);
+    trace::traced(span, || {
+        let scope = trace::Scope::current();
+        groups
+            .par_iter()
+            .map(|group| {
+                let (original, duplicates) = group.paths.split_first().unwrap();
+                duplicates
+                    .par_iter()
+                    .map(|duplicate| {
+                        scope.in_scope(|| {
+                            trace::entry!(
+                                from = %original.display(),
+                                to = %duplicate.display(),
+                                "dedupe"
+                            );
+                            dedupe_file(original, duplicate, group.size, method).map_err(|e| {
+                                Error::IoExtMulti {
+                                    source: e,
+                                    from: original.clone(),
+                                    to: duplicate.clone(),
+                                    operation: Operation::Dedupe,
+                                }
+                            })
+                        })
+                    })
+                    .sum::<Result<u64>>()
+            })
+            .sum()
+    })
+}
+
+fn dedupe_file(
+    original: &Path,
+    duplicate: &Path,
+    size: u64,
+    method: DedupeMethod,
+) -> io::Result<u64> {
+    if method == DedupeMethod::DedupeRange {
+        return dedupe_range(original, duplicate, size);
+    }
+    if same_file(original, duplicate) || !same_contents(original, duplicate)? {
+        return Ok(0);
+    }
+
+    // the duplicate is only replaced once the link exists, so it is never lost
+    let temp = temp_path(duplicate);
+    let linked = match method {
+        DedupeMethod::Hardlink => fs::hard_link(original, &temp),
+        _ => reflink(original, &temp)
+            .and_then(|()| fs::set_permissions(&temp, fs::metadata(duplicate)?.permissions())),
+    };
+    if let Err(e) = linked.and_then(|()| fs::rename(&temp, duplicate)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    Ok(size)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".more-fs-dedupe");
+    path.with_file_name(name)
+}
+
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+
+    loop {
+        let (left, right) = (a.fill_buf()?, b.fill_buf()?);
+        if left.is_empty() || right.is_empty() {
+            return Ok(left.is_empty() && right.is_empty());
+        }
+
+        let len = left.len().min(right.len());
+        if left[..len] != right[..len] {
+            return Ok(false);
+        }
+        a.consume(len);
+        b.consume(len);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(original: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let original = File::open(original)?;
+    let to = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(to)?;
+
+    // SAFETY: both file descriptors are open for the duration of the call
+    let result = unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, original.as_raw_fd()) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_original: &Path, _to: &Path) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(target_os = "linux")]
+fn dedupe_range(original: &Path, duplicate: &Path, size: u64) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    /// `struct file_dedupe_range` from `linux/fs.h` with room for a single destination
+    #[repr(C)]
+    struct FileDedupeRange {
+        src_offset: u64,
+        src_length: u64,
+        dest_count: u16,
+        reserved1: u16,
+        reserved2: u32,
+        dest_fd: i64,
+        dest_offset: u64,
+        bytes_deduped: u64,
+        status: i32,
+        reserved: u32,
+    }
+
+    // the size of the request is the size of the struct without the destinations
+    const FIDEDUPERANGE: libc::Ioctl = libc::_IOWR::<[u64; 3]>(0x94, 54);
+    const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+    // some filesystems limit how much is deduplicated in one call
+    const MAX_LEN: u64 = 16 * 1024 * 1024;
+
+    let original = File::open(original)?;
+    let duplicate = fs::OpenOptions::new().write(true).open(duplicate)?;
+
+    let mut offset = 0;
+    while offset < size {
+        let mut range = FileDedupeRange {
+            src_offset: offset,
+            src_length: (size - offset).min(MAX_LEN),
+            dest_count: 1,
+            reserved1: 0,
+            reserved2: 0,
+            dest_fd: i64::from(duplicate.as_raw_fd()),
+            dest_offset: offset,
+            bytes_deduped: 0,
+            status: 0,
+            reserved: 0,
+        };
+
+        // SAFETY: the struct matches the layout the kernel expects for one destination and both
+        // file descriptors are open for the duration of the call
+        let result = unsafe { libc::ioctl(original.as_raw_fd(), FIDEDUPERANGE, &mut range) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if range.status < 0 {
+            return Err(io::Error::from_raw_os_error(-range.status));
+        }
+        if range.status == FILE_DEDUPE_RANGE_DIFFERS || range.bytes_deduped == 0 {
+            break;
+        }
+        offset += range.bytes_deduped;
+    }
+
+    Ok(offset)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn dedupe_range(_original: &Path, _duplicate: &Path, _size: u64) -> io::Result<u64> {
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "sharing file data is only supported on linux",
+    )
+}
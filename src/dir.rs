@@ -0,0 +1,1071 @@
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+
+use libc::{c_int, mode_t};
+
+use crate::error::{Error, Operation, Result};
+use crate::file::File;
+use crate::report::Report;
+use crate::trace;
+use crate::vfs::FileType;
+
+/// How many symlinks are followed while resolving a single path, the same limit as Linux
+const MAX_SYMLINKS: usize = 40;
+
+/// Opens intermediate directories only to look up their entries, which doesn't need read
+/// permission on platforms that have `O_PATH`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SEARCH: c_int = libc::O_PATH;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const SEARCH: c_int = libc::O_RDONLY;
+
+/// Opens anything only to stat it. Without `O_PATH` a fifo would block until it has a writer
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const STAT: c_int = libc::O_PATH;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const STAT: c_int = libc::O_RDONLY | libc::O_NONBLOCK;
+
+/// A handle to a directory that all of its operations are confined to.
+///
+/// Every path given to a `Dir` is relative to the directory it was opened on and is resolved
+/// beneath it, like `openat2` with `RESOLVE_BENEATH`. A `..` that would leave the directory, an
+/// absolute path or a symlink that points outside of it fails with [`Error::PathEscape`]
+/// instead of touching anything outside. Symlinks that stay inside are followed. The directory
+/// is held open, so renaming it or any of its parents doesn't change what the handle refers to.
+///
+/// On Linux the paths are resolved by the kernel with `openat2`, elsewhere or on kernels that
+/// don't have it every component is opened on its own with `O_NOFOLLOW`. `Dir` is available on
+/// the unix targets whose libc exposes `errno`, which are Linux, Android, the BSDs, Apple
+/// platforms, Solaris, illumos, Haiku, AIX, Hurd, Redox and Cygwin.
+///
+/// ```no_run
+/// use more_fs::Dir;
+///
+/// let uploads = Dir::open("/srv/uploads").unwrap();
+/// let backups = Dir::open("/srv/backups").unwrap();
+///
+/// // `name` came from a user, "../etc" would fail instead of copying /srv/etc
+/// let name = "photos";
+/// uploads.copy_dir_all_to(name, &backups, name).unwrap();
+/// uploads.remove_dir_all(name).unwrap();
+/// ```
+///
+/// Only available on unix
+#[derive(Debug)]
+pub struct Dir {
+    fd: OwnedFd,
+    path: PathBuf,
+}
+
+impl Dir {
+    /// Opens a handle to the directory at `path`. The path itself may contain anything, only
+    /// the paths given to the handle are confined
+    pub fn open(path: impl AsRef<Path>) -> Result<Dir> {
+        let path = path.as_ref();
+
+        let c_path = cstring(path.as_os_str()).map_err(|e| io_ext(e, path, Operation::Open))?;
+        // SAFETY: the path is a valid c string
+        let fd = unsafe {
+            libc::open(
+                c_path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        let fd = owned(fd).map_err(|e| io_ext(e, path, Operation::Open))?;
+        Ok(Dir {
+            fd,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The path the handle was opened with, it is only used for errors
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens a handle to a directory beneath this one, it is confined to that directory
+    pub fn open_dir(&self, path: impl AsRef<Path>) -> Result<Dir> {
+        let path = path.as_ref();
+
+        let fd = self.open_beneath(path, libc::O_RDONLY | libc::O_DIRECTORY, 0, Operation::Open)?;
+        Ok(Dir {
+            fd,
+            path: self.path.join(path),
+        })
+    }
+
+    /// Opens a file in read only mode, like [`File::open`]
+    pub fn open_file(&self, path: impl AsRef<Path>) -> Result<File> {
+        let path = path.as_ref();
+
+        let fd = self.open_beneath(path, libc::O_RDONLY, 0, Operation::Open)?;
+        Ok(File::from_std(fs::File::from(fd), self.path.join(path)))
+    }
+
+    /// Opens a file in write only mode, creating or truncating it, like [`File::create`]
+    pub fn create_file(&self, path: impl AsRef<Path>) -> Result<File> {
+        let path = path.as_ref();
+
+        let fd = self.open_beneath(
+            path,
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+            0o666,
+            Operation::CreateFile,
+        )?;
+        Ok(File::from_std(fs::File::from(fd), self.path.join(path)))
+    }
+
+    /// The metadata of the file or directory at `path`, symlinks are followed
+    pub fn metadata(&self, path: impl AsRef<Path>) -> Result<fs::Metadata> {
+        let path = path.as_ref();
+
+        let fd = self.open_beneath(path, STAT, 0, Operation::Metadata)?;
+        fs::File::from(fd)
+            .metadata()
+            .map_err(|e| io_ext(e, &self.path.join(path), Operation::Metadata))
+    }
+
+    /// Creates a directory, its parent has to exist
+    pub fn create_dir(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let (parent, name) = self.parent_beneath(path, Operation::Create)?;
+        mkdirat(parent.as_fd(), &name, 0o777)
+            .map_err(|e| io_ext(e, &self.path.join(path), Operation::Create))
+    }
+
+    /// Creates a directory and all of its missing parents
+    pub fn create_dir_all(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let mut current = PathBuf::new();
+        for component in path.components() {
+            current.push(component);
+            if !matches!(component, Component::Normal(_)) {
+                continue;
+            }
+
+            match self.create_dir(&current) {
+                Err(e) if e.io_error_kind() == io::ErrorKind::AlreadyExists => {
+                    if !self.metadata(&current)?.is_dir() {
+                        return Err(e);
+                    }
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a file or a symlink, the target of a symlink is left alone
+    pub fn remove_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let (parent, name) = self.parent_beneath(path, Operation::Remove)?;
+        unlinkat(parent.as_fd(), &name, 0)
+            .map_err(|e| io_ext(e, &self.path.join(path), Operation::Remove))
+    }
+
+    /// Removes an empty directory
+    pub fn remove_dir(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let (parent, name) = self.parent_beneath(path, Operation::RemoveDir)?;
+        unlinkat(parent.as_fd(), &name, libc::AT_REMOVEDIR)
+            .map_err(|e| io_ext(e, &self.path.join(path), Operation::RemoveDir))
+    }
+
+    /// Removes a directory and everything inside of it. Symlinks inside are removed and never
+    /// followed, if `path` itself is a symlink only the symlink is removed
+    pub fn remove_dir_all(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let span = trace::operation!(
+            "dir_remove_dir_all",
+            root = %self.path.display(),
+            path = %path.display()
+        );
+        trace::traced(span, || {
+            let (parent, name) = self.parent_beneath(path, Operation::RemoveDirAll)?;
+            remove_entry(parent.as_fd(), &name)
+                .map_err(|e| io_ext(e, &self.path.join(path), Operation::RemoveDirAll))
+        })
+    }
+
+    /// Renames a file or directory, `to` is replaced like [`std::fs::rename`] does
+    pub fn rename(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+        self.rename_to(from, self, to)
+    }
+
+    /// The same as [`Dir::rename`] but `to` is beneath `dest`. Both have to be on the same file
+    /// system
+    pub fn rename_to(
+        &self,
+        from: impl AsRef<Path>,
+        dest: &Dir,
+        to: impl AsRef<Path>,
+    ) -> Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        let (from_parent, from_name) = self.parent_beneath(from, Operation::Rename)?;
+        let (to_parent, to_name) = dest.parent_beneath(to, Operation::Rename)?;
+        // SAFETY: both names are valid c strings and both directories are open
+        let result = unsafe {
+            libc::renameat(
+                from_parent.as_raw_fd(),
+                from_name.as_ptr(),
+                to_parent.as_raw_fd(),
+                to_name.as_ptr(),
+            )
+        };
+        cvt(result)
+            .map(drop)
+            .map_err(|e| self.io_ext_multi(e, from, dest, to, Operation::Rename))
+    }
+
+    /// Copies a file, like [`crate::copy`]. Returns the amount of bytes that were copied. A fifo,
+    /// socket or device fails with [`Error::SpecialFile`] instead of being opened
+    pub fn copy(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+        self.copy_to(from, self, to)
+    }
+
+    /// The same as [`Dir::copy`] but `to` is beneath `dest`
+    pub fn copy_to(&self, from: impl AsRef<Path>, dest: &Dir, to: impl AsRef<Path>) -> Result<u64> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        let span = trace::operation!(
+            "dir_copy",
+            from = %self.path.join(from).display(),
+            to = %dest.path.join(to).display()
+        );
+        trace::traced(span, || {
+            let source = self.open_source(from, Operation::Copy)?;
+            let source_stat = fstat(source.as_fd())
+                .map_err(|e| io_ext(e, &self.path.join(from), Operation::Metadata))?;
+            let file_type = file_type(source_stat.st_mode);
+            // a symlink may point to anything beneath the handle
+            if file_type != FileType::File {
+                return Err(Error::SpecialFile {
+                    path: self.path.join(from),
+                    file_type,
+                });
+            }
+            let destination =
+                dest.open_beneath(to, libc::O_WRONLY | libc::O_CREAT, 0o666, Operation::Copy)?;
+
+            let same_file = match fstat(destination.as_fd()) {
+                Ok(destination) => identity(&source_stat) == identity(&destination),
+                _ => false,
+            };
+            if same_file {
+                return Err(Error::SameFile {
+                    from: self.path.join(from),
+                    to: dest.path.join(to),
+                });
+            }
+
+            copy_contents(fs::File::from(source), fs::File::from(destination))
+                .map_err(|e| self.io_ext_multi(e, from, dest, to, Operation::Copy))
+        })
+    }
+
+    /// Recursively copies a directory beneath this handle to `to`, like [`crate::copy_dir_all`].
+    /// Symlinks are followed as long as they stay beneath the handle, a symlink to a directory
+    /// is copied as a directory
+    pub fn copy_dir_all(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+        self.copy_dir_all_to(from, self, to)
+    }
+
+    /// The same as [`Dir::copy_dir_all`] but `to` is beneath `dest`
+    pub fn copy_dir_all_to(
+        &self,
+        from: impl AsRef<Path>,
+        dest: &Dir,
+        to: impl AsRef<Path>,
+    ) -> Result<Report> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        let span = trace::operation!(
+            "dir_copy_dir_all",
+            from = %self.path.join(from).display(),
+            to = %dest.path.join(to).display()
+        );
+        trace::traced(span, || {
+            let source = self.open_beneath(
+                from,
+                libc::O_RDONLY | libc::O_DIRECTORY,
+                0,
+                Operation::CopyDirAll,
+            )?;
+            let (parent, name) = dest.parent_beneath(to, Operation::CopyDirAll)?;
+            let stat = fstat(source.as_fd())
+                .map_err(|e| io_ext(e, &self.path.join(from), Operation::Metadata))?;
+
+            // the directory is only writable by the owner until everything inside was copied, so
+            // read only directories can still be filled
+            mkdirat(parent.as_fd(), &name, 0o700)
+                .map_err(|e| io_ext(e, &dest.path.join(to), Operation::Create))?;
+            let destination = openat(
+                parent.as_fd(),
+                &name,
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW,
+                0,
+            )
+            .map_err(|e| io_ext(e, &dest.path.join(to), Operation::Open))?;
+
+            let root = fstat(destination.as_fd())
+                .map_err(|e| io_ext(e, &dest.path.join(to), Operation::Metadata))?;
+
+            let mut tree = Tree {
+                source: self,
+                dest,
+                root: identity(&root),
+                root_from: from,
+                root_to: to,
+                ancestors: vec![identity(&stat)],
+                report: Report {
+                    dirs: 1,
+                    ..Report::default()
+                },
+            };
+            tree.copy_dir(source.as_fd(), destination.as_fd(), from, to)?;
+            set_mode(&destination, stat.st_mode)
+                .map_err(|e| self.io_ext_multi(e, from, dest, to, Operation::CopyDirAll))?;
+
+            Ok(tree.report)
+        })
+    }
+
+    /// Moves a file, like [`crate::move_file`]. Returns the amount of bytes that were copied
+    pub fn move_file(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+        self.move_file_to(from, self, to)
+    }
+
+    /// The same as [`Dir::move_file`] but `to` is beneath `dest`
+    pub fn move_file_to(
+        &self,
+        from: impl AsRef<Path>,
+        dest: &Dir,
+        to: impl AsRef<Path>,
+    ) -> Result<u64> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        let span = trace::operation!(
+            "dir_move_file",
+            from = %self.path.join(from).display(),
+            to = %dest.path.join(to).display()
+        );
+        trace::traced(span, || {
+            let amount = self.copy_to(from, dest, to)?;
+            self.remove_file(from)?;
+            Ok(amount)
+        })
+    }
+
+    /// Moves a directory, like [`crate::move_dir_all`]
+    pub fn move_dir_all(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+        self.move_dir_all_to(from, self, to)
+    }
+
+    /// The same as [`Dir::move_dir_all`] but `to` is beneath `dest`
+    pub fn move_dir_all_to(
+        &self,
+        from: impl AsRef<Path>,
+        dest: &Dir,
+        to: impl AsRef<Path>,
+    ) -> Result<Report> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        let span = trace::operation!(
+            "dir_move_dir_all",
+            from = %self.path.join(from).display(),
+            to = %dest.path.join(to).display()
+        );
+        trace::traced(span, || {
+            let report = self.copy_dir_all_to(from, dest, to)?;
+            self.remove_dir_all(from)?;
+            Ok(report)
+        })
+    }
+
+    /// Opens `path` with the lookup confined to this directory
+    fn open_beneath(
+        &self,
+        path: &Path,
+        flags: c_int,
+        mode: mode_t,
+        operation: Operation,
+    ) -> Result<OwnedFd> {
+        resolve(self.fd.as_fd(), path, flags | libc::O_CLOEXEC, mode)
+            .map_err(|e| self.resolve_error(e, path, operation))
+    }
+
+    /// Opens `path` for reading without blocking on a fifo. Only a regular file or a symlink is
+    /// opened, anything else fails with [`Error::SpecialFile`] before it is touched
+    fn open_source(&self, path: &Path, operation: Operation) -> Result<OwnedFd> {
+        let (parent, name) = self.parent_beneath(path, operation)?;
+        let stat = fstatat(parent.as_fd(), &name, libc::AT_SYMLINK_NOFOLLOW)
+            .map_err(|e| io_ext(e, &self.path.join(path), Operation::SymlinkMetadata))?;
+
+        match file_type(stat.st_mode) {
+            FileType::File => openat(
+                parent.as_fd(),
+                &name,
+                libc::O_RDONLY | libc::O_NONBLOCK | libc::O_NOFOLLOW,
+                0,
+            )
+            .map_err(|e| io_ext(e, &self.path.join(path), operation)),
+            // the target is looked up from the root of the handle again, which rejects targets
+            // outside of it
+            FileType::Symlink => {
+                self.open_beneath(path, libc::O_RDONLY | libc::O_NONBLOCK, 0, operation)
+            }
+            file_type => Err(Error::SpecialFile {
+                path: self.path.join(path),
+                file_type,
+            }),
+        }
+    }
+
+    /// Opens the parent of `path` and returns it with the last component, which the caller
+    /// uses without following it
+    fn parent_beneath(&self, path: &Path, operation: Operation) -> Result<(OwnedFd, CString)> {
+        let name = match path.file_name() {
+            Some(name) => name,
+            None if path.is_absolute() || path.ends_with("..") => {
+                return Err(Error::PathEscape {
+                    path: path.to_path_buf(),
+                    root: self.path.clone(),
+                })
+            }
+            None => {
+                let e = io::Error::new(io::ErrorKind::InvalidInput, "path has no file name");
+                return Err(io_ext(e, &self.path.join(path), operation));
+            }
+        };
+        let name = cstring(name).map_err(|e| io_ext(e, &self.path.join(path), operation))?;
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let parent = self.open_beneath(parent, SEARCH | libc::O_DIRECTORY, 0, operation)?;
+        Ok((parent, name))
+    }
+
+    /// Resolving a path only fails with `EXDEV` if it would leave the directory
+    fn resolve_error(&self, source: io::Error, path: &Path, operation: Operation) -> Error {
+        if source.raw_os_error() == Some(libc::EXDEV) {
+            Error::PathEscape {
+                path: path.to_path_buf(),
+                root: self.path.clone(),
+            }
+        } else {
+            io_ext(source, &self.path.join(path), operation)
+        }
+    }
+
+    fn io_ext_multi(
+        &self,
+        source: io::Error,
+        from: &Path,
+        dest: &Dir,
+        to: &Path,
+        operation: Operation,
+    ) -> Error {
+        Error::IoExtMulti {
+            source,
+            from: self.path.join(from),
+            to: dest.path.join(to),
+            operation,
+        }
+    }
+}
+
+impl AsFd for Dir {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// A recursive copy from one handle to another
+struct Tree<'a> {
+    source: &'a Dir,
+    dest: &'a Dir,
+    /// The destination directory of the copy, it is never copied into itself
+    root: (u64, u64),
+    root_from: &'a Path,
+    root_to: &'a Path,
+    /// The source directories that are being copied, from the root down
+    ancestors: Vec<(u64, u64)>,
+    report: Report,
+}
+
+impl Tree<'_> {
+    /// Copies the contents of the directory `source`, which is at `from`, into `destination`.
+    /// The entries are opened relative to their directory without following symlinks, so a
+    /// directory that is replaced by a symlink during the copy isn't followed out of the handle
+    fn copy_dir(
+        &mut self,
+        source: BorrowedFd<'_>,
+        destination: BorrowedFd<'_>,
+        from: &Path,
+        to: &Path,
+    ) -> Result<()> {
+        let names =
+            read_dir(source).map_err(|e| io_ext(e, &self.source_path(from), Operation::ReadDir))?;
+
+        for name in names {
+            let from = from.join(OsStr::from_bytes(name.as_bytes()));
+            let to = to.join(OsStr::from_bytes(name.as_bytes()));
+            let stat = fstatat(source, &name, libc::AT_SYMLINK_NOFOLLOW)
+                .map_err(|e| io_ext(e, &self.source_path(&from), Operation::SymlinkMetadata))?;
+            trace::entry!(
+                path = %self.source_path(&from).display(),
+                file_type = %file_type(stat.st_mode),
+                "copy"
+            );
+
+            match file_type(stat.st_mode) {
+                FileType::Dir => {
+                    let subdir = openat(
+                        source,
+                        &name,
+                        libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW,
+                        0,
+                    )
+                    .map_err(|e| io_ext(e, &self.source_path(&from), Operation::Open))?;
+                    self.copy_subdir(subdir, destination, &name, &stat, &from, &to)?
+                }
+                FileType::File => {
+                    let file = openat(source, &name, libc::O_RDONLY | libc::O_NOFOLLOW, 0)
+                        .map_err(|e| io_ext(e, &self.source_path(&from), Operation::Open))?;
+                    self.report.bytes += self.copy_file(file, destination, &name, &from, &to)?;
+                    self.report.files += 1;
+                }
+                FileType::Symlink => {
+                    // the target is looked up from the root of the handle again, which rejects
+                    // targets outside of it
+                    let file = self.source.open_beneath(
+                        &from,
+                        libc::O_RDONLY | libc::O_NONBLOCK,
+                        0,
+                        Operation::Open,
+                    )?;
+                    self.copy_symlink(file, destination, &name, &from, &to)?;
+                }
+                file_type => {
+                    return Err(Error::SpecialFile {
+                        path: self.source_path(&from),
+                        file_type,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies what the symlink points to, a directory is copied like any other. The target
+    /// was opened without blocking, anything but a file or a directory is rejected
+    fn copy_symlink(
+        &mut self,
+        target: OwnedFd,
+        destination: BorrowedFd<'_>,
+        name: &CStr,
+        from: &Path,
+        to: &Path,
+    ) -> Result<()> {
+        let stat = fstat(target.as_fd())
+            .map_err(|e| io_ext(e, &self.source_path(from), Operation::Metadata))?;
+
+        match file_type(stat.st_mode) {
+            FileType::Dir => {
+                // a symlink to a directory that is being copied would never end
+                if self.ancestors.contains(&identity(&stat)) {
+                    let e = io::Error::from_raw_os_error(libc::ELOOP);
+                    return Err(io_ext(e, &self.source_path(from), Operation::Open));
+                }
+                self.copy_subdir(target, destination, name, &stat, from, to)
+            }
+            FileType::File => {
+                self.report.bytes += self.copy_file(target, destination, name, from, to)?;
+                self.report.symlinks += 1;
+                Ok(())
+            }
+            file_type => Err(Error::SpecialFile {
+                path: self.source_path(from),
+                file_type,
+            }),
+        }
+    }
+
+    fn copy_subdir(
+        &mut self,
+        source: OwnedFd,
+        destination: BorrowedFd<'_>,
+        name: &CStr,
+        stat: &libc::stat,
+        from: &Path,
+        to: &Path,
+    ) -> Result<()> {
+        if identity(stat) == self.root {
+            return Err(Error::DestinationInsideSource {
+                from: self.source_path(self.root_from),
+                to: self.dest_path(self.root_to),
+            });
+        }
+
+        mkdirat(destination, name, 0o700)
+            .map_err(|e| io_ext(e, &self.dest_path(to), Operation::Create))?;
+        let subdir = openat(
+            destination,
+            name,
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW,
+            0,
+        )
+        .map_err(|e| io_ext(e, &self.dest_path(to), Operation::Open))?;
+        self.report.dirs += 1;
+
+        self.ancestors.push(identity(stat));
+        self.copy_dir(source.as_fd(), subdir.as_fd(), from, to)?;
+        self.ancestors.pop();
+        set_mode(&subdir, stat.st_mode).map_err(|e| Error::IoExtMulti {
+            source: e,
+            from: self.source_path(from),
+            to: self.dest_path(to),
+            operation: Operation::CopyDirAll,
+        })
+    }
+
+    fn copy_file(
+        &self,
+        source: OwnedFd,
+        destination: BorrowedFd<'_>,
+        name: &CStr,
+        from: &Path,
+        to: &Path,
+    ) -> Result<u64> {
+        let error = |e| Error::IoExtMulti {
+            source: e,
+            from: self.source_path(from),
+            to: self.dest_path(to),
+            operation: Operation::Copy,
+        };
+
+        let file = openat(
+            destination,
+            name,
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_NOFOLLOW,
+            0o666,
+        )
+        .map_err(error)?;
+        copy_contents(fs::File::from(source), fs::File::from(file)).map_err(error)
+    }
+
+    fn source_path(&self, path: &Path) -> PathBuf {
+        self.source.path.join(path)
+    }
+
+    fn dest_path(&self, path: &Path) -> PathBuf {
+        self.dest.path.join(path)
+    }
+}
+
+/// Opens `path` beneath `root`. Escaping from `root` fails with `EXDEV`, like `openat2` does
+fn resolve(root: BorrowedFd<'_>, path: &Path, flags: c_int, mode: mode_t) -> io::Result<OwnedFd> {
+    #[cfg(target_os = "linux")]
+    if let Some(result) = openat2::resolve(root, path, flags, mode) {
+        return result;
+    }
+
+    resolve_components(root, path, flags, mode)
+}
+
+/// Resolves `path` one component at a time. Every component is opened with `O_NOFOLLOW`, a
+/// symlink is read and its target is resolved in its place. The directories that were passed
+/// are kept open so `..` can go back up without ever going above `root`
+pub(crate) fn resolve_components(
+    root: BorrowedFd<'_>,
+    path: &Path,
+    flags: c_int,
+    mode: mode_t,
+) -> io::Result<OwnedFd> {
+    let mut pending = components(path)?;
+    let mut opened: Vec<OwnedFd> = Vec::new();
+    let mut symlinks = 0;
+
+    while let Some(name) = pending.pop_front() {
+        let current = opened.last().map_or(root, |fd| fd.as_fd());
+        if name.as_bytes() == b".." {
+            if opened.pop().is_none() {
+                return Err(escape());
+            }
+            continue;
+        }
+
+        let name = cstring(&name)?;
+        let last = pending.is_empty();
+        let component_flags = if last {
+            flags | libc::O_NOFOLLOW
+        } else {
+            SEARCH | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC
+        };
+
+        let target = match openat(current, &name, component_flags, mode) {
+            Ok(fd) if !last => {
+                opened.push(fd);
+                continue;
+            }
+            // with O_PATH and O_NOFOLLOW the symlink itself is opened instead of failing, O_RDONLY
+            // is zero so this is only checked where SEARCH is O_PATH
+            Ok(fd) if flags & SEARCH != 0 && is_symlink(fd.as_fd())? => readlinkat(current, &name)?,
+            Ok(fd) => return Ok(fd),
+            // a file that must not exist yet is never followed
+            Err(e) if e.raw_os_error() == Some(libc::EEXIST) => return Err(e),
+            Err(e) => match readlinkat(current, &name) {
+                Ok(target) => target,
+                Err(_) => return Err(e),
+            },
+        };
+
+        symlinks += 1;
+        if symlinks > MAX_SYMLINKS {
+            return Err(io::Error::from_raw_os_error(libc::ELOOP));
+        }
+        for component in components(&target)?.into_iter().rev() {
+            pending.push_front(component);
+        }
+    }
+
+    // the path ended in a directory that is already open, like `""`, `.` or `a/..`
+    let current = opened.last().map_or(root, |fd| fd.as_fd());
+    openat(current, dot(), flags, mode)
+}
+
+/// The components of a relative path, `.` is left out. A path that starts at the root of the
+/// file system escapes right away
+fn components(path: &Path) -> io::Result<VecDeque<OsString>> {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| match component {
+            Component::Normal(name) => Ok(name.to_os_string()),
+            Component::ParentDir => Ok(OsString::from("..")),
+            _ => Err(escape()),
+        })
+        .collect()
+}
+
+fn dot() -> &'static CStr {
+    CStr::from_bytes_with_nul(b".\0").expect("a valid c string")
+}
+
+fn escape() -> io::Error {
+    io::Error::from_raw_os_error(libc::EXDEV)
+}
+
+#[cfg(target_os = "linux")]
+mod openat2 {
+    use std::io;
+    use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use libc::{c_int, mode_t};
+
+    /// Set once the kernel or a seccomp filter turned `openat2` down
+    static UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+    /// How often a lookup that raced with a rename is tried again before falling back
+    const RETRIES: usize = 8;
+
+    /// Resolves `path` with `RESOLVE_BENEATH`. Returns `None` if `openat2` can't be used
+    pub(super) fn resolve(
+        root: BorrowedFd<'_>,
+        path: &Path,
+        flags: c_int,
+        mode: mode_t,
+    ) -> Option<io::Result<OwnedFd>> {
+        if UNSUPPORTED.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
+        };
+        let path = match super::cstring(path.as_os_str()) {
+            Ok(path) => path,
+            Err(e) => return Some(Err(e)),
+        };
+
+        // SAFETY: open_how is plain data and all zeroes are valid
+        let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+        how.flags = flags as u64;
+        // the kernel rejects a mode without O_CREAT
+        if flags & libc::O_CREAT != 0 {
+            how.mode = u64::from(mode);
+        }
+        how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+
+        for _ in 0..RETRIES {
+            // SAFETY: the path is a valid c string and how is a valid open_how of the given size
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_openat2,
+                    root.as_raw_fd(),
+                    path.as_ptr(),
+                    &how as *const libc::open_how,
+                    std::mem::size_of::<libc::open_how>(),
+                )
+            };
+            if fd >= 0 {
+                // SAFETY: the kernel returned a new file descriptor
+                return Some(Ok(unsafe { OwnedFd::from_raw_fd(fd as c_int) }));
+            }
+
+            let e = io::Error::last_os_error();
+            match e.raw_os_error() {
+                Some(libc::EAGAIN) => continue,
+                Some(libc::ENOSYS) | Some(libc::EPERM) => {
+                    UNSUPPORTED.store(true, Ordering::Relaxed);
+                    return None;
+                }
+                _ => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// Removes whatever `name` is without following it
+fn remove_entry(parent: BorrowedFd<'_>, name: &CStr) -> io::Result<()> {
+    let stat = fstatat(parent, name, libc::AT_SYMLINK_NOFOLLOW)?;
+    if file_type(stat.st_mode) != FileType::Dir {
+        return unlinkat(parent, name, 0);
+    }
+
+    let dir = openat(
+        parent,
+        name,
+        libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW,
+        0,
+    )?;
+    for child in read_dir(dir.as_fd())? {
+        remove_entry(dir.as_fd(), &child)?;
+    }
+    unlinkat(parent, name, libc::AT_REMOVEDIR)
+}
+
+/// The names inside of the directory, without `.` and `..`
+fn read_dir(dir: BorrowedFd<'_>) -> io::Result<Vec<CString>> {
+    // a new open file description, so the position of `dir` isn't moved
+    let fd = openat(dir, dot(), libc::O_RDONLY | libc::O_DIRECTORY, 0)?;
+    // SAFETY: the fd is an open directory, the stream owns it from now on
+    let stream = unsafe { libc::fdopendir(fd.as_raw_fd()) };
+    if stream.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    std::mem::forget(fd);
+
+    let mut names = Vec::new();
+    let result = loop {
+        clear_errno();
+        // SAFETY: the stream is open until closedir below
+        let entry = unsafe { libc::readdir(stream) };
+        if entry.is_null() {
+            let e = io::Error::last_os_error();
+            break match e.raw_os_error() {
+                Some(0) | None => Ok(()),
+                Some(_) => Err(e),
+            };
+        }
+
+        // SAFETY: readdir returned a valid entry whose name is a c string
+        let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+        if name.to_bytes() != b"." && name.to_bytes() != b".." {
+            names.push(name.to_owned());
+        }
+    };
+    // SAFETY: the stream is open and not used after this
+    unsafe { libc::closedir(stream) };
+
+    result.map(|()| names)
+}
+
+/// Readdir only reports errors through errno. The module is only built for the targets below
+#[cfg(any(
+    target_os = "linux",
+    target_os = "emscripten",
+    target_os = "dragonfly",
+    target_os = "hurd",
+    target_os = "redox"
+))]
+fn clear_errno() {
+    // SAFETY: errno is thread local
+    unsafe { *libc::__errno_location() = 0 }
+}
+
+#[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+fn clear_errno() {
+    // SAFETY: errno is thread local
+    unsafe { *libc::__error() = 0 }
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "cygwin"
+))]
+fn clear_errno() {
+    // SAFETY: errno is thread local
+    unsafe { *libc::__errno() = 0 }
+}
+
+#[cfg(any(target_os = "solaris", target_os = "illumos"))]
+fn clear_errno() {
+    // SAFETY: errno is thread local
+    unsafe { *libc::___errno() = 0 }
+}
+
+#[cfg(target_os = "haiku")]
+fn clear_errno() {
+    // SAFETY: errno is thread local
+    unsafe { *libc::_errnop() = 0 }
+}
+
+#[cfg(target_os = "aix")]
+fn clear_errno() {
+    // SAFETY: errno is thread local
+    unsafe { *libc::_Errno() = 0 }
+}
+
+fn openat(dir: BorrowedFd<'_>, name: &CStr, flags: c_int, mode: mode_t) -> io::Result<OwnedFd> {
+    // SAFETY: the name is a valid c string and dir is open
+    let fd = unsafe {
+        libc::openat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            flags | libc::O_CLOEXEC,
+            libc::c_uint::from(mode),
+        )
+    };
+    owned(fd)
+}
+
+fn mkdirat(dir: BorrowedFd<'_>, name: &CStr, mode: mode_t) -> io::Result<()> {
+    // SAFETY: the name is a valid c string and dir is open
+    cvt(unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), mode) }).map(drop)
+}
+
+fn unlinkat(dir: BorrowedFd<'_>, name: &CStr, flags: c_int) -> io::Result<()> {
+    // SAFETY: the name is a valid c string and dir is open
+    cvt(unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), flags) }).map(drop)
+}
+
+fn fstatat(dir: BorrowedFd<'_>, name: &CStr, flags: c_int) -> io::Result<libc::stat> {
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: the name is a valid c string and fstatat only writes to the buffer
+    cvt(unsafe { libc::fstatat(dir.as_raw_fd(), name.as_ptr(), stat.as_mut_ptr(), flags) })?;
+    // SAFETY: fstatat succeeded so the buffer was written
+    Ok(unsafe { stat.assume_init() })
+}
+
+fn readlinkat(dir: BorrowedFd<'_>, name: &CStr) -> io::Result<PathBuf> {
+    let mut buffer = vec![0u8; 256];
+    loop {
+        // SAFETY: the name is a valid c string and the buffer is as long as given
+        let len = unsafe {
+            libc::readlinkat(
+                dir.as_raw_fd(),
+                name.as_ptr(),
+                buffer.as_mut_ptr().cast(),
+                buffer.len(),
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let len = len as usize;
+        // the target may have been cut off if it filled the whole buffer
+        if len < buffer.len() {
+            buffer.truncate(len);
+            return Ok(PathBuf::from(OsString::from_vec(buffer)));
+        }
+        buffer.resize(buffer.len() * 2, 0);
+    }
+}
+
+fn fstat(fd: BorrowedFd<'_>) -> io::Result<libc::stat> {
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: fstat only writes to the buffer
+    cvt(unsafe { libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()) })?;
+    // SAFETY: fstat succeeded so the buffer was written
+    Ok(unsafe { stat.assume_init() })
+}
+
+fn is_symlink(fd: BorrowedFd<'_>) -> io::Result<bool> {
+    Ok(file_type(fstat(fd)?.st_mode) == FileType::Symlink)
+}
+
+/// The device and inode, which are the same for two paths to the same file
+#[allow(clippy::unnecessary_cast)] // the types of st_dev and st_ino differ between platforms
+fn identity(stat: &libc::stat) -> (u64, u64) {
+    (stat.st_dev as u64, stat.st_ino as u64)
+}
+
+fn set_mode(fd: &OwnedFd, mode: mode_t) -> io::Result<()> {
+    // SAFETY: fchmod only reads the fd
+    cvt(unsafe { libc::fchmod(fd.as_raw_fd(), mode & 0o7777) }).map(drop)
+}
+
+/// Copies the contents and the permissions, like [`std::fs::copy`]
+fn copy_contents(mut source: fs::File, mut destination: fs::File) -> io::Result<u64> {
+    let permissions = source.metadata()?.permissions();
+    destination.set_len(0)?;
+    let amount = io::copy(&mut source, &mut destination)?;
+    destination.set_permissions(permissions)?;
+    Ok(amount)
+}
+
+fn file_type(mode: mode_t) -> FileType {
+    match mode & libc::S_IFMT {
+        libc::S_IFDIR => FileType::Dir,
+        libc::S_IFREG => FileType::File,
+        libc::S_IFLNK => FileType::Symlink,
+        libc::S_IFIFO => FileType::Fifo,
+        libc::S_IFSOCK => FileType::Socket,
+        libc::S_IFCHR => FileType::CharDevice,
+        libc::S_IFBLK => FileType::BlockDevice,
+        _ => FileType::Other,
+    }
+}
+
+fn cstring(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes()).map_err(io::Error::from)
+}
+
+fn cvt(result: c_int) -> io::Result<c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn owned(fd: c_int) -> io::Result<OwnedFd> {
+    // SAFETY: a non negative result of open is a new file descriptor that nothing else owns
+    cvt(fd).map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn io_ext(source: io::Error, path: &Path, operation: Operation) -> Error {
+    Error::IoExt {
+        source,
+        path: path.to_path_buf(),
+        operation,
+    }
+}
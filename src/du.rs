@@ -0,0 +1,338 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, Operation, Result};
+use crate::trace;
+use crate::vfs::{DirEntry, FileType};
+use crate::walk;
+
+/// How many of the largest files [`disk_usage`] reports by default
+const DEFAULT_LARGEST: usize = 10;
+
+type Filter = Arc<dyn Fn(&DirEntry) -> bool + Send + Sync>;
+
+/// Options for [`disk_usage_with`]
+///
+/// ```no_run
+/// use more_fs::{disk_usage_with, DuOptions};
+///
+/// let usage = disk_usage_with(
+///     "/home",
+///     DuOptions::new()
+///         .one_file_system(true)
+///         .filter(|entry| entry.path().file_name() != Some(".cache".as_ref())),
+/// )
+/// .unwrap();
+/// println!("{} bytes on disk", usage.allocated_size);
+/// ```
+#[derive(Clone)]
+pub struct DuOptions {
+    one_file_system: bool,
+    max_depth: Option<usize>,
+    largest: usize,
+    filter: Option<Filter>,
+}
+
+impl Default for DuOptions {
+    fn default() -> Self {
+        DuOptions {
+            one_file_system: false,
+            max_depth: None,
+            largest: DEFAULT_LARGEST,
+            filter: None,
+        }
+    }
+}
+
+impl fmt::Debug for DuOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DuOptions")
+            .field("one_file_system", &self.one_file_system)
+            .field("max_depth", &self.max_depth)
+            .field("largest", &self.largest)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+impl DuOptions {
+    /// Creates the default options, these behave exactly like [`disk_usage`]
+    pub fn new() -> DuOptions {
+        DuOptions::default()
+    }
+
+    /// Skips directories that are on a different filesystem than the root, like `du -x`
+    pub fn one_file_system(&mut self, one_file_system: bool) -> &mut DuOptions {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Only counts entries up to `depth` levels below the root. The root has a depth of zero
+    pub fn max_depth(&mut self, depth: usize) -> &mut DuOptions {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Sets how many of the largest files are reported. Defaults to 10
+    pub fn largest(&mut self, amount: usize) -> &mut DuOptions {
+        self.largest = amount;
+        self
+    }
+
+    /// Only counts the entries for which `filter` returns true. The contents of a directory
+    /// that is filtered out are skipped as well. The root is never passed to the filter
+    pub fn filter(
+        &mut self,
+        filter: impl Fn(&DirEntry) -> bool + Send + Sync + 'static,
+    ) -> &mut DuOptions {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+}
+
+/// The sizes of a directory below the root of [`disk_usage`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirUsage {
+    pub path: PathBuf,
+    pub apparent_size: u64,
+    pub allocated_size: u64,
+}
+
+/// The result of [`disk_usage`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiskUsage {
+    /// The sum of the lengths of all files and symlinks
+    pub apparent_size: u64,
+    /// The amount of bytes that are allocated on the disk. A file with multiple hard links is
+    /// only counted once. On platforms that don't report blocks this is the apparent size
+    pub allocated_size: u64,
+    /// The amount of files, a file with multiple hard links is counted for every link
+    pub files: u64,
+    /// The amount of directories, including the root
+    pub dirs: u64,
+    pub symlinks: u64,
+    /// Sockets, device files and other special files
+    pub others: u64,
+    /// The largest files together with their length, largest first
+    pub largest: Vec<(PathBuf, u64)>,
+    /// The totals of every direct subdirectory of the root, largest first
+    pub children: Vec<DirUsage>,
+}
+
+impl DiskUsage {
+    fn merge(&mut self, other: DiskUsage, largest: usize) {
+        self.apparent_size += other.apparent_size;
+        self.allocated_size += other.allocated_size;
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.symlinks += other.symlinks;
+        self.others += other.others;
+        self.largest.extend(other.largest);
+        trim_largest(&mut self.largest, largest);
+    }
+}
+
+fn trim_largest(largest: &mut Vec<(PathBuf, u64)>, amount: usize) {
+    largest.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    largest.truncate(amount);
+}
+
+/// Returns how much space a file or directory tree takes up. Directories are walked in parallel
+/// and symlinks are not followed.
+///
+/// ```no_run
+/// let usage = more_fs::disk_usage("from_directory").unwrap();
+/// println!("moving {} files with {} bytes", usage.files, usage.apparent_size);
+/// ```
+pub fn disk_usage(path: impl AsRef<Path>) -> Result<DiskUsage> {
+    disk_usage_with(path, &DuOptions::new())
+}
+
+/// The same as [`disk_usage`] but with options
+pub fn disk_usage_with(path: impl AsRef<Path>, options: &DuOptions) -> Result<DiskUsage> {
+    let path = path.as_ref();
+
+    let span = trace::operation!("disk_usage", path = %path.display());
+    trace::traced(span, || {
+        let metadata = symlink_metadata(path)?;
+
+        let walker = Walker {
+            options,
+            root_dev: dev(&metadata),
+            seen: Mutex::new(HashSet::new()),
+        };
+
+        let mut usage = walker.count(path, &metadata);
+        if metadata.is_dir() && options.max_depth != Some(0) {
+            let counted = walk::walk_par(
+                path,
+                |entry, counted| walker.visit(entry, counted),
+                |a, b| a.merge(b, options.largest),
+            )?;
+
+            usage.merge(counted.usage, options.largest);
+            let mut children: Vec<_> = counted.children.into_values().collect();
+            children.sort_unstable_by(|a, b| {
+                (b.apparent_size.cmp(&a.apparent_size)).then_with(|| a.path.cmp(&b.path))
+            });
+            usage.children = children;
+        }
+
+        Ok(usage)
+    })
+}
+
+/// What one task of the walk counted
+#[derive(Default)]
+struct Counted {
+    usage: DiskUsage,
+    /// The totals of the direct subdirectories of the root, by their path
+    children: HashMap<PathBuf, DirUsage>,
+}
+
+impl Counted {
+    fn merge(mut self, other: Counted, largest: usize) -> Counted {
+        self.usage.merge(other.usage, largest);
+        for (path, child) in other.children {
+            let total = self.children.entry(path).or_insert_with(|| DirUsage {
+                path: child.path.clone(),
+                apparent_size: 0,
+                allocated_size: 0,
+            });
+            total.apparent_size += child.apparent_size;
+            total.allocated_size += child.allocated_size;
+        }
+        self
+    }
+}
+
+struct Walker<'a> {
+    options: &'a DuOptions,
+    root_dev: Option<u64>,
+    /// Files with more than one hard link that were already counted
+    seen: Mutex<HashSet<(u64, u64)>>,
+}
+
+impl Walker<'_> {
+    /// Counts an entry of the walk and returns whether the walk enters it
+    fn visit(&self, entry: &DirEntry, counted: &mut Counted) -> Result<bool> {
+        // the root was already counted
+        if entry.depth == 0 {
+            return Ok(true);
+        }
+        if let Some(filter) = &self.options.filter {
+            if !filter(entry) {
+                return Ok(false);
+            }
+        }
+
+        let metadata = symlink_metadata(&entry.path)?;
+        let is_dir = entry.file_type.is_dir();
+        if is_dir && self.options.one_file_system && dev(&metadata) != self.root_dev {
+            return Ok(false);
+        }
+
+        let usage = self.count(&entry.path, &metadata);
+        // everything below the root that is deeper than a file belongs to a subdirectory
+        if let Some(child) = entry.path.ancestors().nth(entry.depth - 1) {
+            if entry.depth > 1 || is_dir {
+                let total = counted
+                    .children
+                    .entry(child.to_path_buf())
+                    .or_insert_with(|| DirUsage {
+                        path: child.to_path_buf(),
+                        apparent_size: 0,
+                        allocated_size: 0,
+                    });
+                total.apparent_size += usage.apparent_size;
+                total.allocated_size += usage.allocated_size;
+            }
+        }
+        counted.usage.merge(usage, self.options.largest);
+
+        Ok(is_dir && self.options.max_depth.is_none_or(|max| entry.depth < max))
+    }
+
+    fn count(&self, path: &Path, metadata: &fs::Metadata) -> DiskUsage {
+        let mut usage = DiskUsage::default();
+        let file_type = FileType::from(metadata.file_type());
+        match file_type {
+            FileType::File => usage.files = 1,
+            FileType::Dir => usage.dirs = 1,
+            FileType::Symlink => usage.symlinks = 1,
+            _ => usage.others = 1,
+        }
+        if file_type != FileType::Dir {
+            usage.apparent_size = metadata.len();
+        }
+        if file_type == FileType::File && self.options.largest > 0 {
+            usage.largest.push((path.to_path_buf(), metadata.len()));
+        }
+        if self.first_link(metadata) {
+            usage.allocated_size = allocated_size(metadata);
+        }
+        usage
+    }
+
+    #[cfg(unix)]
+    fn first_link(&self, metadata: &fs::Metadata) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        metadata.is_dir()
+            || metadata.nlink() <= 1
+            || self
+                .seen
+                .lock()
+                .unwrap()
+                .insert((metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn first_link(&self, _metadata: &fs::Metadata) -> bool {
+        true
+    }
+}
+
+#[cfg(unix)]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    // the blocks are always 512 bytes, independent of the block size of the filesystem
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+#[cfg(unix)]
+fn dev(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(metadata.dev())
+}
+
+/// There is no stable way to get the volume of a file on this platform, so everything is
+/// treated as one filesystem
+#[cfg(not(unix))]
+fn dev(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+fn symlink_metadata(path: &Path) -> Result<fs::Metadata> {
+    fs::symlink_metadata(path).map_err(|e| io_ext(e, path, Operation::Metadata))
+}
+
+fn io_ext(source: std::io::Error, path: &Path, operation: Operation) -> Error {
+    Error::IoExt {
+        source,
+        path: path.to_path_buf(),
+        operation,
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::path::Path;
+
+use crate::error::{Error, Operation, Result};
+
+/// Opens a handle that is allowed to be flushed. Windows needs write access for that, on unix a
+/// read only handle is enough, which also works for files that were copied as read only
+fn open_for_sync(path: &Path) -> std::io::Result<File> {
+    #[cfg(windows)]
+    {
+        std::fs::OpenOptions::new().write(true).open(path)
+    }
+
+    #[cfg(not(windows))]
+    {
+        File::open(path)
+    }
+}
+
+/// Flushes the contents and metadata of a file to the disk
+pub fn sync_file(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+
+    open_for_sync(path)
+        .and_then(|file| file.sync_all())
+        .map_err(|e| Error::IoExt {
+            source: e,
+            path: path.to_path_buf(),
+            operation: Operation::Sync,
+        })
+}
+
+/// Flushes the entries of a directory to the disk. This is needed for a newly created file to
+/// survive a crash, syncing only the file itself is not enough.
+#[cfg(unix)]
+pub fn sync_dir(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+
+    File::open(path)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| Error::IoExt {
+            source: e,
+            path: path.to_path_buf(),
+            operation: Operation::Sync,
+        })
+}
+
+/// Directories can't be opened as files on this platform, the filesystem is responsible for
+/// making directory entries durable
+#[cfg(not(unix))]
+pub fn sync_dir(_path: impl AsRef<Path>) -> Result<()> {
+    Ok(())
+}
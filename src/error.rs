@@ -3,6 +3,8 @@ use std::fmt;
 use std::path::StripPrefixError;
 use std::{io, path::PathBuf};
 
+use crate::vfs::FileType;
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Debug)]
@@ -38,9 +40,105 @@ pub enum Error {
     WalkDir {
         source: walkdir::Error,
     },
+
+    /// A path that came from an untrusted source, like an entry of an archive, would be
+    /// placed outside of `root`
+    PathEscape {
+        path: PathBuf,
+        root: PathBuf,
+    },
+
+    /// Problems that were found by the preflight checks before anything was written, checkout
+    /// [`CopyOptions::preflight`](crate::CopyOptions::preflight)
+    Preflight {
+        problems: Vec<PreflightProblem>,
+    },
+
+    /// The destination of a recursive copy or move is the source itself or inside of it, so the
+    /// walk would pick up the entries it just created
+    DestinationInsideSource {
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    /// The source and the destination of a copy are the same file, through a hard link, a
+    /// symlink or a different spelling of the same path. Copying would truncate the file
+    SameFile {
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    /// A recursive copy found a fifo, socket or device file and
+    /// [`SpecialFiles::Error`](crate::SpecialFiles::Error) was set
+    SpecialFile {
+        path: PathBuf,
+        file_type: FileType,
+    },
+
+    /// A recursive copy found an existing file at `to` and the conflict hook set with
+    /// [`CopyOptions::on_conflict`](crate::CopyOptions::on_conflict) returned
+    /// [`ConflictAction::Abort`](crate::ConflictAction::Abort)
+    Conflict {
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    /// A lock was still held by someone else when the timeout of
+    /// [`LockOptions::timeout`](crate::LockOptions::timeout) ran out
+    Locked {
+        path: PathBuf,
+    },
 }
 
+/// A problem that would make a recursive copy fail halfway through
 #[derive(Debug)]
+pub enum PreflightProblem {
+    /// The filesystem of `path` has less free space than the files that would be copied to it
+    NotEnoughSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+    /// The destination or its closest existing parent is not writable
+    NotWritable { path: PathBuf },
+    /// The file name of `path` is longer than the destination filesystem allows
+    NameTooLong { path: PathBuf, max: u64 },
+    /// An entry of the source can't be read
+    Unreadable { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for PreflightProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightProblem::NotEnoughSpace {
+                path,
+                needed,
+                available,
+            } => write!(
+                f,
+                "{} needs {} bytes but only {} are available",
+                path.display(),
+                needed,
+                available
+            ),
+            PreflightProblem::NotWritable { path } => {
+                write!(f, "{} is not writable", path.display())
+            }
+            PreflightProblem::NameTooLong { path, max } => write!(
+                f,
+                "the name of {} is longer than {} bytes",
+                path.display(),
+                max
+            ),
+            PreflightProblem::Unreadable { path, source } => {
+                write!(f, "{} can't be read: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+/// What was being done when an [`Error`] happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operation {
     Remove,
     RemoveDirAll,
@@ -50,6 +148,33 @@ pub enum Operation {
     MoveDirAll,
     Copy,
     CopyDirAll,
+    Sync,
+    Metadata,
+    Read,
+    Write,
+    Pack,
+    Unpack,
+    Compress,
+    Decompress,
+    Dedupe,
+    Canonicalize,
+    Rename,
+    ReadToString,
+    SymlinkMetadata,
+    ReadDir,
+    ReadLink,
+    HardLink,
+    Symlink,
+    SetPermissions,
+    RemoveDir,
+    Open,
+    CreateFile,
+    Seek,
+    SetLen,
+    Flush,
+    Lock,
+    Unlock,
+    Watch,
 }
 
 impl fmt::Display for Operation {
@@ -63,6 +188,33 @@ impl fmt::Display for Operation {
             Operation::MoveDirAll => write!(f, "move dir all"),
             Operation::Copy => write!(f, "copy"),
             Operation::CopyDirAll => write!(f, "copy dir all"),
+            Operation::Sync => write!(f, "sync"),
+            Operation::Metadata => write!(f, "metadata"),
+            Operation::Read => write!(f, "read"),
+            Operation::Write => write!(f, "write"),
+            Operation::Pack => write!(f, "pack"),
+            Operation::Unpack => write!(f, "unpack"),
+            Operation::Compress => write!(f, "compress"),
+            Operation::Decompress => write!(f, "decompress"),
+            Operation::Dedupe => write!(f, "dedupe"),
+            Operation::Canonicalize => write!(f, "canonicalize"),
+            Operation::Rename => write!(f, "rename"),
+            Operation::ReadToString => write!(f, "read to string"),
+            Operation::SymlinkMetadata => write!(f, "symlink metadata"),
+            Operation::ReadDir => write!(f, "read dir"),
+            Operation::ReadLink => write!(f, "read link"),
+            Operation::HardLink => write!(f, "hard link"),
+            Operation::Symlink => write!(f, "symlink"),
+            Operation::SetPermissions => write!(f, "set permissions"),
+            Operation::RemoveDir => write!(f, "remove dir"),
+            Operation::Open => write!(f, "open"),
+            Operation::CreateFile => write!(f, "create file"),
+            Operation::Seek => write!(f, "seek"),
+            Operation::SetLen => write!(f, "set len"),
+            Operation::Flush => write!(f, "flush"),
+            Operation::Lock => write!(f, "lock"),
+            Operation::Unlock => write!(f, "unlock"),
+            Operation::Watch => write!(f, "watch"),
         }
     }
 }
@@ -126,6 +278,41 @@ impl fmt::Display for Error {
                 operation, recovery
             ),
             Error::NotDirectory { path } => write!(f, "{} is not a directory", path.display()),
+            Error::Locked { path } => write!(f, "{} is locked", path.display()),
+            Error::Conflict { from, to } => write!(
+                f,
+                "Aborted copying {} because {} already exists",
+                from.display(),
+                to.display()
+            ),
+            Error::SpecialFile { path, file_type } => write!(
+                f,
+                "{} is a {} and can not be copied",
+                path.display(),
+                file_type
+            ),
+            Error::SameFile { from, to } => write!(
+                f,
+                "{} and {} are the same file",
+                from.display(),
+                to.display()
+            ),
+            Error::DestinationInsideSource { from, to } => write!(
+                f,
+                "Can not copy {} into itself at {}",
+                from.display(),
+                to.display()
+            ),
+            Error::Preflight { problems } => {
+                write!(f, "Preflight checks found {} problems", problems.len())?;
+                for (i, problem) in problems.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, problem)?;
+                }
+                Ok(())
+            }
+            Error::PathEscape { path, root } => {
+                write!(f, "{} would escape from {}", path.display(), root.display())
+            }
         }
     }
 }
@@ -134,6 +321,13 @@ impl Error {
     pub fn io_error(&self) -> Option<&io::Error> {
         match self {
             Error::NotDirectory { .. } => None,
+            Error::Locked { .. } => None,
+            Error::Conflict { .. } => None,
+            Error::SpecialFile { .. } => None,
+            Error::SameFile { .. } => None,
+            Error::DestinationInsideSource { .. } => None,
+            Error::Preflight { .. } => None,
+            Error::PathEscape { .. } => None,
             Error::IoExt { source, .. } => Some(source),
             Error::IoExtMulti { source, .. } => Some(source),
             Error::StripPrefix { .. } => None,
@@ -145,6 +339,13 @@ impl Error {
     pub fn into_io_error(self) -> Option<io::Error> {
         match self {
             Error::NotDirectory { .. } => None,
+            Error::Locked { .. } => None,
+            Error::Conflict { .. } => None,
+            Error::SpecialFile { .. } => None,
+            Error::SameFile { .. } => None,
+            Error::DestinationInsideSource { .. } => None,
+            Error::Preflight { .. } => None,
+            Error::PathEscape { .. } => None,
             Error::IoExt { source, .. } => Some(source),
             Error::IoExtMulti { source, .. } => Some(source),
             Error::StripPrefix { .. } => None,
@@ -154,7 +355,6 @@ impl Error {
     }
 
     pub fn io_error_kind(&self) -> io::ErrorKind {
-        let io_error = self.io_error();
         match self.io_error() {
             Some(io_error) => io_error.kind(),
             None => io::ErrorKind::Other,
@@ -181,6 +381,13 @@ impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
             Error::NotDirectory { .. } => None,
+            Error::Locked { .. } => None,
+            Error::Conflict { .. } => None,
+            Error::SpecialFile { .. } => None,
+            Error::SameFile { .. } => None,
+            Error::DestinationInsideSource { .. } => None,
+            Error::Preflight { .. } => None,
+            Error::PathEscape { .. } => None,
             Error::IoExt { source, .. } => Some(source),
             Error::IoExtMulti { source, .. } => Some(source),
             Error::StripPrefix { .. } => None,
@@ -0,0 +1,173 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Operation, Result};
+
+/// A wrapper around [`std::fs::File`] that remembers its path, so that every error says which
+/// file it came from.
+///
+/// The methods of the file return the crate's [`Error`]. The [`Read`], [`Write`] and [`Seek`]
+/// implementations have to return [`io::Error`], their errors are the crate's [`Error`] converted
+/// with `From<Error> for io::Error`. The kind of the error stays the same and the original error
+/// can be taken out again with [`io::Error::into_inner`].
+///
+/// ```no_run
+/// use std::io::Read;
+///
+/// let mut file = more_fs::File::open("config.toml").unwrap();
+/// let mut contents = String::new();
+/// file.read_to_string(&mut contents).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct File {
+    inner: fs::File,
+    path: PathBuf,
+}
+
+impl File {
+    /// Opens a file in read only mode, like [`std::fs::File::open`]
+    pub fn open(path: impl AsRef<Path>) -> Result<File> {
+        let path = path.as_ref();
+
+        let inner = crate::open_file(path)?;
+        Ok(File::from_std(inner, path))
+    }
+
+    /// Opens a file in write only mode, creating or truncating it, like
+    /// [`std::fs::File::create`]
+    pub fn create(path: impl AsRef<Path>) -> Result<File> {
+        let path = path.as_ref();
+
+        let inner = crate::create_file(path)?;
+        Ok(File::from_std(inner, path))
+    }
+
+    /// Opens a file with the given options
+    pub fn open_with(path: impl AsRef<Path>, options: &fs::OpenOptions) -> Result<File> {
+        let path = path.as_ref();
+
+        let inner = options.open(path).map_err(|e| Error::IoExt {
+            source: e,
+            path: path.to_path_buf(),
+            operation: Operation::Open,
+        })?;
+        Ok(File::from_std(inner, path))
+    }
+
+    /// Wraps a file that is already open. `path` is only used for errors
+    pub fn from_std(file: fs::File, path: impl Into<PathBuf>) -> File {
+        File {
+            inner: file,
+            path: path.into(),
+        }
+    }
+
+    /// The path the file was opened with
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The wrapped file
+    pub fn get_ref(&self) -> &fs::File {
+        &self.inner
+    }
+
+    /// The wrapped file, errors of calls on it directly don't carry the path
+    pub fn get_mut(&mut self) -> &mut fs::File {
+        &mut self.inner
+    }
+
+    /// Unwraps the file, forgetting the path
+    pub fn into_std(self) -> fs::File {
+        self.inner
+    }
+
+    /// The same as [`std::fs::File::metadata`]
+    pub fn metadata(&self) -> Result<fs::Metadata> {
+        self.inner
+            .metadata()
+            .map_err(|e| self.error(e, Operation::Metadata))
+    }
+
+    /// The same as [`std::fs::File::sync_all`]
+    pub fn sync_all(&self) -> Result<()> {
+        self.inner
+            .sync_all()
+            .map_err(|e| self.error(e, Operation::Sync))
+    }
+
+    /// The same as [`std::fs::File::sync_data`]
+    pub fn sync_data(&self) -> Result<()> {
+        self.inner
+            .sync_data()
+            .map_err(|e| self.error(e, Operation::Sync))
+    }
+
+    /// The same as [`std::fs::File::set_len`]
+    pub fn set_len(&self, size: u64) -> Result<()> {
+        self.inner
+            .set_len(size)
+            .map_err(|e| self.error(e, Operation::SetLen))
+    }
+
+    /// The same as [`std::fs::File::set_permissions`]
+    pub fn set_permissions(&self, permissions: fs::Permissions) -> Result<()> {
+        self.inner
+            .set_permissions(permissions)
+            .map_err(|e| self.error(e, Operation::SetPermissions))
+    }
+
+    /// The same as [`std::fs::File::try_clone`]
+    pub fn try_clone(&self) -> Result<File> {
+        let inner = self
+            .inner
+            .try_clone()
+            .map_err(|e| self.error(e, Operation::Open))?;
+        Ok(File::from_std(inner, self.path.clone()))
+    }
+
+    fn error(&self, source: io::Error, operation: Operation) -> Error {
+        Error::IoExt {
+            source,
+            path: self.path.clone(),
+            operation,
+        }
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner
+            .read(buf)
+            .map_err(|e| self.error(e, Operation::Read).into())
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner
+            .write(buf)
+            .map_err(|e| self.error(e, Operation::Write).into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner
+            .flush()
+            .map_err(|e| self.error(e, Operation::Flush).into())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner
+            .seek(pos)
+            .map_err(|e| self.error(e, Operation::Seek).into())
+    }
+}
+
+impl From<File> for fs::File {
+    fn from(file: File) -> fs::File {
+        file.into_std()
+    }
+}
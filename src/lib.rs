@@ -13,16 +13,44 @@ more_fs = "2"
 
 This crate adds some new functions that are not in the standard library.
 These new functions are [`copy_dir_all`] and [`move_dir_all`].
+They return a [`Report`] that counts the files, directories and bytes that were copied, lists
+everything that was left out and tells how long every phase took. With the `serde` feature flag the
+report can be serialized.
+With the `rayon` feature [`disk_usage`] tells how big a tree is before it is copied or moved.
 Copying can be done concurrently using [`rayon`] with the `rayon` feature flag (enabled by default).
-Enabling the flag enables the functions [`copy_dir_all_par`] and [`move_dir_all_par`] that are the 
+Enabling the flag enables the functions [`copy_dir_all_par`] and [`move_dir_all_par`] that are the
 same as the prior functions but do things concurrently
 
+Async versions of the functions live in the [`async`] module behind the `tokio` feature flag.
+The recursive functions also have `_in` variants like [`copy_dir_all_in`] that run on any [`FileSystem`],
+for example the in memory [`MemoryFs`]. Checkout the [`vfs`] module to learn more.
+Directories can be copied while compressing every file with the [`compress`] module behind the
+`zstd` or `gzip` feature flags.
+Identical files can be found and turned into links with the [`dedupe`] module behind the `dedupe`
+feature flag.
+With the `notify` feature flag [`mirror`] copies a directory and keeps the copy up to date while
+the source changes.
+The `cli` feature flag builds the `more-fs` binary with `cp`, `mv`, `rm`, `sync`, `du` and `diff`
+commands for use without writing any Rust.
+With the `tracing` feature flag every operation runs in a [`tracing`] span with a span for each of
+its phases, emits a trace event for every entry and an error event when it fails. The operations
+are the recursive functions, the archive, compress, dedupe and mirror functions and the copies,
+moves and recursive removal of `Dir`. The wrappers of the standard library below aren't traced.
+
 # Standard library functions
 
 This crate also includes wrappers for the standard library functions.
 They do the same thing, but have much errors with more context such as
 the path that triggered the error or the operation that was done to
 trigger the error. Checkout the [`Error`] type to learn more about the errors.
+Once a file is open, [`File`] keeps its path around so that reading, writing and seeking fail
+with the same context.
+
+# Untrusted paths
+
+On unix a [`Dir`] handle confines copying, moving and removing to one directory. Paths given to
+it can't leave that directory, not through `..` and not through symlinks, which makes it safe to
+use with paths that came from users.
 
 # Example
 
@@ -61,7 +89,7 @@ use more_fs::copy_dir_all_par;
 
 // from_directory contains from_directory/file1
 // and from_directory/file2
-copy_dir_all_par("from_directory", "to_directory").unwrap()
+copy_dir_all_par("from_directory", "to_directory").unwrap();
 ```
 
 more_fs will first create `to_directory` and copy `from_directory/file1` to `to_directory/file1`.
@@ -94,30 +122,118 @@ while fs_extra performs at around 760ms. The more_fs is faster but not by that m
 both with an average time of 370 ms. You can run `$ cargo bench` to find out more about the benchmarks and get nice plots.
 *Note*: they will take a while because they run git clone on the rust github repo which can take a while.
 
+The `_par` functions walk the tree with their own parallel walker, so many directories are read at
+the same time instead of one after another. The same walker is behind [`remove_dir_all_par`],
+[`disk_usage`] and the [`dedupe`] module.
+
 In short, use [`copy_dir_all_par`] or [`move_dir_all_par`] whenever you can because it will be faster across the board.
 If you don't want to because your directories are extremely small or you don't want to pull in many dependencies from [`rayon`],
 you can turn the `rayon` feature flag off.
+The `_par` functions run on rayon's global thread pool, [`CopyOptions::parallelism`] can give them their
+own pool or pick the amount of threads based on the disks that are involved.
 
 [`criterion`]: https://docs.rs/criterion
 [`fs_extra`]: https://docs.rs/fs_extra
 
 */
 
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub mod archive;
+#[cfg(feature = "tokio")]
+pub mod r#async;
+mod backup;
+#[cfg(any(feature = "zstd", feature = "gzip"))]
+pub mod compress;
+#[cfg(feature = "dedupe")]
+pub mod dedupe;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "emscripten",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "solaris",
+    target_os = "illumos",
+    target_os = "haiku",
+    target_os = "aix",
+    target_os = "hurd",
+    target_os = "redox",
+    target_os = "cygwin"
+))]
+mod dir;
+#[cfg(feature = "rayon")]
+mod du;
+mod durability;
 mod error;
+mod file;
+mod lock;
+#[cfg(feature = "notify")]
+mod mirror;
+mod options;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod preflight;
+mod report;
 #[cfg(test)]
 mod tests;
+mod trace;
 mod utils;
+pub mod vfs;
+#[cfg(feature = "rayon")]
+mod walk;
 
-use std::{fs, path::PathBuf, result};
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Component, PathBuf};
+use std::time::Instant;
 use std::{io, path::Path};
 
-use error::Operation;
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
-use walkdir::WalkDir;
 
-pub use error::{Error, Result};
-use utils::change_dir;
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub use archive::ArchiveOptions;
+#[cfg(feature = "tar")]
+pub use archive::{pack_dir, pack_dir_with, unpack_dir, unpack_dir_with};
+#[cfg(feature = "zip")]
+pub use archive::{unzip_dir, unzip_dir_with, zip_dir, zip_dir_with, ZipCompression};
+#[cfg(all(feature = "zip", feature = "rayon"))]
+pub use archive::{zip_dir_par, zip_dir_par_with};
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "emscripten",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "solaris",
+    target_os = "illumos",
+    target_os = "haiku",
+    target_os = "aix",
+    target_os = "hurd",
+    target_os = "redox",
+    target_os = "cygwin"
+))]
+pub use dir::Dir;
+#[cfg(feature = "rayon")]
+pub use du::{disk_usage, disk_usage_with, DirUsage, DiskUsage, DuOptions};
+pub use error::{Error, Operation, PreflightProblem, Result};
+pub use file::File;
+pub use lock::{FileLock, LockFile, LockMode, LockOptions, LockStyle};
+#[cfg(feature = "notify")]
+pub use mirror::{mirror, mirror_with, Mirror, MirrorOptions};
+#[cfg(feature = "rayon")]
+pub use options::Parallelism;
+pub use options::{Backup, ConflictAction, CopyOptions, Durability, SpecialFiles};
+pub use report::{Conflict, Report, SkipReason, Skipped, SpecialAction, SpecialFile, Timings};
+use utils::{change_dir, parent_dir};
+use vfs::FileType;
+pub use vfs::{FileSystem, MemoryFs, RealFs};
 
 /// helper macro to call asref on all of the identifiers
 macro_rules! as_ref_all {
@@ -128,145 +244,757 @@ macro_rules! as_ref_all {
 
 /// Moves a directory from one place to another recursively. Currently is a wrapper around `copy_dir_all` but removes the
 /// `from` directory
-pub fn move_dir_all(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+pub fn move_dir_all(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+    move_dir_all_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`move_dir_all`] but with options. The copied data is synced according to
+/// [`CopyOptions::durability`] before the `from` directory is removed
+pub fn move_dir_all_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
     as_ref_all!(from, to);
 
-    let copied = copy_dir_all(from, to)?;
-    remove_dir_all(from)?;
+    let span = trace::operation!("move_dir_all", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(from, LockMode::Exclusive)], Some(to))?;
+        check_copy_dir_all(&RealFs, from, to)?;
+        let start = Instant::now();
+        if options.preflight {
+            trace::phase!("preflight").in_scope(|| preflight::preflight(from, to))?;
+        }
+        let preflight = start.elapsed();
+
+        let mut report = move_dir_all_in(&RealFs, from, to, options)?;
+        report.timings.preflight = preflight;
+        Ok(report)
+    })
+}
 
-    Ok(copied)
+/// The same as [`move_dir_all_with`] but on any [`FileSystem`]
+pub fn move_dir_all_in<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
+    as_ref_all!(from, to);
+
+    let span = trace::operation!("move_dir_all_in", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        let mut report = copy_dir_all_in(fs, from, to, options)?;
+        let start = Instant::now();
+        trace::phase!("remove").in_scope(|| remove_moved(fs, from, &report))?;
+        report.timings.remove = start.elapsed();
+
+        Ok(report)
+    })
+}
+
+/// Removes the source of a move, except for the entries that were left out of the copy and the
+/// directories that contain them
+pub(crate) fn remove_moved<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: &Path,
+    report: &Report,
+) -> Result<()> {
+    let kept: Vec<&Path> = report.kept().collect();
+    if kept.is_empty() {
+        return fs.remove_dir_all(from);
+    }
+    remove_except(fs, from, &kept)
+}
+
+fn remove_except<F: FileSystem + ?Sized>(fs: &F, dir: &Path, kept: &[&Path]) -> Result<()> {
+    // the children are collected first so nothing is removed while the walk is still reading
+    let children = fs
+        .walk(dir)
+        .filter(|entry| !matches!(entry, Ok(entry) if entry.depth() != 1))
+        .collect::<Result<Vec<_>>>()?;
+
+    for child in children {
+        let path = child.path();
+        let keep = kept.iter().any(|kept| kept.starts_with(path));
+        match (keep, child.file_type().is_dir()) {
+            (false, true) => fs.remove_dir_all(path)?,
+            (false, false) => fs.remove_file(path)?,
+            (true, true) => remove_except(fs, path, kept)?,
+            (true, false) => continue,
+        }
+        if !keep {
+            trace::entry!(path = %path.display(), "remove");
+        }
+    }
+    Ok(())
 }
 
 /// Moves a directory from one place to another recursively in parallel. Currently is a wrapper around `copy_dir_all` but removes the
 /// `from` directory
-pub fn move_dir_all_par(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+#[cfg(feature = "rayon")]
+pub fn move_dir_all_par(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+    move_dir_all_par_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`move_dir_all_par`] but with options. The copied data is synced according to
+/// [`CopyOptions::durability`] before the `from` directory is removed
+#[cfg(feature = "rayon")]
+pub fn move_dir_all_par_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
     as_ref_all!(from, to);
 
-    copy_dir_all_par(from, to)?;
-    remove_dir_all(from)?;
+    let span = trace::operation!("move_dir_all_par", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(from, LockMode::Exclusive)], Some(to))?;
+        check_copy_dir_all(&RealFs, from, to)?;
+        let mut report = copy_dir_all_par_with(from, to, &options.without_lock())?;
+        let start = Instant::now();
+        trace::phase!("remove").in_scope(|| {
+            if report.kept().next().is_none() {
+                remove_dir_all_par(from)
+            } else {
+                remove_moved(&RealFs, from, &report)
+            }
+        })?;
+        report.timings.remove = start.elapsed();
 
-    Ok(())
+        Ok(report)
+    })
 }
 
 /// Moves a file from one place to another. Currently is a wrapper around `copy` but removes the
 /// `from` argument
 pub fn move_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+    move_file_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`move_file`] but with options. The copied file is synced according to
+/// [`CopyOptions::durability`] before `from` is removed
+pub fn move_file_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    move_file_in(&RealFs, from, to, options)
+}
+
+/// The same as [`move_file_with`] but on any [`FileSystem`]
+pub fn move_file_in<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
     as_ref_all!(from, to);
 
-    let amount = copy_create(from, to)?;
-    remove_file(from)?;
-    Ok(amount)
+    let span = trace::operation!("move_file", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        let amount = copy_create_in(fs, from, to, options)?;
+        fs.remove_file(from)?;
+        Ok(amount)
+    })
 }
 
-fn check_path_copy_dir_all(path: impl AsRef<Path>) -> Result<()> {
+fn check_path_copy_dir_all<F: FileSystem + ?Sized>(fs: &F, path: impl AsRef<Path>) -> Result<()> {
     let path = path.as_ref();
 
-    if !path.exists() {
-        return Err(Error::IoExt {
+    match fs.metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(Error::NotDirectory {
+            path: path.to_path_buf(),
+        }),
+        Err(e) if e.io_error_kind() == io::ErrorKind::NotFound => Err(Error::IoExt {
             source: io::Error::new(io::ErrorKind::NotFound, ""),
             path: path.to_path_buf(),
             operation: Operation::CopyDirAll,
-        });
+        }),
+        Err(e) => Err(e),
     }
+}
 
-    if !path.is_dir() {
-        return Err(Error::NotDirectory {
-            path: path.to_path_buf(),
+/// Checks that `from` is a directory and that `to` is not inside of it. Symlinks are resolved,
+/// `to` doesn't have to exist
+fn check_copy_dir_all<F: FileSystem + ?Sized>(fs: &F, from: &Path, to: &Path) -> Result<()> {
+    check_path_copy_dir_all(fs, from)?;
+
+    if canonicalize_missing(fs, to)?.starts_with(fs.canonicalize(from)?) {
+        return Err(Error::DestinationInsideSource {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
         });
     }
-
     Ok(())
 }
 
-fn copy_or_create(
-    file_type: fs::FileType,
+/// Canonicalizes the longest existing prefix of `path` and appends the rest lexically
+fn canonicalize_missing<F: FileSystem + ?Sized>(fs: &F, path: &Path) -> Result<PathBuf> {
+    let components: Vec<_> = path.components().collect();
+
+    for existing in (0..=components.len()).rev() {
+        let prefix: PathBuf = components[..existing].iter().collect();
+        let prefix = if prefix.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            prefix
+        };
+
+        match fs.canonicalize(&prefix) {
+            Ok(mut resolved) => {
+                for component in &components[existing..] {
+                    match component {
+                        Component::ParentDir => {
+                            resolved.pop();
+                        }
+                        Component::CurDir => (),
+                        component => resolved.push(component),
+                    }
+                }
+                return Ok(resolved);
+            }
+            Err(e) if e.io_error_kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(path.to_path_buf())
+}
+
+fn copy_or_create<F: FileSystem + ?Sized>(
+    fs: &F,
+    file_type: FileType,
     from: impl AsRef<Path>,
     to: impl AsRef<Path>,
-) -> Result<u64> {
-    let amount = if file_type.is_dir() {
-        create_dir(to)?;
-        0
+    options: &CopyOptions,
+    report: &mut Report,
+) -> Result<()> {
+    as_ref_all!(from, to);
+
+    if file_type.is_dir() {
+        create_dir_merging(fs, to, options)?;
+        trace::entry!(path = %to.display(), "mkdir");
+        report.dirs += 1;
+        return Ok(());
+    }
+
+    let to = match resolve_conflict(fs, from, to, options, report)? {
+        Some(to) => to,
+        None => return Ok(()),
+    };
+    if file_type.is_special() {
+        copy_special(fs, file_type, from, to, options, report)?;
     } else {
         // the iterator will always iterate over parent directories first so we don't need to
         // use copy_create
-        copy(from, to)?
+        let bytes = copy_file(fs, from, &to, options)?;
+        report.bytes += bytes;
+        match file_type {
+            FileType::Symlink => report.symlinks += 1,
+            _ => report.files += 1,
+        }
+        trace::entry!(from = %from.display(), to = %to.display(), bytes, "copy");
+        if let Some(hook) = &options.on_progress {
+            hook(from, bytes);
+        }
+    }
+    Ok(())
+}
+
+/// Creates a directory of a recursive copy. With a conflict hook the copy is merged into
+/// directories that already exist
+fn create_dir_merging<F: FileSystem + ?Sized>(
+    fs: &F,
+    path: &Path,
+    options: &CopyOptions,
+) -> Result<()> {
+    match fs.create_dir(path) {
+        Err(_)
+            if options.on_conflict.is_some()
+                && fs
+                    .metadata(path)
+                    .is_ok_and(|metadata| metadata.file_type().is_dir()) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Asks the hook set with [`CopyOptions::on_conflict`] what to do if `to` already exists.
+/// Returns where the file is copied to or `None` if it is not copied
+fn resolve_conflict<'a, F: FileSystem + ?Sized>(
+    fs: &F,
+    from: &Path,
+    to: &'a Path,
+    options: &CopyOptions,
+    report: &mut Report,
+) -> Result<Option<Cow<'a, Path>>> {
+    let hook = match &options.on_conflict {
+        Some(hook) => hook,
+        None => return Ok(Some(Cow::Borrowed(to))),
     };
+    let existing = match fs.metadata(to) {
+        Ok(existing) => existing,
+        Err(_) => return Ok(Some(Cow::Borrowed(to))),
+    };
+    let source = fs.metadata(from)?;
+
+    let action = hook(to, &source, &existing);
+    let target = match action {
+        ConflictAction::Overwrite => Some(Cow::Borrowed(to)),
+        ConflictAction::Skip => None,
+        // without both times it is not known which one is newer, so the existing file is kept
+        ConflictAction::KeepNewer => match (source.modified(), existing.modified()) {
+            (Some(source), Some(existing)) if source > existing => Some(Cow::Borrowed(to)),
+            _ => None,
+        },
+        ConflictAction::Rename => Some(Cow::Owned(free_name(fs, to))),
+        ConflictAction::Abort => {
+            return Err(Error::Conflict {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+            })
+        }
+    };
+
+    trace::entry!(from = %from.display(), to = %to.display(), ?action, "conflict");
+    if target.is_none() {
+        report.skipped.push(Skipped {
+            path: from.to_path_buf(),
+            reason: SkipReason::Conflict,
+        });
+    }
+    report.conflicts.push(Conflict {
+        path: from.to_path_buf(),
+        to: target.as_deref().unwrap_or(to).to_path_buf(),
+        action,
+        copied: target.is_some(),
+    });
+    Ok(target)
+}
+
+/// Finds the first name of the form `name (1).ext` next to `path` that does not exist
+fn free_name<F: FileSystem + ?Sized>(fs: &F, path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    let extension = path.extension();
+
+    (1..)
+        .map(|n| {
+            let mut name = stem.to_os_string();
+            name.push(format!(" ({})", n));
+            if let Some(extension) = extension {
+                name.push(".");
+                name.push(extension);
+            }
+            path.with_file_name(name)
+        })
+        .find(|candidate| fs.metadata(candidate).is_err())
+        .unwrap()
+}
+
+/// Handles a fifo, socket or device file according to [`CopyOptions::special_files`]
+fn copy_special<F: FileSystem + ?Sized>(
+    fs: &F,
+    file_type: FileType,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+    report: &mut Report,
+) -> Result<()> {
+    as_ref_all!(from, to);
+
+    let action = match options.special_files {
+        SpecialFiles::Error => {
+            return Err(Error::SpecialFile {
+                path: from.to_path_buf(),
+                file_type,
+            })
+        }
+        SpecialFiles::Skip => SpecialAction::Skipped,
+        SpecialFiles::Recreate => {
+            backup::backup(fs, from, to, options)?;
+            match fs.create_special(from, to, file_type) {
+                Ok(()) => SpecialAction::Recreated,
+                // sockets can't be recreated and device files need privileges
+                Err(e)
+                    if matches!(
+                        e.io_error_kind(),
+                        io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+                    ) =>
+                {
+                    SpecialAction::Skipped
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    };
+
+    if action == SpecialAction::Skipped {
+        report.skipped.push(Skipped {
+            path: from.to_path_buf(),
+            reason: SkipReason::SpecialFile,
+        });
+    }
+    trace::entry!(path = %from.display(), %file_type, ?action, "special file");
+    report.special_files.push(SpecialFile {
+        path: from.to_path_buf(),
+        file_type,
+        action,
+    });
+    Ok(())
+}
+
+/// Copies a single file inside of a recursive copy. Unlike [`copy_with`] this never syncs the
+/// parent directory, the recursive functions sync all directories once at the end
+fn copy_file<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    as_ref_all!(from, to);
+
+    backup::backup(fs, from, to, options)?;
+    let amount = fs.copy(from, to)?;
+    if options.durability.sync_files() {
+        fs.sync_file(to)?;
+    }
     Ok(amount)
 }
 
 /// Recursively copies all contents of the directory to another directory. Will create the new
 /// directory if it does not exist
-pub fn copy_dir_all(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+pub fn copy_dir_all(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+    copy_dir_all_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`copy_dir_all`] but with options
+pub fn copy_dir_all_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
+    as_ref_all!(from, to);
+
+    let span = trace::operation!("copy_dir_all", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(from, LockMode::Shared)], Some(to))?;
+        check_copy_dir_all(&RealFs, from, to)?;
+        let start = Instant::now();
+        if options.preflight {
+            trace::phase!("preflight").in_scope(|| preflight::preflight(from, to))?;
+        }
+        let preflight = start.elapsed();
+
+        let mut report = copy_tree(&RealFs, from, to, options)?;
+        report.timings.preflight = preflight;
+        Ok(report)
+    })
+}
+
+/// The same as [`copy_dir_all_with`] but on any [`FileSystem`]
+pub fn copy_dir_all_in<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
     as_ref_all!(from, to);
 
-    check_path_copy_dir_all(from)?;
+    let span = trace::operation!("copy_dir_all_in", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        check_copy_dir_all(fs, from, to)?;
+        copy_tree(fs, from, to, options)
+    })
+}
 
-    let walkdir = WalkDir::new(from);
+/// Copies the tree once it was checked. The permissions of the directories are copied after
+/// everything else, so a read only directory never blocks the copy of its contents
+fn copy_tree<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: &Path,
+    to: &Path,
+    options: &CopyOptions,
+) -> Result<Report> {
+    let start = Instant::now();
+    let mut report = Report::default();
+    let mut dirs = Vec::new();
+    trace::phase!("copy").in_scope(|| -> Result<()> {
+        for entry in fs.walk(from) {
+            let entry = entry?;
+            let path = entry.path();
+            let new_path = change_dir(from, to, path)?;
+
+            copy_or_create(fs, entry.file_type(), path, &new_path, options, &mut report)?;
+
+            if entry.file_type().is_dir() {
+                dirs.push((path.to_path_buf(), new_path));
+            }
+        }
+        Ok(())
+    })?;
 
-    let mut copied = 0;
-    for entry in walkdir {
-        let entry = entry?;
-        let path = entry.path();
-        let new_path = change_dir(from, to, &path)?;
+    report.timings.copy = start.elapsed();
 
-        copied += copy_or_create(entry.file_type(), path, new_path)?;
+    let start = Instant::now();
+    trace::phase!("finish").in_scope(|| finish_dirs(fs, to, &dirs, options))?;
+    report.timings.finish = start.elapsed();
+
+    Ok(report)
+}
+
+/// Copies the permissions of the directories and syncs them. The directories are expected in
+/// the order they were walked, so they are finished from the deepest up
+fn finish_dirs<F: FileSystem + ?Sized>(
+    fs: &F,
+    to: &Path,
+    dirs: &[(PathBuf, PathBuf)],
+    options: &CopyOptions,
+) -> Result<()> {
+    for (from, to) in dirs.iter().rev() {
+        fs.copy_permissions(from, to)?;
     }
+    sync_dirs(fs, to, dirs.iter().rev().map(|(_, to)| to), options)
+}
 
-    Ok(copied)
+/// Syncs the created directories. The directories are expected to be in the order of deepest
+/// first, the parent of the destination is synced last so the whole tree becomes visible only
+/// after all of its contents are durable
+fn sync_dirs<F: FileSystem + ?Sized, P: AsRef<Path>>(
+    fs: &F,
+    to: &Path,
+    dirs: impl Iterator<Item = P>,
+    options: &CopyOptions,
+) -> Result<()> {
+    if !options.durability.sync_dirs() {
+        return Ok(());
+    }
+
+    for dir in dirs {
+        fs.sync_dir(dir.as_ref())?;
+    }
+    fs.sync_dir(parent_dir(to))
 }
 
+/// An entry of the source tree together with where it goes in the destination
 #[cfg(feature = "rayon")]
-fn copy_or_create_par(
-    file_type: fs::FileType,
-    from: impl AsRef<Path>,
-    to: impl AsRef<Path>,
-) -> Result<()> {
-    if file_type.is_dir() {
-        create_dir_all(to)?;
-    } else {
-        // the iterator will always iterate over parent directories first so we don't need to
-        // use copy_create
-        copy_create(from, to)?;
+struct Planned {
+    file_type: FileType,
+    from: PathBuf,
+    to: PathBuf,
+    depth: usize,
+}
+
+/// The entries of the source tree that [`copy_dir_all_par_with`] found while creating the
+/// directories of the destination
+#[cfg(feature = "rayon")]
+#[derive(Default)]
+struct Skeleton {
+    dirs: Vec<Planned>,
+    files: Vec<Planned>,
+}
+
+#[cfg(feature = "rayon")]
+impl Skeleton {
+    fn merge(mut self, other: Skeleton) -> Skeleton {
+        self.dirs.extend(other.dirs);
+        self.files.extend(other.files);
+        self
     }
-    Ok(())
 }
 
 /// Recursively copies all contents of the directory to another directory in parallel. Will create the new
 /// directory if it does not exist.
 #[cfg(feature = "rayon")]
-pub fn copy_dir_all_par(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+pub fn copy_dir_all_par(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Report> {
+    copy_dir_all_par_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`copy_dir_all_par`] but with options.
+///
+/// The copy runs in three passes. The whole directory tree is created first while the files are
+/// collected, then the files are copied in parallel without ever checking for their parents.
+/// Finally the permissions of the directories are copied from the deepest directories up, so a
+/// read only directory never blocks the copy of its contents.
+#[cfg(feature = "rayon")]
+pub fn copy_dir_all_par_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<Report> {
     as_ref_all!(from, to);
 
-    check_path_copy_dir_all(from)?;
+    let span = trace::operation!("copy_dir_all_par", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(from, LockMode::Shared)], Some(to))?;
+        check_copy_dir_all(&RealFs, from, to)?;
+        let start = Instant::now();
+        if options.preflight {
+            trace::phase!("preflight").in_scope(|| preflight::preflight(from, to))?;
+        }
+        let preflight = start.elapsed();
+
+        parallel::install(&options.parallelism, from, to, || {
+            let start = Instant::now();
+            let Skeleton { mut dirs, files } =
+                trace::phase!("walk").in_scope(|| create_skeleton(from, to))?;
+            let walk = start.elapsed();
+
+            let start = Instant::now();
+            let phase = trace::phase!("copy");
+            let mut report = files
+                .into_par_iter()
+                .try_fold(Report::default, |mut report, file| -> Result<Report> {
+                    phase.in_scope(|| {
+                        copy_or_create(
+                            &RealFs,
+                            file.file_type,
+                            &file.from,
+                            &file.to,
+                            options,
+                            &mut report,
+                        )
+                    })?;
+                    Ok(report)
+                })
+                .try_reduce(Report::default, |a, b| Ok(a.merge(b)))?;
+            let copy = start.elapsed();
+
+            let start = Instant::now();
+            let phase = trace::phase!("finish");
+            // the directories are collected in any order, so sort them to finish the deepest first
+            dirs.sort_unstable_by_key(|dir| std::cmp::Reverse(dir.depth));
+            for level in dirs.chunk_by(|a, b| a.depth == b.depth) {
+                level.par_iter().try_for_each(|dir| {
+                    phase.in_scope(|| finish_dir(&dir.from, &dir.to, options))
+                })?;
+            }
+            if options.durability.sync_dirs() {
+                RealFs.sync_dir(parent_dir(to))?;
+            }
+
+            report.dirs = dirs.len() as u64;
+            report.timings = Timings {
+                preflight,
+                walk,
+                copy,
+                finish: start.elapsed(),
+                ..Timings::default()
+            };
+            Ok(report)
+        })
+    })
+}
 
-    WalkDir::new(from)
-        .into_iter()
-        .par_bridge()
-        .try_for_each(|entry| -> Result<()> {
-            let entry = entry?;
-            let path = entry.path();
-            let new_path = change_dir(from, to, &path)?;
-            let file_type = entry.file_type();
+/// Creates every directory of `from` below `to` and returns all entries. The walker visits a
+/// directory before its contents, so the parent of a directory always exists already
+#[cfg(feature = "rayon")]
+fn create_skeleton(from: &Path, to: &Path) -> Result<Skeleton> {
+    walk::walk_par(
+        from,
+        |entry, skeleton: &mut Skeleton| {
+            let planned = Planned {
+                file_type: entry.file_type,
+                from: entry.path.clone(),
+                to: change_dir(from, to, &entry.path)?,
+                depth: entry.depth,
+            };
+
+            if entry.file_type.is_dir() {
+                create_dir_all(&planned.to)?;
+                trace::entry!(path = %planned.to.display(), "mkdir");
+                skeleton.dirs.push(planned);
+                Ok(true)
+            } else {
+                skeleton.files.push(planned);
+                Ok(false)
+            }
+        },
+        Skeleton::merge,
+    )
+}
 
-            copy_or_create_par(file_type, path, new_path)?;
+/// Copies the permissions of a directory once everything inside of it was copied and syncs it
+#[cfg(feature = "rayon")]
+fn finish_dir(from: &Path, to: &Path, options: &CopyOptions) -> Result<()> {
+    RealFs.copy_permissions(from, to)?;
 
-            Ok(())
-        })?;
+    if options.durability.sync_dirs() {
+        RealFs.sync_dir(to)?;
+    }
     Ok(())
 }
 
 /// A wrapper around `copy` that will also create the parent directories of the file if they do not
 /// exist
 pub fn copy_create(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
+    copy_create_with(from, to, &CopyOptions::new())
+}
+
+/// The same as [`copy_create`] but with options
+pub fn copy_create_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    copy_create_in(&RealFs, from, to, options)
+}
+
+/// The same as [`copy_create_with`] but on any [`FileSystem`]
+pub fn copy_create_in<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
     as_ref_all!(from, to);
 
-    if let Some(parent) = to.parent() {
-        if !parent.exists() {
-            create_dir_all(parent)?;
+    let span = trace::operation!("copy_create", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        create_parent(fs, to)?;
+        copy_synced(fs, from, to, options)
+    })
+}
+
+fn create_parent<F: FileSystem + ?Sized>(fs: &F, path: impl AsRef<Path>) -> Result<()> {
+    if let Some(parent) = path.as_ref().parent() {
+        if fs.metadata(parent).is_err() {
+            fs.create_dir_all(parent)?;
         }
     }
+    Ok(())
+}
 
-    copy(from, to)
+/// The same as [`copy`] but with options. With [`Durability::Files`] the new file is synced
+/// before returning, [`Durability::FilesAndDirs`] also syncs the directory containing it
+pub fn copy_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    as_ref_all!(from, to);
+
+    let span = trace::operation!("copy", from = %from.display(), to = %to.display());
+    trace::traced(span, || copy_synced(&RealFs, from, to, options))
+}
+
+fn copy_synced<F: FileSystem + ?Sized>(
+    fs: &F,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &CopyOptions,
+) -> Result<u64> {
+    as_ref_all!(from, to);
+
+    let amount = copy_file(fs, from, to, options)?;
+    if options.durability.sync_dirs() {
+        fs.sync_dir(parent_dir(to))?;
+    }
+
+    Ok(amount)
 }
 
 /// A wrapper for the standard library's `copy`. Will fail with a custom error that
@@ -274,6 +1002,13 @@ pub fn copy_create(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64>
 pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<u64> {
     as_ref_all!(from, to);
 
+    if utils::same_file(from, to) {
+        return Err(Error::SameFile {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+    }
+
     fs::copy(from, to).map_err(|e| Error::IoExtMulti {
         source: e,
         from: from.to_path_buf(),
@@ -306,6 +1041,89 @@ pub fn remove_dir_all(path: impl AsRef<Path>) -> Result<()> {
     })
 }
 
+/// The same as [`remove_dir_all`] but with options, only [`CopyOptions::lock`] is used
+pub fn remove_dir_all_with(path: impl AsRef<Path>, options: &CopyOptions) -> Result<()> {
+    as_ref_all!(path);
+
+    let span = trace::operation!("remove_dir_all", path = %path.display());
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(path, LockMode::Exclusive)], None)?;
+        remove_dir_all(path)
+    })
+}
+
+/// The same as [`remove_dir_all`] but the tree is walked and its files are removed in parallel.
+/// The directories are removed afterwards, deepest first. A symlink is removed itself and not
+/// followed
+#[cfg(feature = "rayon")]
+pub fn remove_dir_all_par(path: impl AsRef<Path>) -> Result<()> {
+    as_ref_all!(path);
+
+    let span = trace::operation!("remove_dir_all_par", path = %path.display());
+    trace::traced(span, || {
+        let metadata = fs::symlink_metadata(path).map_err(|e| Error::IoExt {
+            source: e,
+            path: path.to_path_buf(),
+            operation: Operation::Metadata,
+        })?;
+        if !metadata.is_dir() {
+            return remove_dir_all(path);
+        }
+
+        // the files are removed while walking, the directories once they are empty
+        let mut dirs = trace::phase!("walk").in_scope(|| {
+            walk::walk_par(
+                path,
+                |entry, dirs: &mut Vec<(usize, PathBuf)>| {
+                    if entry.file_type.is_dir() {
+                        dirs.push((entry.depth, entry.path.clone()));
+                        Ok(true)
+                    } else {
+                        remove_file(&entry.path)?;
+                        trace::entry!(path = %entry.path.display(), "remove");
+                        Ok(false)
+                    }
+                },
+                |mut a, b| {
+                    a.extend(b);
+                    a
+                },
+            )
+        })?;
+
+        let phase = trace::phase!("remove");
+        // every directory of one level can be removed at the same time once the deeper ones are gone
+        dirs.sort_unstable_by_key(|(depth, _)| std::cmp::Reverse(*depth));
+        for level in dirs.chunk_by(|a, b| a.0 == b.0) {
+            level.par_iter().try_for_each(|(_, dir)| {
+                phase.in_scope(|| -> Result<()> {
+                    fs::remove_dir(dir).map_err(|e| Error::IoExt {
+                        source: e,
+                        path: dir.clone(),
+                        operation: Operation::RemoveDirAll,
+                    })?;
+                    trace::entry!(path = %dir.display(), "remove");
+                    Ok(())
+                })
+            })?;
+        }
+
+        Ok(())
+    })
+}
+
+/// The same as [`remove_dir_all_par`] but with options, only [`CopyOptions::lock`] is used
+#[cfg(feature = "rayon")]
+pub fn remove_dir_all_par_with(path: impl AsRef<Path>, options: &CopyOptions) -> Result<()> {
+    as_ref_all!(path);
+
+    let span = trace::operation!("remove_dir_all_par", path = %path.display());
+    trace::traced(span, || {
+        let _lock = lock::lock_tree(options, &[(path, LockMode::Exclusive)], None)?;
+        remove_dir_all_par(path)
+    })
+}
+
 /// A wrapper for the standard library's [`fs::create_dir_all`]. Will fail with a custom error that
 /// includes the source error, path, and operation. Checkout [`fs::create_dir`] to see the
 /// differences between this function and [`create_dir`]
@@ -330,3 +1148,241 @@ pub fn create_dir(path: impl AsRef<Path>) -> Result<()> {
         operation: Operation::Create,
     })
 }
+
+/// A wrapper for the standard library's [`fs::remove_dir`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn remove_dir(path: impl AsRef<Path>) -> Result<()> {
+    as_ref_all!(path);
+
+    fs::remove_dir(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::RemoveDir,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::rename`]. Will fail with a custom error that
+/// includes the source error, both paths, and operation
+pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+    as_ref_all!(from, to);
+
+    fs::rename(from, to).map_err(|e| Error::IoExtMulti {
+        source: e,
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+        operation: Operation::Rename,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::read`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    as_ref_all!(path);
+
+    fs::read(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::Read,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::read_to_string`]. Will fail with a custom error
+/// that includes the source error, path, and operation
+pub fn read_to_string(path: impl AsRef<Path>) -> Result<String> {
+    as_ref_all!(path);
+
+    fs::read_to_string(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::ReadToString,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::write`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    as_ref_all!(path);
+
+    fs::write(path, contents).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::Write,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::metadata`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn metadata(path: impl AsRef<Path>) -> Result<fs::Metadata> {
+    as_ref_all!(path);
+
+    fs::metadata(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::Metadata,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::symlink_metadata`]. Will fail with a custom error
+/// that includes the source error, path, and operation
+pub fn symlink_metadata(path: impl AsRef<Path>) -> Result<fs::Metadata> {
+    as_ref_all!(path);
+
+    fs::symlink_metadata(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::SymlinkMetadata,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::read_dir`]. Will fail with a custom error that
+/// includes the source error, path, and operation. The errors of the entries carry the path of
+/// the directory as well
+pub fn read_dir(path: impl AsRef<Path>) -> Result<ReadDir> {
+    as_ref_all!(path);
+
+    let inner = fs::read_dir(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::ReadDir,
+    })?;
+    Ok(ReadDir {
+        inner,
+        path: path.to_path_buf(),
+    })
+}
+
+/// The iterator returned by [`read_dir`]
+#[derive(Debug)]
+pub struct ReadDir {
+    inner: fs::ReadDir,
+    path: PathBuf,
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<fs::DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|entry| {
+            entry.map_err(|e| Error::IoExt {
+                source: e,
+                path: self.path.clone(),
+                operation: Operation::ReadDir,
+            })
+        })
+    }
+}
+
+/// A wrapper for the standard library's [`fs::read_link`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn read_link(path: impl AsRef<Path>) -> Result<PathBuf> {
+    as_ref_all!(path);
+
+    fs::read_link(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::ReadLink,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::canonicalize`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn canonicalize(path: impl AsRef<Path>) -> Result<PathBuf> {
+    as_ref_all!(path);
+
+    fs::canonicalize(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::Canonicalize,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::hard_link`]. Will fail with a custom error that
+/// includes the source error, both paths, and operation
+pub fn hard_link(original: impl AsRef<Path>, link: impl AsRef<Path>) -> Result<()> {
+    as_ref_all!(original, link);
+
+    fs::hard_link(original, link).map_err(|e| Error::IoExtMulti {
+        source: e,
+        from: original.to_path_buf(),
+        to: link.to_path_buf(),
+        operation: Operation::HardLink,
+    })
+}
+
+/// A wrapper for the standard library's [`std::os::unix::fs::symlink`]. Will fail with a custom
+/// error that includes the source error, both paths, and operation
+#[cfg(unix)]
+pub fn symlink(original: impl AsRef<Path>, link: impl AsRef<Path>) -> Result<()> {
+    as_ref_all!(original, link);
+
+    std::os::unix::fs::symlink(original, link).map_err(|e| Error::IoExtMulti {
+        source: e,
+        from: original.to_path_buf(),
+        to: link.to_path_buf(),
+        operation: Operation::Symlink,
+    })
+}
+
+/// A wrapper for the standard library's [`std::os::windows::fs::symlink_file`]. Will fail with a
+/// custom error that includes the source error, both paths, and operation
+#[cfg(windows)]
+pub fn symlink_file(original: impl AsRef<Path>, link: impl AsRef<Path>) -> Result<()> {
+    as_ref_all!(original, link);
+
+    std::os::windows::fs::symlink_file(original, link).map_err(|e| Error::IoExtMulti {
+        source: e,
+        from: original.to_path_buf(),
+        to: link.to_path_buf(),
+        operation: Operation::Symlink,
+    })
+}
+
+/// A wrapper for the standard library's [`std::os::windows::fs::symlink_dir`]. Will fail with a
+/// custom error that includes the source error, both paths, and operation
+#[cfg(windows)]
+pub fn symlink_dir(original: impl AsRef<Path>, link: impl AsRef<Path>) -> Result<()> {
+    as_ref_all!(original, link);
+
+    std::os::windows::fs::symlink_dir(original, link).map_err(|e| Error::IoExtMulti {
+        source: e,
+        from: original.to_path_buf(),
+        to: link.to_path_buf(),
+        operation: Operation::Symlink,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::set_permissions`]. Will fail with a custom error
+/// that includes the source error, path, and operation
+pub fn set_permissions(path: impl AsRef<Path>, permissions: fs::Permissions) -> Result<()> {
+    as_ref_all!(path);
+
+    fs::set_permissions(path, permissions).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::SetPermissions,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::File::open`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn open_file(path: impl AsRef<Path>) -> Result<fs::File> {
+    as_ref_all!(path);
+
+    fs::File::open(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::Open,
+    })
+}
+
+/// A wrapper for the standard library's [`fs::File::create`]. Will fail with a custom error that
+/// includes the source error, path, and operation
+pub fn create_file(path: impl AsRef<Path>) -> Result<fs::File> {
+    as_ref_all!(path);
+
+    fs::File::create(path).map_err(|e| Error::IoExt {
+        source: e,
+        path: path.to_path_buf(),
+        operation: Operation::CreateFile,
+    })
+}
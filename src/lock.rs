@@ -0,0 +1,482 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Operation, Result};
+use crate::options::CopyOptions;
+use crate::trace;
+
+/// The longest pause between two attempts to take a lock that is held by someone else
+const MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Whether a lock can be held by others at the same time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LockMode {
+    /// Any amount of shared locks can be held at once, but not together with an exclusive one
+    Shared,
+    /// Only one exclusive lock can be held at once
+    #[default]
+    Exclusive,
+}
+
+/// Which kind of advisory lock is taken. Advisory locks only keep out processes that lock the
+/// same file as well, everyone else can still read and write it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockStyle {
+    /// `flock` on unix and `LockFileEx` on windows. The lock belongs to the open file, so it
+    /// also keeps out other handles of the same process
+    #[default]
+    Flock,
+    /// Open file description locks taken with `fcntl`. They behave like `flock` but are
+    /// forwarded to the server on NFS. Only available on linux
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Ofd,
+}
+
+/// Options for taking advisory locks.
+///
+/// Like [`CopyOptions`] every setter takes `&mut self` so they can be chained.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use more_fs::{LockMode, LockOptions};
+///
+/// let lock = LockOptions::new()
+///     .mode(LockMode::Shared)
+///     .timeout(Duration::from_secs(5))
+///     .lock("data.lock")
+///     .unwrap();
+/// // read the data while nobody can take an exclusive lock
+/// drop(lock);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LockOptions {
+    mode: LockMode,
+    style: LockStyle,
+    timeout: Option<Duration>,
+}
+
+impl LockOptions {
+    /// Creates the default options, an exclusive [`LockStyle::Flock`] that waits as long as it
+    /// takes
+    pub fn new() -> LockOptions {
+        LockOptions::default()
+    }
+
+    /// Sets whether the lock is shared or exclusive. Checkout [`LockMode`]
+    pub fn mode(&mut self, mode: LockMode) -> &mut LockOptions {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets which kind of lock is taken. Checkout [`LockStyle`]
+    pub fn style(&mut self, style: LockStyle) -> &mut LockOptions {
+        self.style = style;
+        self
+    }
+
+    /// Sets how long to wait for a lock that someone else holds before failing with
+    /// [`Error::Locked`]. A timeout of zero tries only once. Without a timeout the lock is waited
+    /// for as long as it takes
+    pub fn timeout(&mut self, timeout: Duration) -> &mut LockOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Locks the file at `path`, it is created if it doesn't exist. The lock is released when
+    /// the returned [`FileLock`] is dropped
+    pub fn lock(&self, path: impl AsRef<Path>) -> Result<FileLock> {
+        let path = path.as_ref();
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| io_ext(e, path, Operation::Open))?;
+        self.lock_opened(file, path)
+    }
+
+    /// Locks a file that is already open. Exclusive [`LockStyle::Ofd`] locks need a file that
+    /// was opened for writing, shared ones a file that was opened for reading. `path` is only
+    /// used for errors
+    pub fn lock_opened(&self, file: fs::File, path: impl Into<PathBuf>) -> Result<FileLock> {
+        let lock = FileLock {
+            file,
+            path: path.into(),
+            style: self.style,
+        };
+
+        let acquired = match self.timeout {
+            // the kernel wakes us up as soon as the lock is free
+            None => lock.lock(self.mode).map(|()| true),
+            Some(timeout) => poll(Some(timeout), || lock.try_lock(self.mode)),
+        };
+        match acquired {
+            Ok(true) => Ok(lock),
+            Ok(false) => Err(Error::Locked { path: lock.path }),
+            Err(e) => Err(io_ext(e, &lock.path, Operation::Lock)),
+        }
+    }
+
+    /// Creates a lock file at `path` that contains the id of this process, checkout
+    /// [`LockFile`]. Only the timeout of the options is used
+    pub fn pid_file(&self, path: impl AsRef<Path>) -> Result<LockFile> {
+        let path = path.as_ref();
+
+        let mut created = None;
+        let acquired = poll(self.timeout, || {
+            created = LockFile::try_create(path)?;
+            Ok(created.is_some())
+        })
+        .map_err(|e| io_ext(e, path, Operation::Lock))?;
+
+        match created {
+            Some(lock) if acquired => Ok(lock),
+            _ => Err(Error::Locked {
+                path: path.to_path_buf(),
+            }),
+        }
+    }
+}
+
+/// Calls `try_lock` until it succeeds or the timeout runs out, pausing a little longer after
+/// every attempt. Returns whether the lock was taken
+fn poll(
+    timeout: Option<Duration>,
+    mut try_lock: impl FnMut() -> io::Result<bool>,
+) -> io::Result<bool> {
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+
+    let mut backoff = Duration::from_millis(1);
+    loop {
+        if try_lock()? {
+            return Ok(true);
+        }
+
+        let pause = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => backoff.min(left),
+                _ => return Ok(false),
+            },
+            None => backoff,
+        };
+        thread::sleep(pause);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// An advisory lock on an open file, created by [`LockOptions::lock`]. The lock is released
+/// when it is dropped
+#[derive(Debug)]
+pub struct FileLock {
+    file: fs::File,
+    path: PathBuf,
+    style: LockStyle,
+}
+
+impl FileLock {
+    /// The path of the locked file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The locked file
+    pub fn file(&self) -> &fs::File {
+        &self.file
+    }
+
+    /// Releases the lock. Dropping the lock does the same but ignores errors
+    pub fn unlock(self) -> Result<()> {
+        let result = match self.style {
+            LockStyle::Flock => self.file.unlock(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            LockStyle::Ofd => ofd::unlock(&self.file),
+        };
+        result.map_err(|e| io_ext(e, &self.path, Operation::Unlock))
+    }
+
+    fn lock(&self, mode: LockMode) -> io::Result<()> {
+        match (self.style, mode) {
+            (LockStyle::Flock, LockMode::Shared) => self.file.lock_shared(),
+            (LockStyle::Flock, LockMode::Exclusive) => self.file.lock(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            (LockStyle::Ofd, mode) => ofd::lock(&self.file, mode),
+        }
+    }
+
+    /// Returns false if someone else holds the lock
+    fn try_lock(&self, mode: LockMode) -> io::Result<bool> {
+        let result = match (self.style, mode) {
+            (LockStyle::Flock, LockMode::Shared) => self.file.try_lock_shared(),
+            (LockStyle::Flock, LockMode::Exclusive) => self.file.try_lock(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            (LockStyle::Ofd, mode) => return ofd::try_lock(&self.file, mode),
+        };
+        match result {
+            Ok(()) => Ok(true),
+            Err(fs::TryLockError::WouldBlock) => Ok(false),
+            Err(fs::TryLockError::Error(e)) => Err(e),
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod ofd {
+    use std::fs;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    use libc::{c_int, c_short};
+
+    use super::LockMode;
+
+    pub(super) fn lock(file: &fs::File, mode: LockMode) -> io::Result<()> {
+        fcntl(file, libc::F_OFD_SETLKW, lock_type(mode))
+    }
+
+    pub(super) fn try_lock(file: &fs::File, mode: LockMode) -> io::Result<bool> {
+        match fcntl(file, libc::F_OFD_SETLK, lock_type(mode)) {
+            Ok(()) => Ok(true),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EAGAIN) | Some(libc::EACCES)) => {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(super) fn unlock(file: &fs::File) -> io::Result<()> {
+        fcntl(file, libc::F_OFD_SETLK, libc::F_UNLCK as c_short)
+    }
+
+    fn lock_type(mode: LockMode) -> c_short {
+        match mode {
+            LockMode::Shared => libc::F_RDLCK as c_short,
+            LockMode::Exclusive => libc::F_WRLCK as c_short,
+        }
+    }
+
+    /// Locks or unlocks the whole file
+    fn fcntl(file: &fs::File, command: c_int, lock_type: c_short) -> io::Result<()> {
+        // SAFETY: flock is plain data and all zeroes are valid, a length of zero covers the whole
+        // file and OFD locks require a pid of zero
+        let mut flock: libc::flock = unsafe { std::mem::zeroed() };
+        flock.l_type = lock_type;
+        flock.l_whence = libc::SEEK_SET as c_short;
+
+        loop {
+            // SAFETY: the file is open and flock is a valid struct
+            if unsafe { libc::fcntl(file.as_raw_fd(), command, &flock) } == 0 {
+                return Ok(());
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// A lock file that contains the id of the process that created it, created by
+/// [`LockOptions::pid_file`]. The file is removed when the `LockFile` is dropped.
+///
+/// Unlike a [`FileLock`] the lock is the file itself, so it also works on file systems that
+/// don't support locking. If the process that created it died without removing it, the next
+/// process that wants the lock finds out that the id in the file doesn't belong to a running
+/// process anymore and replaces it. Only unix can tell whether a process is still running,
+/// elsewhere and for files that don't contain an id the lock file is never taken over.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+}
+
+impl LockFile {
+    /// The path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the lock file. Dropping it does the same but ignores errors
+    pub fn release(mut self) -> Result<()> {
+        let path = std::mem::take(&mut self.path);
+        crate::remove_file(path)
+    }
+
+    /// Returns `None` if the lock file exists and belongs to a running process
+    fn try_create(path: &Path) -> io::Result<Option<LockFile>> {
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    let lock = LockFile {
+                        path: path.to_path_buf(),
+                    };
+                    // the lock file is removed again by the drop if writing fails
+                    writeln!(file, "{}", std::process::id())?;
+                    return Ok(Some(lock));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    if !remove_stale(path)? {
+                        return Ok(None);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Removes the lock file at `path` if the process in it isn't running anymore. Returns whether
+/// creating the lock file should be tried again
+#[cfg(unix)]
+fn remove_stale(path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        // the owner removed it in the meantime
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    // without the lock two processes that found the same stale file could both remove it, the
+    // second one removing the new lock file of the first one
+    file.lock()?;
+    let opened = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(current) if current.dev() == opened.dev() && current.ino() == opened.ino() => {}
+        Ok(_) => return Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e),
+    }
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let stale = match contents.trim().parse::<libc::pid_t>() {
+        Ok(pid) if pid > 0 => !running(pid),
+        // the owner may not have written its id yet
+        _ => false,
+    };
+    if stale {
+        fs::remove_file(path)?;
+    }
+    Ok(stale)
+}
+
+#[cfg(not(unix))]
+fn remove_stale(_path: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(unix)]
+fn running(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 only checks whether the process exists
+    let result = unsafe { libc::kill(pid, 0) };
+    // a process of another user can't be signaled but is running
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// The locks that [`CopyOptions::lock`] takes for a recursive operation. They are released when
+/// this is dropped
+#[derive(Debug)]
+pub(crate) struct TreeLock {
+    _locks: Vec<FileLock>,
+}
+
+/// Locks every path with its mode and the destination exclusively if the options ask for it.
+/// The lock files are taken in the order of their paths, so two operations on the same paths can
+/// never wait for each other. Only the destination may be missing its parent, for the other paths
+/// that fails with `NotFound` before anything is created
+pub(crate) fn lock_tree(
+    options: &CopyOptions,
+    paths: &[(&Path, LockMode)],
+    destination: Option<&Path>,
+) -> Result<TreeLock> {
+    let lock = match &options.lock {
+        Some(lock) => lock,
+        None => return Ok(TreeLock { _locks: Vec::new() }),
+    };
+    let _phase = trace::phase!("lock").entered();
+
+    let destination = destination.map(|path| (path, LockMode::Exclusive, true));
+    let mut paths = paths
+        .iter()
+        .map(|(path, mode)| (*path, *mode, false))
+        .chain(destination)
+        .map(|(path, mode, create)| Ok((lock_path(path)?, mode, create)))
+        .collect::<Result<Vec<_>>>()?;
+    paths.sort();
+    // the stronger lock of the same path comes last
+    paths.dedup_by(|next, previous| {
+        next.0 == previous.0 && {
+            previous.1 = previous.1.max(next.1);
+            previous.2 |= next.2;
+            true
+        }
+    });
+
+    for (path, _, create) in &paths {
+        match path.parent() {
+            Some(parent) if !create && !parent.is_dir() => {
+                return Err(Error::IoExt {
+                    source: io::ErrorKind::NotFound.into(),
+                    path: parent.to_path_buf(),
+                    operation: Operation::Lock,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    let locks = paths
+        .into_iter()
+        .map(|(path, mode, create)| {
+            // a destination whose parent doesn't exist yet still needs a place for its lock file
+            if let Some(parent) = path.parent().filter(|_| create) {
+                crate::create_dir_all(parent)?;
+            }
+            lock.clone().mode(mode).lock(path)
+        })
+        .collect::<Result<_>>()?;
+    Ok(TreeLock { _locks: locks })
+}
+
+/// The lock file of a tree is next to it, so it can be taken before the tree exists and stays
+/// when the tree is moved away. `dir` is locked through `.dir.lock`
+fn lock_path(path: &Path) -> Result<PathBuf> {
+    let path = match path.file_name() {
+        Some(_) => path.to_path_buf(),
+        None => crate::canonicalize(path)?,
+    };
+    let name = path.file_name().unwrap_or_default();
+    let parent = match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+        Some(parent) => parent,
+        None => Path::new("/"),
+    };
+    // every spelling of the parent has to end up with the same lock file
+    let parent = crate::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf());
+
+    let mut lock_name = std::ffi::OsString::from(".");
+    lock_name.push(name);
+    lock_name.push(".lock");
+    Ok(parent.join(lock_name))
+}
+
+fn io_ext(source: io::Error, path: &Path, operation: Operation) -> Error {
+    Error::IoExt {
+        source,
+        path: path.to_path_buf(),
+        operation,
+    }
+}
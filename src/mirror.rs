@@ -0,0 +1,464 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use walkdir::WalkDir;
+
+use crate::error::{Error, Operation, Result};
+use crate::options::CopyOptions;
+use crate::report::Report;
+use crate::trace;
+use crate::utils::change_dir;
+use crate::vfs::{FileType, RealFs};
+
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A source that never stops changing still gets mirrored after this many debounce times
+const MAX_DEBOUNCES: u32 = 10;
+
+/// Options for [`mirror_with`]
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    debounce: Duration,
+    copy_options: CopyOptions,
+}
+
+impl Default for MirrorOptions {
+    fn default() -> Self {
+        MirrorOptions {
+            debounce: DEFAULT_DEBOUNCE,
+            copy_options: CopyOptions::new(),
+        }
+    }
+}
+
+impl MirrorOptions {
+    /// Creates the default options
+    pub fn new() -> MirrorOptions {
+        MirrorOptions::default()
+    }
+
+    /// Sets how long the source has to be quiet before the changes are applied, so an editor
+    /// that saves a file in several steps causes one copy. A source that keeps changing is
+    /// mirrored at least every ten debounce times. Defaults to 100 milliseconds
+    pub fn debounce(&mut self, debounce: Duration) -> &mut MirrorOptions {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Sets the options of the initial copy, it is only done with [`copy_dir_all_with`] if
+    /// the destination doesn't exist yet
+    ///
+    /// [`copy_dir_all_with`]: crate::copy_dir_all_with
+    pub fn copy_options(&mut self, copy_options: CopyOptions) -> &mut MirrorOptions {
+        self.copy_options = copy_options;
+        self
+    }
+}
+
+/// Copies `from` to `to` and keeps `to` up to date with every change in `from` until the
+/// returned [`Mirror`] is stopped or dropped.
+///
+/// The source is watched before the initial copy, so nothing that changes during the copy is
+/// missed. Created, modified, renamed and removed entries are applied to the destination on a
+/// background thread, a file is copied again when its size or modification time differ. If the
+/// watcher loses events because its queue overflowed the whole tree is compared again.
+/// Fifos, sockets and device files are left out. Symlinks to a file are copied as files, other
+/// symlinks are left out as well once the initial copy is done, which handles them like
+/// [`copy_dir_all_with`].
+///
+/// [`copy_dir_all_with`]: crate::copy_dir_all_with
+///
+/// ```no_run
+/// let mirror = more_fs::mirror("assets", "public/assets").unwrap();
+/// println!("copied {} files", mirror.initial().files);
+/// // serve the files while the mirror keeps them up to date
+/// let changes = mirror.stop().unwrap();
+/// println!("copied {} files since then", changes.files);
+/// ```
+pub fn mirror(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<Mirror> {
+    mirror_with(from, to, &MirrorOptions::new())
+}
+
+/// The same as [`mirror`] but with options
+pub fn mirror_with(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    options: &MirrorOptions,
+) -> Result<Mirror> {
+    let (from, to) = (from.as_ref().to_path_buf(), to.as_ref().to_path_buf());
+
+    let span = trace::operation!("mirror", from = %from.display(), to = %to.display());
+    trace::traced(span, || {
+        crate::check_copy_dir_all(&RealFs, &from, &to)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = {
+            let sender = sender.clone();
+            notify::recommended_watcher(move |event| {
+                // the mirror stopped if nobody receives anymore
+                let _ = sender.send(Message::Event(event));
+            })
+            .map_err(|e| watch_error(e, &from))?
+        };
+        watcher
+            .watch(&from, RecursiveMode::Recursive)
+            .map_err(|e| watch_error(e, &from))?;
+
+        let initial = if to.exists() {
+            let mut report = Report::default();
+            sync_tree(&from, &to, &mut report)?;
+            report
+        } else {
+            crate::copy_dir_all_with(&from, &to, &options.copy_options)?
+        };
+
+        // the changes are applied in the span of the mirror
+        let scope = trace::Scope::current();
+        let worker = Worker {
+            receiver,
+            debounce: options.debounce,
+            from: from.clone(),
+            to,
+            report: Report::default(),
+        };
+        let thread = thread::Builder::new()
+            .name("more-fs-mirror".to_string())
+            .spawn(move || scope.in_scope(|| worker.run()))
+            .map_err(|e| Error::IoExt {
+                source: e,
+                path: from,
+                operation: Operation::Watch,
+            })?;
+
+        Ok(Mirror {
+            watcher: Some(watcher),
+            sender,
+            thread: Some(thread),
+            initial,
+        })
+    })
+}
+
+/// A running mirror created by [`mirror`]. Dropping it stops the mirror like [`Mirror::stop`]
+/// but ignores its result
+#[derive(Debug)]
+pub struct Mirror {
+    watcher: Option<RecommendedWatcher>,
+    sender: Sender<Message>,
+    thread: Option<JoinHandle<Result<Report>>>,
+    initial: Report,
+}
+
+impl Mirror {
+    /// What the initial copy did
+    pub fn initial(&self) -> &Report {
+        &self.initial
+    }
+
+    /// Whether the mirror still applies changes. It stops by itself if a change could not be
+    /// applied even after comparing the whole tree again, [`Mirror::stop`] returns the error
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stops watching the source, applies the changes that were already seen and waits for the
+    /// background thread. Returns what was copied after the initial copy, or the error that
+    /// stopped the mirror. Removed entries are not counted
+    pub fn stop(mut self) -> Result<Report> {
+        match self.shutdown() {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            None => Ok(Report::default()),
+        }
+    }
+
+    fn shutdown(&mut self) -> Option<thread::Result<Result<Report>>> {
+        // no new events can arrive after the watcher is gone
+        drop(self.watcher.take());
+        let _ = self.sender.send(Message::Stop);
+        self.thread.take().map(JoinHandle::join)
+    }
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[derive(Debug)]
+enum Message {
+    Event(notify::Result<Event>),
+    Stop,
+}
+
+/// The changes seen since the last time they were applied. The paths are relative to the
+/// source and sorted, so a directory is always handled before its contents
+#[derive(Debug, Default)]
+struct Changes {
+    paths: BTreeSet<PathBuf>,
+    renames: Vec<(PathBuf, PathBuf)>,
+    rescan: bool,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.paths.is_empty() && !self.rescan
+    }
+}
+
+struct Worker {
+    receiver: Receiver<Message>,
+    debounce: Duration,
+    from: PathBuf,
+    to: PathBuf,
+    report: Report,
+}
+
+impl Worker {
+    fn run(mut self) -> Result<Report> {
+        let mut stop = false;
+        while !stop {
+            let mut changes = Changes::default();
+            // wait for the first change, then for the source to be quiet
+            match self.receiver.recv() {
+                Ok(message) => stop = self.add(&mut changes, message),
+                Err(_) => stop = true,
+            }
+
+            let deadline = Instant::now() + self.debounce * MAX_DEBOUNCES;
+            while !stop {
+                let wait = self
+                    .debounce
+                    .min(deadline.saturating_duration_since(Instant::now()));
+                match self.receiver.recv_timeout(wait) {
+                    Ok(message) => stop = self.add(&mut changes, message),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => stop = true,
+                }
+            }
+
+            if !changes.is_empty() {
+                self.apply(changes)?;
+            }
+        }
+        Ok(self.report)
+    }
+
+    /// Returns whether the mirror should stop
+    fn add(&self, changes: &mut Changes, message: Message) -> bool {
+        let event = match message {
+            Message::Stop => return true,
+            Message::Event(Ok(event)) => event,
+            // the watcher doesn't know what it missed
+            Message::Event(Err(_)) => {
+                changes.rescan = true;
+                return false;
+            }
+        };
+
+        if event.need_rescan() {
+            changes.rescan = true;
+        }
+        let paths: Vec<PathBuf> = event
+            .paths
+            .iter()
+            .filter_map(|path| path.strip_prefix(&self.from).ok())
+            .map(Path::to_path_buf)
+            .collect();
+
+        match event.kind {
+            EventKind::Access(_) => {}
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                changes.renames.push((paths[0].clone(), paths[1].clone()));
+                changes.paths.extend(paths);
+            }
+            _ => {
+                for path in paths {
+                    // the source itself changed
+                    if path.as_os_str().is_empty() {
+                        changes.rescan = true;
+                    } else {
+                        changes.paths.insert(path);
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn apply(&mut self, changes: Changes) -> Result<()> {
+        if changes.rescan {
+            return sync_tree(&self.from, &self.to, &mut self.report);
+        }
+
+        // renaming in the destination saves copying the entry again
+        for (old, new) in &changes.renames {
+            let (old_to, new_to) = (self.to.join(old), self.to.join(new));
+            if !self.from.join(old).exists() && self.from.join(new).exists() && !new_to.exists() {
+                let _ = fs::rename(old_to, new_to);
+            }
+        }
+
+        for path in &changes.paths {
+            match reconcile(&self.from.join(path), &self.to.join(path), &mut self.report) {
+                Ok(()) => {}
+                // it changed again in the meantime, which is another event
+                Err(e) if e.io_error_kind() == io::ErrorKind::NotFound => {}
+                // compare everything before giving up
+                Err(_) => return sync_tree(&self.from, &self.to, &mut self.report),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Makes `to` look like `from`, whatever `from` is
+fn reconcile(from: &Path, to: &Path, report: &mut Report) -> Result<()> {
+    match fs::symlink_metadata(from) {
+        Ok(metadata) if metadata.is_dir() => sync_tree(from, to, report),
+        Ok(metadata) if metadata.file_type().is_symlink() => sync_symlink(from, to, report),
+        Ok(metadata) => sync_file(from, to, &metadata, report),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => remove(to),
+        Err(e) => Err(Error::IoExt {
+            source: e,
+            path: from.to_path_buf(),
+            operation: Operation::Metadata,
+        }),
+    }
+}
+
+/// Copies everything that is new or changed from `from` to `to` and removes everything from
+/// `to` that isn't in `from` anymore
+fn sync_tree(from: &Path, to: &Path, report: &mut Report) -> Result<()> {
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let target = change_dir(from, to, entry.path())?;
+
+        if entry.file_type().is_dir() {
+            sync_dir(&target, report)?;
+        } else if entry.file_type().is_symlink() {
+            sync_symlink(entry.path(), &target, report)?;
+        } else {
+            let metadata = crate::symlink_metadata(entry.path())?;
+            sync_file(entry.path(), &target, &metadata, report)?;
+        }
+    }
+
+    let mut walk = WalkDir::new(to).min_depth(1).into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        let source = change_dir(to, from, entry.path())?;
+
+        if source.symlink_metadata().is_err() {
+            remove(entry.path())?;
+            if entry.file_type().is_dir() {
+                walk.skip_current_dir();
+            }
+        }
+    }
+    Ok(())
+}
+
+fn sync_dir(to: &Path, report: &mut Report) -> Result<()> {
+    match to.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => return Ok(()),
+        Ok(_) => crate::remove_file(to)?,
+        Err(_) => {}
+    }
+    crate::create_dir_all(to)?;
+    report.dirs += 1;
+    Ok(())
+}
+
+/// A symlink to a file is copied like a file, as the copy functions do. Symlinks to anything
+/// else and dangling ones are skipped, whatever was mirrored there before is removed
+fn sync_symlink(from: &Path, to: &Path, report: &mut Report) -> Result<()> {
+    match fs::metadata(from) {
+        Ok(metadata) if metadata.is_file() => sync_file(from, to, &metadata, report),
+        _ => remove(to),
+    }
+}
+
+/// Copies the file if the destination has a different size or modification time. The copy
+/// gets the modification time of the source, so it is only copied again after it changed
+fn sync_file(from: &Path, to: &Path, metadata: &fs::Metadata, report: &mut Report) -> Result<()> {
+    // copying would block on a fifo or read a device forever
+    if FileType::from(metadata.file_type()).is_special() {
+        return Ok(());
+    }
+
+    match to.symlink_metadata() {
+        Ok(existing) if existing.is_dir() => crate::remove_dir_all(to)?,
+        Ok(existing) if existing.file_type().is_symlink() => crate::remove_file(to)?,
+        Ok(existing)
+            if existing.len() == metadata.len()
+                && existing.modified().ok() == metadata.modified().ok() =>
+        {
+            return Ok(())
+        }
+        _ => {}
+    }
+
+    if let Some(parent) = to.parent() {
+        crate::create_dir_all(parent)?;
+    }
+    report.bytes += crate::copy(from, to)?;
+    report.files += 1;
+
+    if let Ok(modified) = metadata.modified() {
+        set_modified(to, modified).map_err(|e| Error::IoExt {
+            source: e,
+            path: to.to_path_buf(),
+            operation: Operation::Write,
+        })?;
+    }
+    Ok(())
+}
+
+/// The copy may be read only like its source, the owner can still change its times without
+/// opening it for writing
+fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_WRITE_ATTRIBUTES
+        options.access_mode(0x100);
+    }
+    #[cfg(not(windows))]
+    options.read(true);
+
+    options.open(path)?.set_modified(modified)
+}
+
+fn remove(path: &Path) -> Result<()> {
+    match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => crate::remove_dir_all(path),
+        Ok(_) => crate::remove_file(path),
+        Err(_) => Ok(()),
+    }
+}
+
+fn watch_error(source: notify::Error, path: &Path) -> Error {
+    let source = match source.kind {
+        notify::ErrorKind::Io(e) => e,
+        notify::ErrorKind::PathNotFound => io::Error::from(io::ErrorKind::NotFound),
+        _ => io::Error::other(source),
+    };
+    Error::IoExt {
+        source,
+        path: path.to_path_buf(),
+        operation: Operation::Watch,
+    }
+}
@@ -0,0 +1,312 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+#[cfg(feature = "rayon")]
+use rayon::ThreadPool;
+
+use crate::lock::LockOptions;
+use crate::vfs::Metadata;
+
+/// How much of a copy has to reach the disk before a function reports success.
+///
+/// By default nothing is synced and the operating system decides when the copied data is
+/// written out. After a power loss the destination can then be truncated or missing, which is
+/// especially bad for the move functions because the source is already gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Never call fsync
+    #[default]
+    None,
+    /// fsync every copied file
+    Files,
+    /// fsync every copied file and every directory that received new entries
+    FilesAndDirs,
+}
+
+impl Durability {
+    pub(crate) fn sync_files(self) -> bool {
+        self != Durability::None
+    }
+
+    pub(crate) fn sync_dirs(self) -> bool {
+        self == Durability::FilesAndDirs
+    }
+}
+
+/// What the recursive copy functions do with fifos, sockets and device files. Copying them like
+/// regular files would block forever on a fifo or read the whole contents of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpecialFiles {
+    /// Fail with [`Error::SpecialFile`](crate::Error::SpecialFile)
+    #[default]
+    Error,
+    /// Leave them out of the copy
+    Skip,
+    /// Create fifos with `mkfifo` and device files with `mknod` at the destination. Creating
+    /// device files needs privileges, without them and for sockets the entry is skipped
+    Recreate,
+}
+
+/// How the copy and move functions keep a file they are about to overwrite, like the `--backup`
+/// option of GNU `cp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backup {
+    /// Overwrite the file without a backup
+    #[default]
+    None,
+    /// Rename the file to `file~`, or whatever [`CopyOptions::backup_suffix`] sets. An older
+    /// backup with that name is replaced
+    Simple,
+    /// Rename the file to `file.~1~`, `file.~2~` and so on, using one more than the highest
+    /// number that exists
+    Numbered,
+    /// Make a numbered backup if the file already has one and a simple backup otherwise
+    Existing,
+}
+
+/// What a recursive copy does with a file whose destination already exists, returned by the hook
+/// set with [`CopyOptions::on_conflict`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConflictAction {
+    /// Replace the existing file, this is what happens without a hook
+    Overwrite,
+    /// Leave the existing file alone and don't copy the source
+    Skip,
+    /// Replace the existing file only if the source was modified more recently
+    KeepNewer,
+    /// Copy the source next to the existing file as `name (1).ext`, counting up until the name
+    /// is free
+    Rename,
+    /// Stop the copy with [`Error::Conflict`](crate::Error::Conflict)
+    Abort,
+}
+
+type ConflictHook = Arc<dyn Fn(&Path, &Metadata, &Metadata) -> ConflictAction + Send + Sync>;
+type ProgressHook = Arc<dyn Fn(&Path, u64) + Send + Sync>;
+
+/// Which threads the `_par` functions run on
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone, Default)]
+pub enum Parallelism {
+    /// Rayon's global thread pool
+    #[default]
+    Global,
+    /// A new thread pool with this many threads, built for every call. A value of zero is
+    /// treated as one
+    Threads(usize),
+    /// A thread pool owned by the caller, which keeps the work away from other rayon users
+    Pool(Arc<ThreadPool>),
+    /// Looks at the devices of the source and destination. If one of them is a spinning disk
+    /// only a few threads are used, because more of them make the disk seek back and forth.
+    /// Otherwise this is the same as [`Parallelism::Global`]. Only linux can tell the kind of
+    /// disk, on other platforms this is always the same as [`Parallelism::Global`]
+    Auto,
+}
+
+/// Options for the `_with` variants of the copy and move functions.
+///
+/// This is modeled after [`std::fs::OpenOptions`], every setter takes `&mut self` so they can
+/// be chained.
+///
+/// ```no_run
+/// use more_fs::{copy_dir_all_with, CopyOptions, Durability};
+///
+/// copy_dir_all_with(
+///     "from_directory",
+///     "to_directory",
+///     CopyOptions::new().durability(Durability::FilesAndDirs),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct CopyOptions {
+    pub(crate) durability: Durability,
+    pub(crate) preflight: bool,
+    pub(crate) special_files: SpecialFiles,
+    #[cfg(feature = "rayon")]
+    pub(crate) parallelism: Parallelism,
+    #[cfg(feature = "tokio")]
+    pub(crate) concurrency: Option<usize>,
+    pub(crate) on_conflict: Option<ConflictHook>,
+    pub(crate) on_progress: Option<ProgressHook>,
+    pub(crate) backup: Backup,
+    pub(crate) backup_suffix: Option<OsString>,
+    pub(crate) lock: Option<LockOptions>,
+}
+
+impl fmt::Debug for CopyOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("CopyOptions");
+        debug
+            .field("durability", &self.durability)
+            .field("preflight", &self.preflight)
+            .field("special_files", &self.special_files);
+        #[cfg(feature = "rayon")]
+        debug.field("parallelism", &self.parallelism);
+        #[cfg(feature = "tokio")]
+        debug.field("concurrency", &self.concurrency);
+        debug
+            .field("on_conflict", &self.on_conflict.is_some())
+            .field("on_progress", &self.on_progress.is_some())
+            .field("backup", &self.backup)
+            .field("backup_suffix", &self.backup_suffix)
+            .field("lock", &self.lock)
+            .finish()
+    }
+}
+
+impl CopyOptions {
+    /// Creates the default options, these behave exactly like the functions without the `_with`
+    /// suffix
+    pub fn new() -> CopyOptions {
+        CopyOptions::default()
+    }
+
+    /// Sets how much of the copied data has to be synced to disk. Checkout [`Durability`]
+    pub fn durability(&mut self, durability: Durability) -> &mut CopyOptions {
+        self.durability = durability;
+        self
+    }
+
+    /// Sets how fifos, sockets and device files are copied by the recursive functions. What
+    /// happened to each of them is listed in [`Report::special_files`](crate::Report::special_files)
+    pub fn special_files(&mut self, special_files: SpecialFiles) -> &mut CopyOptions {
+        self.special_files = special_files;
+        self
+    }
+
+    /// Sets which threads the `_par` functions run on. Checkout [`Parallelism`]
+    #[cfg(feature = "rayon")]
+    pub fn parallelism(&mut self, parallelism: Parallelism) -> &mut CopyOptions {
+        self.parallelism = parallelism;
+        self
+    }
+
+    /// Checks before anything is written that the destination is writable, has enough free space
+    /// and allows the names of all entries, and that every entry of the source can be read. All
+    /// problems are reported together in [`Error::Preflight`](crate::Error::Preflight). Only the
+    /// functions that copy on the real filesystem run the checks, the `_in` variants ignore it.
+    pub fn preflight(&mut self, preflight: bool) -> &mut CopyOptions {
+        self.preflight = preflight;
+        self
+    }
+
+    /// Sets the maximum number of files that the functions in the [`async`](crate::async) module
+    /// copy at the same time. Defaults to 64. A value of zero is treated as one.
+    #[cfg(feature = "tokio")]
+    pub fn concurrency(&mut self, concurrency: usize) -> &mut CopyOptions {
+        self.concurrency = Some(concurrency.max(1));
+        self
+    }
+
+    /// Sets a hook that decides what happens when the recursive copy and move functions find an
+    /// existing file at the destination. It receives the destination path and the metadata of
+    /// the source and of the existing file. Without a hook existing files are overwritten. With a hook
+    /// the recursive functions also copy into directories that already exist. Every
+    /// decision is listed in [`Report::conflicts`](crate::Report::conflicts), and the move
+    /// functions keep the sources of the files that were not copied.
+    ///
+    /// ```no_run
+    /// use more_fs::{copy_dir_all_with, ConflictAction, CopyOptions};
+    ///
+    /// let report = copy_dir_all_with(
+    ///     "from_directory",
+    ///     "to_directory",
+    ///     CopyOptions::new().on_conflict(|_to, _source, _existing| ConflictAction::KeepNewer),
+    /// )
+    /// .unwrap();
+    /// println!("{} conflicts", report.conflicts.len());
+    /// ```
+    pub fn on_conflict(
+        &mut self,
+        hook: impl Fn(&Path, &Metadata, &Metadata) -> ConflictAction + Send + Sync + 'static,
+    ) -> &mut CopyOptions {
+        self.on_conflict = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets a hook that the recursive copy and move functions call after every file and symlink
+    /// they copied, with the path of the source and the amount of bytes that were copied. The
+    /// `_par` functions call it from many threads at once, so it should be quick.
+    ///
+    /// ```no_run
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// use more_fs::{copy_dir_all_with, CopyOptions};
+    ///
+    /// let copied = Arc::new(AtomicU64::new(0));
+    /// let counter = Arc::clone(&copied);
+    /// copy_dir_all_with(
+    ///     "from_directory",
+    ///     "to_directory",
+    ///     CopyOptions::new().on_progress(move |_from, bytes| {
+    ///         counter.fetch_add(bytes, Ordering::Relaxed);
+    ///     }),
+    /// )
+    /// .unwrap();
+    /// println!("{} bytes copied", copied.load(Ordering::Relaxed));
+    /// ```
+    pub fn on_progress(
+        &mut self,
+        hook: impl Fn(&Path, u64) + Send + Sync + 'static,
+    ) -> &mut CopyOptions {
+        self.on_progress = Some(Arc::new(hook));
+        self
+    }
+
+    /// Makes the copy and move functions back up every file they overwrite, the compressing copies
+    /// of the `compress` module included. Checkout [`Backup`]. `Dir` has no options and `mirror`
+    /// keeps the destination equal to the source, so neither of them makes backups
+    pub fn backup(&mut self, backup: Backup) -> &mut CopyOptions {
+        self.backup = backup;
+        self
+    }
+
+    /// Sets the suffix of simple backups. Defaults to `~`
+    pub fn backup_suffix(&mut self, suffix: impl Into<OsString>) -> &mut CopyOptions {
+        self.backup_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Makes the recursive copy, move and remove functions hold advisory locks while they run,
+    /// so two of them working on the same trees don't interleave. The source of a copy is
+    /// locked shared, everything else exclusive. The mode of `lock` is ignored, its style and
+    /// timeout are used.
+    ///
+    /// A tree is locked through a lock file next to it, `dir` through `.dir.lock`, so it can be
+    /// locked before it exists and after it was moved away. A missing parent of the destination
+    /// of a copy or a move is created to hold its lock file, for any other tree it fails with
+    /// `NotFound`. The lock files are left behind, removing them would let a waiting process lock
+    /// a file that is already gone. The locks are advisory and only keep out others that lock the
+    /// same trees. The `_in` variants don't take locks.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use more_fs::{move_dir_all_with, CopyOptions, LockOptions};
+    ///
+    /// move_dir_all_with(
+    ///     "from_directory",
+    ///     "to_directory",
+    ///     CopyOptions::new().lock(LockOptions::new().timeout(Duration::from_secs(30)).clone()),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn lock(&mut self, lock: LockOptions) -> &mut CopyOptions {
+        self.lock = Some(lock);
+        self
+    }
+
+    /// The same options without the locks, for the parts of an operation that already hold them
+    #[cfg(any(feature = "rayon", feature = "tokio"))]
+    pub(crate) fn without_lock(&self) -> CopyOptions {
+        CopyOptions {
+            lock: None,
+            ..self.clone()
+        }
+    }
+}
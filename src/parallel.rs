@@ -0,0 +1,78 @@
+use std::io;
+use std::path::Path;
+
+use rayon::ThreadPoolBuilder;
+
+use crate::error::{Error, Operation, Result};
+use crate::options::Parallelism;
+use crate::trace::Scope;
+
+/// How many threads are used when [`Parallelism::Auto`] finds a spinning disk. A few requests at
+/// once still let the disk reorder them, more just make it seek
+#[cfg(target_os = "linux")]
+const ROTATIONAL_THREADS: usize = 2;
+
+/// Runs `op` on the threads that were chosen with `parallelism`
+pub(crate) fn install<R: Send>(
+    parallelism: &Parallelism,
+    from: &Path,
+    to: &Path,
+    op: impl FnOnce() -> Result<R> + Send,
+) -> Result<R> {
+    // the threads of a pool don't know the span of the caller
+    let scope = Scope::current();
+    match parallelism {
+        Parallelism::Global => op(),
+        Parallelism::Threads(threads) => ThreadPoolBuilder::new()
+            .num_threads((*threads).max(1))
+            .build()
+            .map_err(|e| Error::IoExtMulti {
+                source: io::Error::other(e),
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+                operation: Operation::CopyDirAll,
+            })?
+            .install(|| scope.in_scope(op)),
+        Parallelism::Pool(pool) => pool.install(|| scope.in_scope(op)),
+        Parallelism::Auto => match auto_threads(from, to) {
+            Some(threads) => install(&Parallelism::Threads(threads), from, to, op),
+            None => op(),
+        },
+    }
+}
+
+/// Returns the amount of threads to use, or `None` for the global pool
+#[cfg(target_os = "linux")]
+fn auto_threads(from: &Path, to: &Path) -> Option<usize> {
+    let to = crate::utils::closest_existing(to);
+
+    if rotational(from) == Some(true) || rotational(&to) == Some(true) {
+        Some(ROTATIONAL_THREADS)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn auto_threads(_from: &Path, _to: &Path) -> Option<usize> {
+    None
+}
+
+/// Finds out if `path` is on a spinning disk by reading `queue/rotational` of its block device
+/// in sysfs. Returns `None` if it is not on a block device, like tmpfs or network filesystems
+#[cfg(target_os = "linux")]
+fn rotational(path: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).ok()?.dev();
+    // the encoding of glibc's major and minor
+    let major = ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0x0fff);
+    let minor = ((dev >> 12) & 0xffff_ff00) | (dev & 0x00ff);
+
+    let device = std::fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)).ok()?;
+    // partitions don't have a queue, their disk is the parent directory
+    let read = |dir: &Path| std::fs::read_to_string(dir.join("queue/rotational")).ok();
+    let queue = read(&device).or_else(|| device.parent().and_then(read))?;
+
+    Some(queue.trim() == "1")
+}
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::path::Path;
+
+use walkdir::WalkDir;
+
+use crate::error::{Error, PreflightProblem, Result};
+use crate::utils::{change_dir, closest_existing};
+use crate::vfs::RealFs;
+
+/// The longest file name most filesystems allow
+#[cfg(not(unix))]
+const DEFAULT_NAME_MAX: u64 = 255;
+
+/// What the filesystem of the destination allows
+struct Limits {
+    /// Free bytes for unprivileged users, `None` if the platform can't tell
+    available: Option<u64>,
+    name_max: u64,
+}
+
+/// Checks everything that can be known in advance about copying `from` to `to` and reports all
+/// problems together, without writing anything
+pub(crate) fn preflight(from: &Path, to: &Path) -> Result<()> {
+    crate::check_path_copy_dir_all(&RealFs, from)?;
+
+    let mut problems = Vec::new();
+    let mut needed = 0;
+    // the destination usually doesn't exist yet
+    let existing = closest_existing(to);
+    let limits = limits(&existing);
+
+    if !writable(&existing) {
+        problems.push(PreflightProblem::NotWritable {
+            path: existing.clone(),
+        });
+    }
+
+    for entry in WalkDir::new(from) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().unwrap_or(from).to_path_buf();
+                problems.push(PreflightProblem::Unreadable {
+                    path,
+                    source: e.into(),
+                });
+                continue;
+            }
+        };
+        let path = entry.path();
+        let new_path = change_dir(from, to, path)?;
+
+        if let Some(name) = new_path.file_name() {
+            if name_len(name) > limits.name_max {
+                problems.push(PreflightProblem::NameTooLong {
+                    path: new_path,
+                    max: limits.name_max,
+                });
+            }
+        }
+
+        if entry.file_type().is_file() {
+            match File::open(path).and_then(|file| file.metadata()) {
+                Ok(metadata) => needed += metadata.len(),
+                Err(e) => problems.push(PreflightProblem::Unreadable {
+                    path: path.to_path_buf(),
+                    source: e,
+                }),
+            }
+        }
+    }
+
+    if let Some(available) = limits.available {
+        if needed > available {
+            problems.push(PreflightProblem::NotEnoughSpace {
+                path: existing,
+                needed,
+                available,
+            });
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Preflight { problems })
+    }
+}
+
+#[cfg(unix)]
+fn limits(path: &Path) -> Limits {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let statvfs = CString::new(path.as_os_str().as_bytes())
+        .map_err(std::io::Error::from)
+        .and_then(|path| {
+            let mut statvfs = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+            // SAFETY: the path is a valid c string and statvfs only writes to the buffer
+            if unsafe { libc::statvfs(path.as_ptr(), statvfs.as_mut_ptr()) } == 0 {
+                // SAFETY: statvfs succeeded so the buffer is initialized
+                Ok(unsafe { statvfs.assume_init() })
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+
+    match statvfs {
+        #[allow(clippy::unnecessary_cast, clippy::useless_conversion)]
+        Ok(statvfs) => Limits {
+            available: Some((statvfs.f_bavail as u64).saturating_mul(statvfs.f_frsize as u64)),
+            name_max: statvfs.f_namemax as u64,
+        },
+        // if the filesystem can't be queried the copy will report the real problem
+        Err(_) => Limits {
+            available: None,
+            name_max: u64::MAX,
+        },
+    }
+}
+
+#[cfg(not(unix))]
+fn limits(_path: &Path) -> Limits {
+    Limits {
+        available: None,
+        name_max: DEFAULT_NAME_MAX,
+    }
+}
+
+#[cfg(unix)]
+fn name_len(name: &std::ffi::OsStr) -> u64 {
+    use std::os::unix::ffi::OsStrExt;
+
+    name.as_bytes().len() as u64
+}
+
+#[cfg(not(unix))]
+fn name_len(name: &std::ffi::OsStr) -> u64 {
+    name.to_string_lossy().chars().count() as u64
+}
+
+#[cfg(unix)]
+fn writable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    match CString::new(path.as_os_str().as_bytes()) {
+        // SAFETY: the path is a valid c string
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn writable(path: &Path) -> bool {
+    path.metadata()
+        .map(|metadata| !metadata.permissions().readonly())
+        .unwrap_or(false)
+}
@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::options::ConflictAction;
+use crate::vfs::FileType;
+
+/// What a recursive copy or move did. With the `serde` feature flag it can be serialized, for
+/// example to keep it with the logs of a CI run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    /// The amount of regular files that were copied
+    pub files: u64,
+    /// The amount of directories that were created, including the destination itself
+    pub dirs: u64,
+    /// The amount of symlinks that were copied, their targets are copied like files
+    pub symlinks: u64,
+    /// The amount of bytes that were copied
+    pub bytes: u64,
+    /// Every fifo, socket and device file that was found, checkout
+    /// [`CopyOptions::special_files`](crate::CopyOptions::special_files)
+    pub special_files: Vec<SpecialFile>,
+    /// Every file whose destination already existed, checkout
+    /// [`CopyOptions::on_conflict`](crate::CopyOptions::on_conflict)
+    pub conflicts: Vec<Conflict>,
+    /// Every entry of the source that was left out of the copy
+    pub skipped: Vec<Skipped>,
+    /// How long each phase of the operation took, checkout [`Timings`]
+    pub timings: Timings,
+}
+
+impl Report {
+    /// The amount of special files that were created again at the destination
+    pub fn recreated(&self) -> usize {
+        self.special_files
+            .iter()
+            .filter(|special| special.action == SpecialAction::Recreated)
+            .count()
+    }
+
+    /// Adds the entries counted by another part of the same operation. The timings are only
+    /// measured by the operation as a whole, so they are not added
+    #[cfg(any(feature = "rayon", feature = "tokio"))]
+    pub(crate) fn merge(mut self, other: Report) -> Report {
+        self.files += other.files;
+        self.dirs += other.dirs;
+        self.symlinks += other.symlinks;
+        self.bytes += other.bytes;
+        self.special_files.extend(other.special_files);
+        self.conflicts.extend(other.conflicts);
+        self.skipped.extend(other.skipped);
+        self
+    }
+
+    /// The sources that were left out of the copy, a move has to keep them
+    pub(crate) fn kept(&self) -> impl Iterator<Item = &Path> {
+        self.skipped.iter().map(|skipped| skipped.path.as_path())
+    }
+}
+
+/// How long each phase of an operation took. Phases that an operation doesn't have stay zero
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timings {
+    /// Checking the source and destination with
+    /// [`CopyOptions::preflight`](crate::CopyOptions::preflight)
+    pub preflight: Duration,
+    /// Walking the source and creating the directories, only the `_par` functions do this
+    /// before copying. Everywhere else it is part of [`Timings::copy`]
+    pub walk: Duration,
+    /// Copying the files
+    pub copy: Duration,
+    /// Copying directory permissions and syncing directories after the files were copied
+    pub finish: Duration,
+    /// Removing the source of a move
+    pub remove: Duration,
+}
+
+impl Timings {
+    /// The time of all phases together
+    pub fn total(&self) -> Duration {
+        self.preflight + self.walk + self.copy + self.finish + self.remove
+    }
+}
+
+/// An entry of the source that a recursive copy left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Skipped {
+    /// The path in the source
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// Why a [`Skipped`] entry was left out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SkipReason {
+    /// A fifo, socket or device file that was skipped or could not be recreated, checkout
+    /// [`Report::special_files`]
+    SpecialFile,
+    /// The destination already existed and the conflict hook decided to keep it, checkout
+    /// [`Report::conflicts`]
+    Conflict,
+}
+
+/// A fifo, socket or device file found by a recursive copy
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpecialFile {
+    /// The path in the source
+    pub path: PathBuf,
+    pub file_type: FileType,
+    pub action: SpecialAction,
+}
+
+/// What happened to a [`SpecialFile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SpecialAction {
+    /// It was created again at the destination
+    Recreated,
+    /// It was left out of the copy
+    Skipped,
+}
+
+/// A file of a recursive copy whose destination already existed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Conflict {
+    /// The path in the source
+    pub path: PathBuf,
+    /// Where the file was copied to, or the existing file if it was not copied. With
+    /// [`ConflictAction::Rename`] this is the new name
+    pub to: PathBuf,
+    pub action: ConflictAction,
+    /// Whether the source was copied, [`ConflictAction::KeepNewer`] only copies newer files
+    pub copied: bool,
+}
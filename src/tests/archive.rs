@@ -0,0 +1,435 @@
+use std::io::Cursor;
+
+#[cfg(feature = "tar")]
+use tar::{Builder, EntryType, Header};
+use test_dir::{assert_file_contents_eq, assert_paths_exists, fs_fn, join_all};
+
+use crate::Error;
+
+#[cfg(feature = "tar")]
+fn file_header(size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(size);
+    header.set_mode(0o644);
+    header
+}
+
+#[cfg(feature = "tar")]
+/// Appends an entry with a raw path, the tar builder itself refuses to write paths with `..`
+fn append_raw(builder: &mut Builder<Vec<u8>>, path: &str, contents: &[u8]) {
+    let mut header = file_header(contents.len() as u64);
+    header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+    header.set_cksum();
+    builder.append(&header, contents).unwrap();
+}
+
+#[cfg(feature = "tar")]
+fn append_symlink(builder: &mut Builder<Vec<u8>>, path: &str, target: &str) {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, path, target).unwrap();
+}
+
+#[cfg(feature = "tar")]
+fs_fn! {
+    #[test]
+    fn pack_unpack()(dir) {
+        let (create_dir, create_file, from, to) = join_all!(dir, "from/b/c", "from/b/hello.txt", "from", "to");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+
+        let archive = crate::pack_dir(&from, Vec::new()).unwrap();
+        crate::unpack_dir(Cursor::new(archive), &to).unwrap();
+
+        assert_paths_exists!(to.join("b/c"));
+        assert_file_contents_eq!(create_file, to.join("b/hello.txt"));
+    }
+}
+
+#[cfg(feature = "tar")]
+fs_fn! {
+    #[test]
+    fn unpack_parent_dir()(dir) {
+        let to = dir.join("nested/to");
+        let mut builder = Builder::new(Vec::new());
+        append_raw(&mut builder, "../evil.txt", b"evil");
+        let archive = builder.into_inner().unwrap();
+
+        let err = crate::unpack_dir(Cursor::new(archive), &to).unwrap_err();
+
+        assert!(matches!(err, Error::PathEscape { .. }));
+        assert!(!dir.join("nested/evil.txt").exists());
+    }
+}
+
+#[cfg(feature = "tar")]
+fs_fn! {
+    #[test]
+    fn unpack_symlink_escape()(dir) {
+        let to = dir.join("to");
+        let mut builder = Builder::new(Vec::new());
+        append_symlink(&mut builder, "link", "../../outside");
+        let archive = builder.into_inner().unwrap();
+
+        let err = crate::unpack_dir(Cursor::new(archive), &to).unwrap_err();
+
+        assert!(matches!(err, Error::PathEscape { .. }));
+        assert!(to.join("link").symlink_metadata().is_err());
+    }
+}
+
+#[cfg(feature = "tar")]
+fs_fn! {
+    #[test]
+    fn unpack_chained_symlink_escape()(dir) {
+        // `t` resolves to `s/..`, which is the parent of `to` once `s` points to `.`
+        for links in [[("s", "."), ("t", "s/..")], [("t", "s/.."), ("s", ".")]] {
+            let to = dir.join("nested/to");
+            let mut builder = Builder::new(Vec::new());
+            for (path, target) in links {
+                append_symlink(&mut builder, path, target);
+            }
+            let archive = builder.into_inner().unwrap();
+
+            let err = crate::unpack_dir(Cursor::new(archive), &to).unwrap_err();
+
+            assert!(matches!(err, Error::PathEscape { .. }), "{:?}", links);
+            assert!(to.join("t").symlink_metadata().is_err());
+            std::fs::remove_dir_all(&to).unwrap();
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "tar"))]
+fs_fn! {
+    #[test]
+    fn unpack_hard_link_to_symlink_escape()(dir) {
+        let to = dir.join("nested/to");
+        let mut builder = Builder::new(Vec::new());
+        append_symlink(&mut builder, "a/b/s", "../..");
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Link);
+        header.set_size(0);
+        builder.append_link(&mut header, "s2", "a/b/s").unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let err = crate::unpack_dir(Cursor::new(archive), &to).unwrap_err();
+
+        assert!(matches!(err, Error::PathEscape { .. }), "{:?}", err);
+        assert!(to.join("a/b/s").symlink_metadata().is_ok());
+        assert!(to.join("s2").symlink_metadata().is_err());
+    }
+}
+
+#[cfg(all(unix, feature = "tar"))]
+fs_fn! {
+    #[test]
+    fn unpack_relative_symlink()(dir) {
+        let (create_dir, create_file, from, to) = join_all!(dir, "from/b", "from/hello.txt", "from", "to");
+        dir.mkdirp(&create_dir);
+        dir.touch_with_contents(&create_file);
+        std::os::unix::fs::symlink("../hello.txt", create_dir.join("link")).unwrap();
+
+        let archive = crate::pack_dir(&from, Vec::new()).unwrap();
+        crate::unpack_dir(Cursor::new(archive), &to).unwrap();
+
+        assert_file_contents_eq!(create_file, to.join("b/link"));
+    }
+}
+
+#[cfg(all(unix, feature = "tar"))]
+fs_fn! {
+    #[test]
+    fn unpack_through_symlink()(dir) {
+        let (to, outside) = join_all!(dir, "to", "outside");
+        dir.mkdirp(&to);
+        dir.mkdirp(&outside);
+        // a symlink that already exists in the destination must not be followed
+        std::os::unix::fs::symlink(&outside, to.join("link")).unwrap();
+        let mut builder = Builder::new(Vec::new());
+        append_raw(&mut builder, "link/file.txt", b"evil");
+        let archive = builder.into_inner().unwrap();
+
+        let err = crate::unpack_dir(Cursor::new(archive), &to).unwrap_err();
+
+        assert!(matches!(err, Error::PathEscape { .. }));
+        assert!(!outside.join("file.txt").exists());
+    }
+}
+
+#[cfg(feature = "tar")]
+fs_fn! {
+    #[test]
+    fn pack_unpack_with_filter()(dir) {
+        let (create_dir, create_file, skipped_file, from, to) =
+            join_all!(dir, "from/target/debug", "from/hello.txt", "from/target/debug/out", "from", "to");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+        dir.touch_with_contents(&skipped_file);
+
+        let mut options = crate::ArchiveOptions::new();
+        options.filter(|entry| entry.path().file_name() != Some("target".as_ref()));
+        let archive = crate::pack_dir_with(&from, Vec::new(), &options).unwrap();
+        crate::unpack_dir(Cursor::new(archive), &to).unwrap();
+
+        assert_file_contents_eq!(create_file, to.join("hello.txt"));
+        assert!(!to.join("target").exists());
+    }
+}
+
+#[cfg(all(unix, feature = "tar"))]
+fs_fn! {
+    #[test]
+    fn pack_fifo()(dir) {
+        use std::os::unix::fs::FileTypeExt;
+
+        let (fifo, from, to, skipped) = join_all!(dir, "from/fifo", "from", "to", "skipped");
+        dir.mkdirp(&from);
+        super::utils::make_fifo(&fifo);
+
+        let err = crate::pack_dir(&from, Vec::new()).unwrap_err();
+        assert!(matches!(err, Error::SpecialFile { .. }));
+
+        let mut options = crate::ArchiveOptions::new();
+        options.special_files(crate::SpecialFiles::Recreate);
+        let archive = crate::pack_dir_with(&from, Vec::new(), &options).unwrap();
+        let err = crate::unpack_dir(Cursor::new(archive.clone()), &to).unwrap_err();
+        assert!(matches!(err, Error::SpecialFile { .. }));
+
+        crate::unpack_dir_with(Cursor::new(archive.clone()), &to, &options).unwrap();
+        assert!(std::fs::symlink_metadata(to.join("fifo")).unwrap().file_type().is_fifo());
+
+        options.special_files(crate::SpecialFiles::Skip);
+        crate::unpack_dir_with(Cursor::new(archive), &skipped, &options).unwrap();
+        assert!(skipped.exists() && skipped.join("fifo").symlink_metadata().is_err());
+    }
+}
+
+#[cfg(all(unix, feature = "tar"))]
+fs_fn! {
+    #[test]
+    fn unpack_setuid()(dir) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let to = dir.join("to");
+        let mut builder = Builder::new(Vec::new());
+        let mut header = file_header(4);
+        header.set_mode(0o4755);
+        builder.append_data(&mut header, "setuid", &b"evil"[..]).unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        crate::unpack_dir(Cursor::new(archive), &to).unwrap();
+
+        let mode = std::fs::metadata(to.join("setuid")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+}
+
+#[cfg(feature = "zip")]
+fs_fn! {
+    #[test]
+    fn zip_unzip()(dir) {
+        let (create_dir, create_file, from, to) = join_all!(dir, "from/b/c", "from/b/hello.txt", "from", "to");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+
+        let mut options = crate::ArchiveOptions::new();
+        options.compression(crate::ZipCompression::Zstd(3));
+        let archive = crate::zip_dir_with(&from, Cursor::new(Vec::new()), &options).unwrap();
+        crate::unzip_dir(archive, &to).unwrap();
+
+        assert_paths_exists!(to.join("b/c"));
+        assert_file_contents_eq!(create_file, to.join("b/hello.txt"));
+    }
+}
+
+#[cfg(all(feature = "zip", feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn zip_unzip_par()(dir) {
+        let (create_dir, from, to) = join_all!(dir, "from/b/c", "from", "to");
+        dir.mkdirp(&create_dir);
+        for i in 0..50 {
+            dir.touch_with_contents(create_dir.join(format!("file{}", i)));
+        }
+
+        let archive = crate::zip_dir_par(&from, Cursor::new(Vec::new())).unwrap();
+        crate::unzip_dir(archive, &to).unwrap();
+
+        for i in 0..50 {
+            let name = format!("file{}", i);
+            assert_file_contents_eq!(create_dir.join(&name), to.join("b/c").join(&name));
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "zip"))]
+fs_fn! {
+    #[test]
+    fn zip_unix_mode()(dir) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (create_file, from, to) = join_all!(dir, "from/script.sh", "from", "to");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+        std::fs::set_permissions(&create_file, std::fs::Permissions::from_mode(0o751)).unwrap();
+
+        let archive = crate::zip_dir(&from, Cursor::new(Vec::new())).unwrap();
+        crate::unzip_dir(archive, &to).unwrap();
+
+        let mode = std::fs::metadata(to.join("script.sh")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o751);
+    }
+}
+
+#[cfg(feature = "zip")]
+fs_fn! {
+    #[test]
+    fn unzip_slip()(dir) {
+        use std::io::Write;
+
+        let to = dir.join("nested/to");
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("../evil.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+        let archive = zip.finish().unwrap();
+
+        let err = crate::unzip_dir(archive, &to).unwrap_err();
+
+        assert!(matches!(err, Error::PathEscape { .. }));
+        assert!(!dir.join("nested/evil.txt").exists());
+    }
+}
+
+#[cfg(feature = "zip")]
+fs_fn! {
+    #[test]
+    fn unzip_chained_symlink_escape()(dir) {
+        let to = dir.join("nested/to");
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_symlink("s", ".", options).unwrap();
+        zip.add_symlink("t", "s/..", options).unwrap();
+        let archive = zip.finish().unwrap();
+
+        let err = crate::unzip_dir(archive, &to).unwrap_err();
+
+        assert!(matches!(err, Error::PathEscape { .. }));
+        assert!(to.join("t").symlink_metadata().is_err());
+    }
+}
+
+#[cfg(all(unix, feature = "zip"))]
+fs_fn! {
+    #[test]
+    fn zip_fifo()(dir) {
+        let (fifo, create_file, from, to) = join_all!(dir, "from/fifo", "from/hello.txt", "from", "to");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+        super::utils::make_fifo(&fifo);
+
+        let err = crate::zip_dir(&from, Cursor::new(Vec::new())).unwrap_err();
+        assert!(matches!(err, Error::SpecialFile { .. }));
+
+        let mut options = crate::ArchiveOptions::new();
+        options.special_files(crate::SpecialFiles::Skip);
+        let archive = crate::zip_dir_with(&from, Cursor::new(Vec::new()), &options).unwrap();
+        crate::unzip_dir(archive, &to).unwrap();
+
+        assert_file_contents_eq!(create_file, to.join("hello.txt"));
+        assert!(to.join("fifo").symlink_metadata().is_err());
+    }
+}
+
+#[cfg(all(unix, feature = "zip"))]
+fs_fn! {
+    #[test]
+    fn unzip_setuid_and_mtime()(dir) {
+        use std::io::Write;
+        use std::os::unix::fs::PermissionsExt;
+
+        let to = dir.join("to");
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let modified = zip::DateTime::from_date_and_time(2001, 2, 3, 4, 5, 6).unwrap();
+        let options = zip::write::SimpleFileOptions::default()
+            .unix_permissions(0o4755)
+            .last_modified_time(modified);
+        zip.start_file("setuid", options).unwrap();
+        zip.write_all(b"evil").unwrap();
+        let archive = zip.finish().unwrap();
+
+        crate::unzip_dir(archive, &to).unwrap();
+
+        let metadata = std::fs::metadata(to.join("setuid")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o755);
+        let secs = metadata.modified().unwrap().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(secs, 981_173_106);
+    }
+}
+
+#[cfg(all(unix, feature = "zip"))]
+fs_fn! {
+    #[test]
+    fn unzip_dir_mode_and_mtime()(dir) {
+        use std::io::Write;
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let to = dir.join("to");
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = |mode, year| {
+            let modified = zip::DateTime::from_date_and_time(year, 2, 3, 4, 5, 6).unwrap();
+            zip::write::SimpleFileOptions::default()
+                .unix_permissions(mode)
+                .last_modified_time(modified)
+        };
+        zip.add_directory("read_only/", options(0o555, 2001)).unwrap();
+        zip.add_directory("read_only/sub/", options(0o750, 2002)).unwrap();
+        zip.start_file("read_only/sub/file", options(0o644, 2003)).unwrap();
+        zip.write_all(b"file").unwrap();
+        let archive = zip.finish().unwrap();
+
+        let unzipped = crate::unzip_dir(archive, &to);
+        let metadata = |path: &str| std::fs::metadata(to.join(path)).unwrap();
+        let (read_only, sub) = (metadata("read_only"), metadata("read_only/sub"));
+        let _ = std::fs::set_permissions(to.join("read_only"), std::fs::Permissions::from_mode(0o755));
+
+        unzipped.unwrap();
+        assert_eq!(read_only.permissions().mode() & 0o7777, 0o555);
+        assert_eq!(sub.permissions().mode() & 0o7777, 0o750);
+        let secs = |metadata: std::fs::Metadata| {
+            metadata.modified().unwrap().duration_since(UNIX_EPOCH).unwrap().as_secs()
+        };
+        assert_eq!(secs(read_only), 981_173_106);
+        assert_eq!(secs(sub), 1_012_709_106);
+
+        // the times survive a round trip, zip archives store them with two seconds precision
+        let (from, round_trip) = join_all!(dir, "from", "round_trip");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(from.join("file"));
+        let modified = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        std::fs::File::options().write(true).open(from.join("file")).unwrap().set_modified(modified).unwrap();
+        let archive = crate::zip_dir(&from, Cursor::new(Vec::new())).unwrap();
+        crate::unzip_dir(archive, &round_trip).unwrap();
+        assert_eq!(secs(std::fs::metadata(round_trip.join("file")).unwrap()), 1_500_000_000);
+    }
+}
+
+#[cfg(all(feature = "zip", feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn zip_unzip_par_large_file()(dir) {
+        let (create_file, small_file, from, to) = join_all!(dir, "from/large", "from/small", "from", "to");
+        dir.mkdirp(&from);
+        std::fs::write(&create_file, vec![7; 17 * 1024 * 1024]).unwrap();
+        dir.touch_with_contents(&small_file);
+
+        let archive = crate::zip_dir_par(&from, Cursor::new(Vec::new())).unwrap();
+        crate::unzip_dir(archive, &to).unwrap();
+
+        assert_file_contents_eq!(create_file, to.join("large"));
+        assert_file_contents_eq!(small_file, to.join("small"));
+    }
+}
@@ -0,0 +1,81 @@
+use test_dir::{assert_file_contents_eq, assert_paths_exists, join_all, TestDir};
+
+#[tokio::test]
+async fn copy() {
+    let dir = TestDir::new();
+    let (from, to) = join_all!(dir, "from", "to");
+    dir.touch_with_contents(&from);
+
+    crate::r#async::copy(&from, &to).await.unwrap();
+
+    assert_file_contents_eq!(&from, &to);
+    dir.close();
+}
+
+#[tokio::test]
+async fn copy_dir_all() {
+    let dir = TestDir::new();
+    let (create_dir, from, to) = join_all!(dir, "from/b/c/d", "from", "to");
+    dir.mkdirp(&create_dir);
+    for i in 0..100 {
+        dir.touch_with_contents(create_dir.join(format!("file{}", i)));
+    }
+
+    let mut options = crate::CopyOptions::new();
+    options.concurrency(4);
+    let copied = crate::r#async::copy_dir_all_with(&from, &to, &options)
+        .await
+        .unwrap();
+
+    assert_eq!(copied.bytes, 100 * 512);
+    assert_eq!((copied.files, copied.dirs), (100, 4));
+    assert_file_contents_eq!(create_dir.join("file42"), to.join("b/c/d/file42"));
+    dir.close();
+}
+
+#[tokio::test]
+async fn move_dir_all() {
+    let dir = TestDir::new();
+    let (create_file, from, to) = join_all!(dir, "from/hello.txt", "from", "to");
+    dir.mkdirp(&from);
+    dir.touch_with_contents(&create_file);
+
+    crate::r#async::move_dir_all(&from, &to).await.unwrap();
+
+    assert_paths_exists!(to.join("hello.txt"));
+    assert!(!from.exists());
+    dir.close();
+}
+
+#[tokio::test]
+async fn copy_dir_all_not_found() {
+    let dir = TestDir::new();
+    let (from, to) = join_all!(dir, "from", "to");
+
+    let err = crate::r#async::copy_dir_all(&from, &to).await.unwrap_err();
+
+    assert_eq!(err.io_error_kind(), std::io::ErrorKind::NotFound);
+    dir.close();
+}
+
+#[tokio::test]
+async fn move_dir_all_with_lock() {
+    let dir = TestDir::new();
+    let (from, to) = join_all!(dir, "from", "to");
+    dir.mkdirp(from.join("sub"));
+    dir.touch_with_contents(from.join("sub/file"));
+
+    let mut options = crate::CopyOptions::new();
+    options.lock(
+        crate::LockOptions::new()
+            .timeout(std::time::Duration::from_secs(10))
+            .clone(),
+    );
+    crate::r#async::move_dir_all_with(&from, &to, &options)
+        .await
+        .unwrap();
+
+    assert_paths_exists!(to.join("sub/file"), dir.join(".to.lock"));
+    assert!(!from.exists());
+    dir.close();
+}
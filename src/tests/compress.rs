@@ -0,0 +1,177 @@
+use test_dir::{assert_file_contents_eq, assert_paths_exists, fs_fn, join_all};
+
+use crate::compress::{self, Codec};
+
+#[cfg(feature = "zstd")]
+fs_fn! {
+    #[test]
+    fn zstd_round_trip()(dir) {
+        let (create_dir, create_file, from, compressed, to) =
+            join_all!(dir, "from/b/c", "from/b/hello.txt", "from", "compressed", "to");
+        dir.mkdirp(create_dir);
+        std::fs::write(&create_file, "hello ".repeat(1000)).unwrap();
+
+        let report = compress::copy_dir_all_compressed(&from, &compressed, Codec::Zstd(3)).unwrap();
+        assert_paths_exists!(compressed.join("b/c"), compressed.join("b/hello.txt.zst"));
+        assert_eq!(report.files, 1);
+        assert_eq!(report.original_bytes, 6000);
+        assert!(report.compressed_bytes < report.original_bytes);
+
+        let report = compress::copy_dir_all_decompressed(&compressed, &to).unwrap();
+        assert_eq!(report.original_bytes, 6000);
+        assert_file_contents_eq!(create_file, to.join("b/hello.txt"));
+    }
+}
+
+#[cfg(feature = "gzip")]
+fs_fn! {
+    #[test]
+    fn gzip_round_trip()(dir) {
+        let (create_dir, create_file, from, compressed, to) =
+            join_all!(dir, "from/b/c", "from/b/hello.txt", "from", "compressed", "to");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+
+        compress::copy_dir_all_compressed(&from, &compressed, Codec::Gzip(6)).unwrap();
+        assert_paths_exists!(compressed.join("b/hello.txt.gz"));
+
+        compress::copy_dir_all_decompressed(&compressed, &to).unwrap();
+        assert_file_contents_eq!(create_file, to.join("b/hello.txt"));
+    }
+}
+
+#[cfg(all(feature = "zstd", feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn zstd_round_trip_par()(dir) {
+        let (create_dir, create_file, create_file2, from, compressed, to) = join_all!(
+            dir,
+            "from/b/c",
+            "from/b/hello.txt",
+            "from/b/c/world.txt",
+            "from",
+            "compressed",
+            "to"
+        );
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+        dir.touch_with_contents(&create_file2);
+
+        let report = compress::copy_dir_all_compressed_par(&from, &compressed, Codec::Zstd(3)).unwrap();
+        assert_eq!(report.files, 2);
+
+        compress::copy_dir_all_decompressed_par(&compressed, &to).unwrap();
+        assert_file_contents_eq!(create_file, to.join("b/hello.txt"));
+        assert_file_contents_eq!(create_file2, to.join("b/c/world.txt"));
+    }
+}
+
+#[cfg(feature = "zstd")]
+fs_fn! {
+    #[test]
+    fn decompress_copies_unknown_files()(dir) {
+        let (create_file, from, to) = join_all!(dir, "from/notes.txt", "from", "to");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+
+        let report = compress::copy_dir_all_decompressed(&from, &to).unwrap();
+
+        assert_eq!(report.files, 0);
+        assert_file_contents_eq!(create_file, to.join("notes.txt"));
+    }
+}
+
+#[cfg(all(unix, feature = "zstd"))]
+fs_fn! {
+    #[test]
+    fn compress_symlinks_and_fifos()(dir) {
+        let (fifo, link, create_file, from, compressed) =
+            join_all!(dir, "from/fifo", "from/link", "from/hello.txt", "from", "compressed");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+        super::utils::make_fifo(&fifo);
+        std::os::unix::fs::symlink("hello.txt", &link).unwrap();
+
+        let err = compress::copy_dir_all_compressed(&from, &compressed, Codec::Zstd(3)).unwrap_err();
+        assert!(matches!(err, crate::Error::SpecialFile { .. }));
+        std::fs::remove_dir_all(&compressed).unwrap();
+
+        let mut options = crate::CopyOptions::new();
+        options.special_files(crate::SpecialFiles::Skip);
+        let report = compress::copy_dir_all_compressed_with(&from, &compressed, Codec::Zstd(3), &options).unwrap();
+
+        assert_eq!(report.files, 1);
+        assert_eq!(report.special_files.len(), 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].path, fifo);
+        // symlinks are copied like copy_dir_all does, without compressing them
+        assert_file_contents_eq!(create_file, compressed.join("link"));
+        assert!(compressed.join("fifo").symlink_metadata().is_err());
+    }
+}
+
+#[cfg(all(feature = "zstd", feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn compress_par_with_conflict()(dir) {
+        let (create_file, from, compressed) = join_all!(dir, "from/hello.txt", "from", "compressed");
+        dir.mkdirp(&from);
+        dir.mkdirp(&compressed);
+        dir.touch_with_contents(&create_file);
+        std::fs::write(compressed.join("hello.txt.zst"), "existing").unwrap();
+
+        let mut options = crate::CopyOptions::new();
+        options.on_conflict(|_, _, _| crate::ConflictAction::Skip);
+        let report = compress::copy_dir_all_compressed_par_with(&from, &compressed, Codec::Zstd(3), &options).unwrap();
+
+        assert_eq!(report.files, 0);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].to, compressed.join("hello.txt.zst"));
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(std::fs::read(compressed.join("hello.txt.zst")).unwrap(), b"existing");
+    }
+}
+
+#[cfg(all(unix, feature = "zstd"))]
+fs_fn! {
+    #[test]
+    fn compress_read_only_dir()(dir) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (read_only, file, from, compressed) = join_all!(dir, "from/read_only", "from/read_only/hello.txt", "from", "compressed");
+        dir.mkdirp(&read_only);
+        dir.touch_with_contents(&file);
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let copied = compress::copy_dir_all_compressed(&from, &compressed, Codec::Zstd(3));
+        let mode = std::fs::metadata(compressed.join("read_only")).map(|metadata| metadata.permissions().mode());
+        for path in [&read_only, &compressed.join("read_only")] {
+            let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755));
+        }
+
+        assert_eq!(copied.unwrap().files, 1);
+        assert_eq!(mode.unwrap() & 0o777, 0o555);
+    }
+}
+
+#[cfg(all(unix, feature = "zstd", feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn compress_par_read_only_dir()(dir) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (read_only, file, from, compressed) = join_all!(dir, "from/read_only", "from/read_only/hello.txt", "from", "compressed");
+        dir.mkdirp(&read_only);
+        dir.touch_with_contents(&file);
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let copied = compress::copy_dir_all_compressed_par(&from, &compressed, Codec::Zstd(3));
+        let mode = std::fs::metadata(compressed.join("read_only")).map(|metadata| metadata.permissions().mode());
+        for path in [&read_only, &compressed.join("read_only")] {
+            let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755));
+        }
+
+        assert_eq!(copied.unwrap().files, 1);
+        assert_eq!(mode.unwrap() & 0o777, 0o555);
+    }
+}
@@ -0,0 +1,74 @@
+use test_dir::{assert_file_contents_eq, fs_fn, join_all};
+
+use crate::dedupe::{dedupe, find_duplicates, DedupeMethod};
+
+fs_fn! {
+    #[test]
+    fn find_duplicates_groups()(dir) {
+        let (a, b, c, other, empty, empty2) = join_all!(
+            dir,
+            "one/a.txt",
+            "one/nested/b.txt",
+            "two/c.txt",
+            "two/other.txt",
+            "one/empty",
+            "two/empty"
+        );
+        dir.mkdirp(dir.join("one/nested"));
+        dir.mkdirp(dir.join("two"));
+        for path in &[&a, &b, &c] {
+            std::fs::write(path, "same ".repeat(2000)).unwrap();
+        }
+        // same size and same first block, but a different end
+        std::fs::write(&other, "same ".repeat(1999) + "diff ").unwrap();
+        std::fs::write(&empty, "").unwrap();
+        std::fs::write(&empty2, "").unwrap();
+
+        let groups = find_duplicates(&[dir.join("one"), dir.join("two")]).unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].paths(), &[a, b, c][..]);
+        assert_eq!(groups[0].size(), 10000);
+        assert_eq!(groups[0].wasted(), 20000);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dedupe_hardlink()(dir) {
+        let (a, b) = join_all!(dir, "a.txt", "b.txt");
+        std::fs::write(&a, "hello").unwrap();
+        std::fs::write(&b, "hello").unwrap();
+
+        let groups = find_duplicates([dir.path()]).unwrap();
+        let freed = dedupe(&groups, DedupeMethod::Hardlink).unwrap();
+
+        assert_eq!(freed, 5);
+        assert_file_contents_eq!(&a, &b);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(std::fs::metadata(&a).unwrap().ino(), std::fs::metadata(&b).unwrap().ino());
+        }
+
+        // linked files are not duplicates anymore
+        #[cfg(unix)]
+        assert!(find_duplicates([dir.path()]).unwrap().is_empty());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dedupe_skips_changed_files()(dir) {
+        let (a, b) = join_all!(dir, "a.txt", "b.txt");
+        std::fs::write(&a, "hello").unwrap();
+        std::fs::write(&b, "hello").unwrap();
+
+        let groups = find_duplicates([dir.path()]).unwrap();
+        std::fs::write(&b, "world").unwrap();
+        let freed = dedupe(&groups, DedupeMethod::Hardlink).unwrap();
+
+        assert_eq!(freed, 0);
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "world");
+    }
+}
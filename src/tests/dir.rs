@@ -0,0 +1,257 @@
+use std::io::{Read, Write};
+use std::os::fd::AsFd;
+use std::path::Path;
+
+use test_dir::{assert_file_contents_eq, fs_fn, join_all};
+
+use super::utils::make_fifo;
+use crate::dir::resolve_components;
+use crate::{Dir, Error};
+
+fn assert_escapes<T: std::fmt::Debug>(result: crate::Result<T>) {
+    match result {
+        Err(Error::PathEscape { .. }) => {}
+        result => panic!("expected a path escape, got {:?}", result),
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_copy_and_remove()(dir) {
+        let (root, outside) = join_all!(dir, "root", "outside");
+        dir.mkdirp(root.join("src/nested"));
+        dir.mkdirp(&outside);
+        std::fs::write(root.join("src/a.txt"), "a").unwrap();
+        std::fs::write(root.join("src/nested/b.txt"), "b").unwrap();
+        std::os::unix::fs::symlink("../a.txt", root.join("src/nested/link")).unwrap();
+
+        let handle = Dir::open(&root).unwrap();
+        assert_eq!(handle.copy("src/a.txt", "copy.txt").unwrap(), 1);
+        let report = handle.copy_dir_all("src", "dst").unwrap();
+
+        assert_eq!((report.files, report.dirs, report.symlinks), (2, 2, 1));
+        assert_file_contents_eq!(root.join("src/nested/b.txt"), root.join("dst/nested/b.txt"));
+        assert_eq!(std::fs::read(root.join("dst/nested/link")).unwrap(), b"a");
+        assert!(handle.metadata("dst/nested").unwrap().is_dir());
+
+        handle.remove_dir_all("dst").unwrap();
+        handle.remove_file("copy.txt").unwrap();
+        assert!(!root.join("dst").exists());
+        assert!(!root.join("copy.txt").exists());
+
+        let report = handle.move_dir_all("src", "moved").unwrap();
+        assert_eq!(report.files, 2);
+        assert!(!root.join("src").exists());
+        assert!(root.join("moved/nested/b.txt").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_between_handles()(dir) {
+        let (a, b) = join_all!(dir, "a", "b");
+        dir.mkdirp(a.join("tree/sub"));
+        dir.mkdirp(&b);
+        std::fs::write(a.join("tree/sub/file"), "hello").unwrap();
+
+        let (a_handle, b_handle) = (Dir::open(&a).unwrap(), Dir::open(&b).unwrap());
+        a_handle.copy_dir_all_to("tree", &b_handle, "tree").unwrap();
+        a_handle.copy_to("tree/sub/file", &b_handle, "file").unwrap();
+        a_handle.move_file_to("tree/sub/file", &b_handle, "moved").unwrap();
+
+        assert_eq!(std::fs::read_to_string(b.join("tree/sub/file")).unwrap(), "hello");
+        assert_eq!(std::fs::read_to_string(b.join("file")).unwrap(), "hello");
+        assert_eq!(std::fs::read_to_string(b.join("moved")).unwrap(), "hello");
+        assert!(!a.join("tree/sub/file").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_rejects_escapes()(dir) {
+        let (root, outside) = join_all!(dir, "root", "outside");
+        dir.mkdirp(root.join("sub"));
+        dir.mkdirp(&outside);
+        std::fs::write(outside.join("secret"), "secret").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("absolute")).unwrap();
+        std::os::unix::fs::symlink("../../outside", root.join("sub/relative")).unwrap();
+
+        let handle = Dir::open(&root).unwrap();
+
+        assert_escapes(handle.open_file("../outside/secret"));
+        assert_escapes(handle.open_file("sub/../../outside/secret"));
+        assert_escapes(handle.open_file(outside.join("secret")));
+        assert_escapes(handle.open_file("absolute/secret"));
+        assert_escapes(handle.open_file("sub/relative/secret"));
+        assert_escapes(handle.copy("absolute/secret", "stolen"));
+        assert_escapes(handle.remove_dir_all("absolute/secret"));
+        assert_escapes(handle.copy_dir_all("absolute", "stolen"));
+        assert_escapes(handle.create_dir_all("../outside/new"));
+
+        // `..` that stays inside is fine
+        handle.create_file("sub/../inside").unwrap();
+        assert!(root.join("inside").exists());
+        // the symlinks themselves are removed without touching their targets
+        handle.remove_dir_all("absolute").unwrap();
+        assert_eq!(std::fs::read_to_string(outside.join("secret")).unwrap(), "secret");
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_copy_dir_all_rejects_escaping_symlinks()(dir) {
+        let (root, outside) = join_all!(dir, "root", "outside");
+        dir.mkdirp(root.join("src"));
+        dir.mkdirp(&outside);
+        std::fs::write(outside.join("secret"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret"), root.join("src/link")).unwrap();
+
+        let handle = Dir::open(&root).unwrap();
+
+        assert_escapes(handle.copy_dir_all("src", "dst"));
+        assert!(!root.join("dst/link").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_destination_inside_source()(dir) {
+        let root = dir.join("root");
+        dir.mkdirp(root.join("src"));
+        std::fs::write(root.join("src/file"), "file").unwrap();
+
+        let handle = Dir::open(&root).unwrap();
+
+        assert!(matches!(
+            handle.copy_dir_all("src", "src/inner"),
+            Err(Error::DestinationInsideSource { .. })
+        ));
+        assert!(matches!(
+            handle.copy("src/file", "src/./file"),
+            Err(Error::SameFile { .. })
+        ));
+        assert_eq!(std::fs::read_to_string(root.join("src/file")).unwrap(), "file");
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_files()(dir) {
+        let root = dir.join("root");
+        dir.mkdirp(&root);
+
+        let handle = Dir::open(&root).unwrap();
+        handle.create_dir_all("a/b/c").unwrap();
+        handle.create_dir_all("a/b").unwrap();
+        handle.create_file("a/b/c/file").unwrap().write_all(b"hello").unwrap();
+
+        let nested = handle.open_dir("a/b").unwrap();
+        assert_eq!(nested.path(), root.join("a/b"));
+        let mut contents = String::new();
+        nested.open_file("c/file").unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+        // the nested handle is confined to its own directory
+        assert_escapes(nested.open_file("../b/c/file"));
+
+        nested.rename_to("c/file", &handle, "renamed").unwrap();
+        assert!(root.join("renamed").exists());
+        nested.remove_dir("c").unwrap();
+        assert!(!root.join("a/b/c").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_resolve_components()(dir) {
+        // the component-wise fallback that is used without openat2
+        let (root, outside) = join_all!(dir, "root", "outside");
+        dir.mkdirp(root.join("sub/deeper"));
+        dir.mkdirp(&outside);
+        std::fs::write(root.join("sub/deeper/file"), "file").unwrap();
+        std::fs::write(outside.join("secret"), "secret").unwrap();
+        std::os::unix::fs::symlink("deeper/file", root.join("sub/inside")).unwrap();
+        std::os::unix::fs::symlink("../../outside", root.join("sub/relative")).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("absolute")).unwrap();
+        std::os::unix::fs::symlink("loop", root.join("loop")).unwrap();
+
+        let handle = Dir::open(&root).unwrap();
+        let resolve = |path: &str| {
+            resolve_components(handle.as_fd(), Path::new(path), libc::O_RDONLY, 0)
+        };
+        let escapes = |path: &str| resolve(path).unwrap_err().raw_os_error() == Some(libc::EXDEV);
+
+        let mut contents = String::new();
+        std::fs::File::from(resolve("sub/./inside").unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "file");
+        resolve("sub/deeper/../../sub/deeper/file").unwrap();
+        resolve("").unwrap();
+        // with O_PATH the symlink itself could be opened, it still has to be followed
+        #[cfg(target_os = "linux")]
+        {
+            let fd = resolve_components(handle.as_fd(), Path::new("sub/inside"), libc::O_PATH, 0);
+            assert!(std::fs::File::from(fd.unwrap()).metadata().unwrap().is_file());
+        }
+
+        assert!(escapes(".."));
+        assert!(escapes("sub/../../outside/secret"));
+        assert!(escapes("sub/relative/secret"));
+        assert!(escapes("absolute/secret"));
+        assert!(escapes("/etc/passwd"));
+        assert_eq!(resolve("loop").unwrap_err().raw_os_error(), Some(libc::ELOOP));
+        assert_eq!(resolve("missing").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_copy_fifo()(dir) {
+        let root = join_all!(dir, "root");
+        dir.mkdirp(&root);
+        make_fifo(&root.join("fifo"));
+        std::os::unix::fs::symlink("fifo", root.join("link")).unwrap();
+
+        let handle = Dir::open(&root).unwrap();
+        for from in ["fifo", "link"] {
+            match handle.copy(from, "copy") {
+                Err(Error::SpecialFile { path, .. }) => assert_eq!(path, root.join(from)),
+                result => panic!("expected a special file error, got {:?}", result),
+            }
+        }
+        assert!(!root.join("copy").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn dir_copy_dir_all_symlinks()(dir) {
+        let root = join_all!(dir, "root");
+        dir.mkdirp(root.join("src/real"));
+        dir.mkdirp(root.join("cycle/sub"));
+        dir.mkdirp(root.join("special"));
+        std::fs::write(root.join("src/real/file"), "file").unwrap();
+        std::os::unix::fs::symlink("real", root.join("src/dir_link")).unwrap();
+        std::os::unix::fs::symlink("..", root.join("cycle/sub/up")).unwrap();
+        make_fifo(&root.join("fifo"));
+        std::os::unix::fs::symlink("../fifo", root.join("special/link")).unwrap();
+
+        let handle = Dir::open(&root).unwrap();
+        let report = handle.copy_dir_all("src", "dst").unwrap();
+        assert_eq!((report.files, report.dirs), (2, 3));
+        assert!(root.join("dst/dir_link").symlink_metadata().unwrap().is_dir());
+        assert_file_contents_eq!(root.join("src/real/file"), root.join("dst/dir_link/file"));
+
+        match handle.copy_dir_all("cycle", "cycle_copy") {
+            Err(Error::IoExt { source, .. }) => {
+                assert_eq!(source.raw_os_error(), Some(libc::ELOOP))
+            }
+            result => panic!("expected a symlink loop, got {:?}", result),
+        }
+        match handle.copy_dir_all("special", "special_copy") {
+            Err(Error::SpecialFile { path, .. }) => assert_eq!(path, root.join("special/link")),
+            result => panic!("expected a special file error, got {:?}", result),
+        }
+        assert!(!root.join("special_copy/link").exists());
+    }
+}
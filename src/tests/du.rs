@@ -0,0 +1,80 @@
+use test_dir::{fs_fn, join_all};
+
+use crate::{disk_usage, disk_usage_with, DuOptions};
+
+fs_fn! {
+    #[test]
+    fn disk_usage_counts()(dir) {
+        let (root, a, b, big) = join_all!(dir, "root", "root/a.txt", "root/sub/b.txt", "root/sub/deeper/big.txt");
+        dir.mkdirp(dir.join("root/sub/deeper"));
+        std::fs::write(&a, "a".repeat(10)).unwrap();
+        std::fs::write(&b, "b".repeat(20)).unwrap();
+        std::fs::write(&big, "c".repeat(30)).unwrap();
+
+        let usage = disk_usage(&root).unwrap();
+
+        assert_eq!(usage.apparent_size, 60);
+        assert_eq!(usage.files, 3);
+        assert_eq!(usage.dirs, 3);
+        assert_eq!(usage.largest[0], (big, 30));
+        assert_eq!(usage.children.len(), 1);
+        assert_eq!(usage.children[0].path, root.join("sub"));
+        assert_eq!(usage.children[0].apparent_size, 50);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn disk_usage_options()(dir) {
+        let (root, a, b, big) = join_all!(dir, "root", "root/a.txt", "root/sub/b.txt", "root/sub/deeper/big.txt");
+        dir.mkdirp(dir.join("root/sub/deeper"));
+        std::fs::write(&a, "a".repeat(10)).unwrap();
+        std::fs::write(&b, "b".repeat(20)).unwrap();
+        std::fs::write(&big, "c".repeat(30)).unwrap();
+
+        let usage = disk_usage_with(&root, DuOptions::new().max_depth(2)).unwrap();
+        assert_eq!(usage.apparent_size, 30);
+        assert_eq!(usage.dirs, 3);
+
+        let usage = disk_usage_with(
+            &root,
+            DuOptions::new().filter(|entry| entry.path().file_name() != Some("deeper".as_ref())),
+        )
+        .unwrap();
+        assert_eq!(usage.apparent_size, 30);
+        assert_eq!(usage.dirs, 2);
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn disk_usage_hardlinks_once()(dir) {
+        let (root, a, b) = join_all!(dir, "root", "root/a.txt", "root/b.txt");
+        dir.mkdirp(&root);
+        std::fs::write(&a, "a".repeat(100_000)).unwrap();
+        let single = disk_usage(&root).unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+
+        let usage = disk_usage(&root).unwrap();
+
+        assert_eq!(usage.files, 2);
+        assert_eq!(usage.apparent_size, 200_000);
+        assert_eq!(usage.allocated_size, single.allocated_size);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn disk_usage_deep_tree()(dir) {
+        let root = join_all!(dir, "root");
+        let deepest = (0..1500).fold(root.clone(), |path, _| path.join("d"));
+        dir.mkdirp(&deepest);
+        std::fs::write(deepest.join("file"), "deep").unwrap();
+
+        // the walk doesn't recurse per level, so a small stack is enough
+        let pool = rayon::ThreadPoolBuilder::new().stack_size(256 * 1024).build().unwrap();
+        let usage = pool.install(|| disk_usage(&root)).unwrap();
+        assert_eq!((usage.files, usage.dirs, usage.apparent_size), (1, 1501, 4));
+    }
+}
@@ -1,4 +1,6 @@
 use super::utils::clone_repo;
+#[cfg(unix)]
+use super::utils::make_fifo;
 use test_dir::{assert_file_contents_eq, assert_paths_exists, fs_fn, join_all};
 
 fs_fn! {
@@ -118,3 +120,862 @@ fs_fn! {
         assert_paths_exists!(from, to);
     }
 }
+
+fs_fn! {
+    #[test]
+    fn copy_with_durability()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.touch_with_contents(&from);
+
+        let mut options = crate::CopyOptions::new();
+        options.durability(crate::Durability::FilesAndDirs);
+        crate::copy_with(&from, &to, &options).unwrap();
+
+        assert_file_contents_eq!(&from, &to);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn move_dir_all_with_durability()(dir) {
+        let (create_dir, create_file, from, to) = join_all!(dir, "from/b/c", "from/b/hello.txt", "from", "to");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+
+        let mut options = crate::CopyOptions::new();
+        options.durability(crate::Durability::FilesAndDirs);
+        crate::move_dir_all_with(&from, &to, &options).unwrap();
+
+        assert_paths_exists!(to.join("b/c"), to.join("b/hello.txt"));
+        assert!(!from.exists());
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn move_dir_all_par_with_durability()(dir) {
+        let (create_dir, create_file, from, to) = join_all!(dir, "from/b/c", "from/b/c/hello.txt", "from", "to");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+
+        let mut options = crate::CopyOptions::new();
+        options.durability(crate::Durability::Files);
+        crate::move_dir_all_par_with(&from, &to, &options).unwrap();
+
+        assert_paths_exists!(to.join("b/c/hello.txt"));
+        assert!(!from.exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_dir_all_with_preflight()(dir) {
+        let (create_dir, create_file, from, to) = join_all!(dir, "from/b/c", "from/b/hello.txt", "from", "to");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+
+        let mut options = crate::CopyOptions::new();
+        options.preflight(true);
+        crate::copy_dir_all_with(&from, &to, &options).unwrap();
+
+        assert_file_contents_eq!(create_file, to.join("b/hello.txt"));
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_with_preflight_problems()(dir) {
+        use std::os::unix::fs::PermissionsExt;
+
+        // root can read and write everything
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        let (create_file, from, readonly, to) = join_all!(dir, "from/secret.txt", "from", "readonly", "readonly/to");
+        dir.mkdirp(&from);
+        dir.mkdirp(&readonly);
+        dir.touch_with_contents(&create_file);
+        std::fs::set_permissions(&create_file, std::fs::Permissions::from_mode(0o000)).unwrap();
+        std::fs::set_permissions(&readonly, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let mut options = crate::CopyOptions::new();
+        options.preflight(true);
+        let err = crate::copy_dir_all_with(&from, &to, &options).unwrap_err();
+
+        std::fs::set_permissions(&readonly, std::fs::Permissions::from_mode(0o755)).unwrap();
+        match err {
+            crate::Error::Preflight { problems } => {
+                assert_eq!(problems.len(), 2);
+                assert!(matches!(&problems[0], crate::PreflightProblem::NotWritable { path } if path == &readonly));
+                assert!(matches!(&problems[1], crate::PreflightProblem::Unreadable { path, .. } if path == &create_file));
+            }
+            err => panic!("unexpected error {}", err),
+        }
+        assert!(!to.exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_dir_all_into_itself()(dir) {
+        let (create_file, from, to) = join_all!(dir, "from/hello.txt", "from", "from/backup");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+
+        let err = crate::copy_dir_all(&from, &to).unwrap_err();
+
+        assert!(matches!(err, crate::Error::DestinationInsideSource { .. }));
+        assert!(!to.exists());
+
+        // checked before the preflight walks the source
+        let err = crate::copy_dir_all_with(&from, &to, crate::CopyOptions::new().preflight(true)).unwrap_err();
+        assert!(matches!(err, crate::Error::DestinationInsideSource { .. }));
+
+        let err = crate::move_dir_all_with(&from, &to, crate::CopyOptions::new().preflight(true)).unwrap_err();
+        assert!(matches!(err, crate::Error::DestinationInsideSource { .. }));
+        assert_paths_exists!(create_file);
+        assert!(!to.exists());
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn move_dir_all_par_into_itself()(dir) {
+        let (create_file, from) = join_all!(dir, "from/hello.txt", "from");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+
+        let err = crate::move_dir_all_par(&from, &from).unwrap_err();
+
+        assert!(matches!(err, crate::Error::DestinationInsideSource { .. }));
+        assert!(create_file.exists());
+
+        // checked before the preflight walks the source
+        let err = crate::move_dir_all_par_with(&from, &from, crate::CopyOptions::new().preflight(true)).unwrap_err();
+        assert!(matches!(err, crate::Error::DestinationInsideSource { .. }));
+        assert_paths_exists!(create_file);
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_into_itself_through_symlink()(dir) {
+        let (create_file, from, link) = join_all!(dir, "from/hello.txt", "from", "link");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+        std::os::unix::fs::symlink(&from, &link).unwrap();
+
+        let err = crate::copy_dir_all(&from, link.join("sub/backup")).unwrap_err();
+
+        assert!(matches!(err, crate::Error::DestinationInsideSource { .. }));
+        assert!(!from.join("sub").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_same_file()(dir) {
+        let (from, link) = join_all!(dir, "from", "link");
+        dir.touch_with_contents(&from);
+        let contents = std::fs::read(&from).unwrap();
+        std::fs::hard_link(&from, &link).unwrap();
+
+        let err = crate::copy(&from, &link).unwrap_err();
+
+        assert!(matches!(err, crate::Error::SameFile { .. }));
+        assert_eq!(std::fs::read(&from).unwrap(), contents);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn move_file_same_path()(dir) {
+        let from = dir.join("from");
+        dir.touch_with_contents(&from);
+
+        let err = crate::move_file(&from, dir.join("./from")).unwrap_err();
+
+        assert!(matches!(err, crate::Error::SameFile { .. }));
+        assert_paths_exists!(from);
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_create_to_symlink()(dir) {
+        let (from, link) = join_all!(dir, "from", "link");
+        dir.touch_with_contents(&from);
+        let contents = std::fs::read(&from).unwrap();
+        std::os::unix::fs::symlink(&from, &link).unwrap();
+
+        let err = crate::copy_create(&from, &link).unwrap_err();
+
+        assert!(matches!(err, crate::Error::SameFile { .. }));
+        assert_eq!(std::fs::read(&from).unwrap(), contents);
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_fifo_error()(dir) {
+        let (fifo, from, to) = join_all!(dir, "from/fifo", "from", "to");
+        dir.mkdirp(&from);
+        make_fifo(&fifo);
+
+        let err = crate::copy_dir_all(&from, &to).unwrap_err();
+
+        assert!(matches!(err, crate::Error::SpecialFile { file_type: crate::vfs::FileType::Fifo, .. }));
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_special_files()(dir) {
+        use std::os::unix::fs::FileTypeExt;
+
+        let (fifo, socket, create_file, from, skipped, recreated) =
+            join_all!(dir, "from/fifo", "from/socket", "from/hello.txt", "from", "skipped", "recreated");
+        dir.mkdirp(&from);
+        dir.touch_with_contents(&create_file);
+        make_fifo(&fifo);
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+        let mut options = crate::CopyOptions::new();
+        options.special_files(crate::SpecialFiles::Skip);
+        let report = crate::copy_dir_all_with(&from, &skipped, &options).unwrap();
+        assert_eq!(report.special_files.len(), 2);
+        assert!(report.special_files.iter().all(|file| file.action == crate::SpecialAction::Skipped));
+        assert!(!skipped.join("fifo").exists());
+        assert_file_contents_eq!(&create_file, skipped.join("hello.txt"));
+
+        options.special_files(crate::SpecialFiles::Recreate);
+        let mut report = crate::copy_dir_all_with(&from, &recreated, &options).unwrap();
+        report.special_files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(report.special_files[0].path, fifo);
+        assert_eq!(report.special_files[0].action, crate::SpecialAction::Recreated);
+        assert_eq!(report.special_files[1].path, socket);
+        assert_eq!(report.special_files[1].action, crate::SpecialAction::Skipped);
+        assert!(std::fs::symlink_metadata(recreated.join("fifo")).unwrap().file_type().is_fifo());
+        assert!(!recreated.join("socket").exists());
+    }
+}
+
+#[cfg(all(unix, feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_par_recreate_fifo()(dir) {
+        use std::os::unix::fs::FileTypeExt;
+
+        let (fifo, from, to) = join_all!(dir, "from/a/b/fifo", "from", "to");
+        dir.mkdirp(dir.join("from/a/b"));
+        make_fifo(&fifo);
+
+        let mut options = crate::CopyOptions::new();
+        options.special_files(crate::SpecialFiles::Recreate);
+        let report = crate::copy_dir_all_par_with(&from, &to, &options).unwrap();
+
+        assert_eq!(report.special_files.len(), 1);
+        assert!(std::fs::symlink_metadata(to.join("a/b/fifo")).unwrap().file_type().is_fifo());
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn move_dir_all_keeps_skipped_fifo()(dir) {
+        let (fifo, create_file, other_file, from, to) =
+            join_all!(dir, "from/a/b/fifo", "from/a/hello.txt", "from/c/hello.txt", "from", "to");
+        dir.mkdirp(dir.join("from/a/b"));
+        dir.mkdirp(dir.join("from/c"));
+        dir.touch_with_contents(&create_file);
+        dir.touch_with_contents(&other_file);
+        make_fifo(&fifo);
+
+        let mut options = crate::CopyOptions::new();
+        options.special_files(crate::SpecialFiles::Skip);
+        let report = crate::move_dir_all_with(&from, &to, &options).unwrap();
+
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].path, fifo);
+        assert!(std::fs::symlink_metadata(&fifo).is_ok());
+        assert!(!create_file.exists());
+        assert!(!from.join("c").exists());
+        assert_paths_exists!(to.join("a/hello.txt"), to.join("c/hello.txt"));
+        assert!(!to.join("a/b/fifo").exists());
+    }
+}
+
+#[cfg(all(unix, feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn move_dir_all_par_keeps_skipped_fifo()(dir) {
+        let (fifo, create_file, from, to) = join_all!(dir, "from/a/b/fifo", "from/a/hello.txt", "from", "to");
+        dir.mkdirp(dir.join("from/a/b"));
+        dir.touch_with_contents(&create_file);
+        make_fifo(&fifo);
+
+        let mut options = crate::CopyOptions::new();
+        options.special_files(crate::SpecialFiles::Skip);
+        let report = crate::move_dir_all_par_with(&from, &to, &options).unwrap();
+
+        assert_eq!(report.skipped.len(), 1);
+        assert!(std::fs::symlink_metadata(&fifo).is_ok());
+        assert!(!create_file.exists());
+        assert_paths_exists!(to.join("a/hello.txt"));
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_par_parallelism()(dir) {
+        use std::sync::Arc;
+
+        let (create_dir, create_file, from) = join_all!(dir, "from/b/c", "from/b/c/hello.txt", "from");
+        dir.mkdirp(create_dir);
+        dir.touch_with_contents(&create_file);
+
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        let modes = vec![
+            crate::Parallelism::Threads(0),
+            crate::Parallelism::Pool(pool),
+            crate::Parallelism::Auto,
+        ];
+        for (i, parallelism) in modes.into_iter().enumerate() {
+            let to = dir.join(format!("to{}", i));
+            let mut options = crate::CopyOptions::new();
+            options.parallelism(parallelism);
+
+            crate::copy_dir_all_par_with(&from, &to, &options).unwrap();
+
+            assert_file_contents_eq!(&create_file, to.join("b/c/hello.txt"));
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_par_wide_tree()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        for i in 0..20 {
+            let sub = from.join(format!("dir{}/nested", i));
+            dir.mkdirp(&sub);
+            for j in 0..5 {
+                std::fs::write(sub.join(format!("file{}", j)), format!("{} {}", i, j)).unwrap();
+            }
+        }
+
+        let report = crate::copy_dir_all_par_with(&from, &to, &crate::CopyOptions::new()).unwrap();
+
+        assert_eq!(report.bytes, 20 * 5 * 3 + 10 * 5);
+        assert_eq!((report.files, report.dirs), (100, 41));
+        assert_file_contents_eq!(from.join("dir13/nested/file4"), to.join("dir13/nested/file4"));
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn remove_dir_all_par()(dir) {
+        let (create_dir, create_file, root) = join_all!(dir, "root/a/b/c", "root/a/b/hello.txt", "root");
+        dir.mkdirp(&create_dir);
+        dir.mkdirp(dir.join("root/empty"));
+        dir.touch_with_contents(&create_file);
+
+        crate::remove_dir_all_par(&root).unwrap();
+
+        assert!(!root.exists());
+    }
+}
+
+#[cfg(all(unix, feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn remove_dir_all_par_symlink()(dir) {
+        let (target, file, link) = join_all!(dir, "target", "target/hello.txt", "link");
+        dir.mkdirp(&target);
+        dir.touch_with_contents(&file);
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        crate::remove_dir_all_par(&link).unwrap();
+
+        assert!(!link.exists());
+        assert_paths_exists!(file);
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_read_only_dir()(dir) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (read_only, file, from, to) = join_all!(dir, "from/read_only", "from/read_only/hello.txt", "from", "to");
+        dir.mkdirp(&read_only);
+        dir.touch_with_contents(&file);
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let copied = crate::copy_dir_all(&from, &to);
+        let mode = std::fs::metadata(to.join("read_only")).map(|metadata| metadata.permissions().mode());
+        for path in [&read_only, &to.join("read_only")] {
+            let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755));
+        }
+
+        copied.unwrap();
+        assert_eq!(mode.unwrap() & 0o777, 0o555);
+        assert_file_contents_eq!(file, to.join("read_only/hello.txt"));
+    }
+}
+
+#[cfg(all(unix, feature = "rayon"))]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_par_read_only_dir()(dir) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (read_only, file, from, to) = join_all!(dir, "from/read_only", "from/read_only/hello.txt", "from", "to");
+        dir.mkdirp(&read_only);
+        dir.touch_with_contents(&file);
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let copied = crate::copy_dir_all_par(&from, &to);
+        let mode = std::fs::metadata(to.join("read_only")).map(|metadata| metadata.permissions().mode());
+        for path in [&read_only, &to.join("read_only")] {
+            let _ = std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755));
+        }
+
+        copied.unwrap();
+        assert_eq!(mode.unwrap() & 0o777, 0o555);
+        assert_file_contents_eq!(file, to.join("read_only/hello.txt"));
+    }
+}
+
+/// Creates `from` and `to` with the same four files but different contents, the files in `to`
+/// being newer
+fn conflicting_dirs(from: &std::path::Path, to: &std::path::Path) {
+    use std::time::{Duration, SystemTime};
+
+    for (dir, contents) in [(from, "from"), (to, "to")] {
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["skip.txt", "overwrite.txt", "rename.txt", "sub/newer.txt"] {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+    }
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(from.join("sub/newer.txt"))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+}
+
+fn conflict_options() -> crate::CopyOptions {
+    use crate::ConflictAction;
+
+    let mut options = crate::CopyOptions::new();
+    options.on_conflict(
+        |to, _source, _existing| match to.file_name().unwrap().to_str().unwrap() {
+            "skip.txt" => ConflictAction::Skip,
+            "overwrite.txt" => ConflictAction::Overwrite,
+            "rename.txt" => ConflictAction::Rename,
+            "newer.txt" => ConflictAction::KeepNewer,
+            _ => ConflictAction::Abort,
+        },
+    );
+    options
+}
+
+fn assert_conflicts_resolved(from: &std::path::Path, to: &std::path::Path, report: crate::Report) {
+    use crate::ConflictAction;
+
+    assert_eq!(std::fs::read_to_string(to.join("skip.txt")).unwrap(), "to");
+    assert_eq!(
+        std::fs::read_to_string(to.join("overwrite.txt")).unwrap(),
+        "from"
+    );
+    assert_eq!(
+        std::fs::read_to_string(to.join("rename.txt")).unwrap(),
+        "to"
+    );
+    assert_eq!(
+        std::fs::read_to_string(to.join("rename (1).txt")).unwrap(),
+        "from"
+    );
+    assert_eq!(
+        std::fs::read_to_string(to.join("sub/newer.txt")).unwrap(),
+        "to"
+    );
+
+    let mut conflicts = report.conflicts;
+    conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+    let resolved: Vec<_> = conflicts
+        .iter()
+        .map(|conflict| {
+            (
+                conflict.path.strip_prefix(from).unwrap().to_path_buf(),
+                conflict.action,
+                conflict.copied,
+            )
+        })
+        .collect();
+    assert_eq!(
+        resolved,
+        vec![
+            ("overwrite.txt".into(), ConflictAction::Overwrite, true),
+            ("rename.txt".into(), ConflictAction::Rename, true),
+            ("skip.txt".into(), ConflictAction::Skip, false),
+            ("sub/newer.txt".into(), ConflictAction::KeepNewer, false),
+        ]
+    );
+    assert_eq!(conflicts[1].to, to.join("rename (1).txt"));
+}
+
+fs_fn! {
+    #[test]
+    fn copy_dir_all_with_conflicts()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        conflicting_dirs(&from, &to);
+
+        let report = crate::copy_dir_all_with(&from, &to, &conflict_options()).unwrap();
+
+        assert_conflicts_resolved(&from, &to, report);
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_par_with_conflicts()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        conflicting_dirs(&from, &to);
+
+        let report = crate::copy_dir_all_par_with(&from, &to, &conflict_options()).unwrap();
+
+        assert_conflicts_resolved(&from, &to, report);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_dir_all_with_conflict_abort()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        conflicting_dirs(&from, &to);
+        let mut options = crate::CopyOptions::new();
+        options.on_conflict(|_, _, _| crate::ConflictAction::Abort);
+
+        let err = crate::copy_dir_all_with(&from, &to, &options).unwrap_err();
+
+        assert!(matches!(err, crate::Error::Conflict { .. }));
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn move_dir_all_with_conflicts_keeps_sources()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        conflicting_dirs(&from, &to);
+
+        crate::move_dir_all_with(&from, &to, &conflict_options()).unwrap();
+
+        assert_paths_exists!(from.join("skip.txt"), from.join("sub/newer.txt"));
+        assert!(!from.join("overwrite.txt").exists());
+        assert!(!from.join("rename.txt").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_with_backup()(dir) {
+        use crate::Backup;
+
+        let (from, to) = join_all!(dir, "from", "to");
+        std::fs::write(&to, "0").unwrap();
+        let copy = |backup, contents: &str| {
+            std::fs::write(&from, contents).unwrap();
+            let mut options = crate::CopyOptions::new();
+            options.backup(backup);
+            crate::copy_with(&from, &to, &options).unwrap();
+        };
+
+        copy(Backup::Existing, "1");
+        assert_eq!(std::fs::read_to_string(dir.join("to~")).unwrap(), "0");
+
+        copy(Backup::Numbered, "2");
+        copy(Backup::Numbered, "3");
+        assert_eq!(std::fs::read_to_string(dir.join("to.~1~")).unwrap(), "1");
+        assert_eq!(std::fs::read_to_string(dir.join("to.~2~")).unwrap(), "2");
+
+        copy(Backup::Existing, "4");
+        assert_eq!(std::fs::read_to_string(dir.join("to.~3~")).unwrap(), "3");
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), "4");
+        assert_eq!(std::fs::read_to_string(dir.join("to~")).unwrap(), "0");
+
+        let mut options = crate::CopyOptions::new();
+        options.backup(Backup::Simple).backup_suffix(".bak");
+        crate::copy_with(&from, &to, &options).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("to.bak")).unwrap(), "4");
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_with_backup_existing_gap()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        std::fs::write(&from, "new").unwrap();
+        std::fs::write(&to, "old").unwrap();
+        std::fs::write(dir.join("to.~3~"), "3").unwrap();
+        let mut options = crate::CopyOptions::new();
+        options.backup(crate::Backup::Existing);
+
+        crate::copy_with(&from, &to, &options).unwrap();
+
+        assert_eq!(std::fs::read_to_string(dir.join("to.~4~")).unwrap(), "old");
+        assert!(!dir.join("to.~1~").exists());
+        assert!(!dir.join("to~").exists());
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_with_backup_not_unicode()(dir) {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = |bytes: &[u8]| dir.join(OsStr::from_bytes(bytes));
+        let (from, to) = (dir.join("from"), name(b"to\xff"));
+        std::fs::write(&from, "new").unwrap();
+        std::fs::write(&to, "old").unwrap();
+        // the same name as `to` once both are made valid unicode
+        std::fs::write(name(b"to\xfe.~5~"), "5").unwrap();
+        let mut options = crate::CopyOptions::new();
+        options.backup(crate::Backup::Numbered);
+
+        crate::copy_with(&from, &to, &options).unwrap();
+
+        assert_eq!(std::fs::read_to_string(name(b"to\xff.~1~")).unwrap(), "old");
+        assert!(!name(b"to\xff.~6~").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_with_backup_same_file()(dir) {
+        let from = dir.join("from");
+        std::fs::write(&from, "contents").unwrap();
+        let mut options = crate::CopyOptions::new();
+        options.backup(crate::Backup::Simple);
+
+        let err = crate::copy_with(&from, &from, &options).unwrap_err();
+
+        assert!(matches!(err, crate::Error::SameFile { .. }));
+        assert!(!dir.join("from~").exists());
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_par_with_backup()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(&from);
+        dir.mkdirp(&to);
+        std::fs::write(from.join("file"), "new").unwrap();
+        std::fs::write(to.join("file"), "old").unwrap();
+        let mut options = crate::CopyOptions::new();
+        options.backup(crate::Backup::Numbered);
+
+        crate::copy_dir_all_par_with(&from, &to, &options).unwrap();
+
+        assert_eq!(std::fs::read_to_string(to.join("file")).unwrap(), "new");
+        assert_eq!(std::fs::read_to_string(to.join("file.~1~")).unwrap(), "old");
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_report()(dir) {
+        let (from, to, fifo) = join_all!(dir, "from", "to", "from/sub/fifo");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("file"), "hello").unwrap();
+        std::os::unix::fs::symlink("file", from.join("link")).unwrap();
+        make_fifo(&fifo);
+        let mut options = crate::CopyOptions::new();
+        options.special_files(crate::SpecialFiles::Skip).preflight(true);
+
+        let report = crate::copy_dir_all_with(&from, &to, &options).unwrap();
+
+        assert_eq!((report.files, report.dirs, report.symlinks), (1, 2, 1));
+        assert_eq!(report.bytes, 10);
+        assert_eq!(report.recreated(), 0);
+        assert_eq!(
+            report.skipped,
+            vec![crate::Skipped {
+                path: fifo,
+                reason: crate::SkipReason::SpecialFile,
+            }]
+        );
+        assert!(report.timings.preflight > std::time::Duration::ZERO);
+        assert_eq!(report.timings.total(), report.timings.preflight + report.timings.copy + report.timings.finish);
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn copy_dir_all_par_with_progress()(dir) {
+        use std::sync::{Arc, Mutex};
+
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("a"), "hello").unwrap();
+        std::fs::write(from.join("sub/b"), "hi").unwrap();
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let mut options = crate::CopyOptions::new();
+        let hook = Arc::clone(&progress);
+        options.on_progress(move |from, bytes| hook.lock().unwrap().push((from.to_path_buf(), bytes)));
+
+        let report = crate::copy_dir_all_par_with(&from, &to, &options).unwrap();
+
+        let mut progress = progress.lock().unwrap().clone();
+        progress.sort();
+        assert_eq!(progress, vec![(from.join("a"), 5), (from.join("sub/b"), 2)]);
+        assert_eq!(report.bytes, 7);
+    }
+}
+
+#[cfg(feature = "serde")]
+fs_fn! {
+    #[test]
+    fn report_serde()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(&from);
+        std::fs::write(from.join("file"), "hello").unwrap();
+
+        let report = crate::move_dir_all(&from, &to).unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+
+        assert!(json.contains("\"files\":1"));
+        assert_eq!(serde_json::from_str::<crate::Report>(&json).unwrap(), report);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn std_fs_wrappers()(dir) {
+        let (file, renamed, sub) = join_all!(dir, "file", "renamed", "sub");
+
+        crate::write(&file, "hello").unwrap();
+        crate::rename(&file, &renamed).unwrap();
+        assert_eq!(crate::read(&renamed).unwrap(), b"hello");
+        assert_eq!(crate::read_to_string(&renamed).unwrap(), "hello");
+        assert_eq!(crate::metadata(&renamed).unwrap().len(), 5);
+
+        crate::create_dir(&sub).unwrap();
+        crate::hard_link(&renamed, sub.join("link")).unwrap();
+        let names: Vec<_> = crate::read_dir(&sub)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["link"]);
+
+        let mut permissions = crate::metadata(&renamed).unwrap().permissions();
+        permissions.set_readonly(true);
+        crate::set_permissions(&renamed, permissions).unwrap();
+        assert!(crate::metadata(&renamed).unwrap().permissions().readonly());
+
+        crate::remove_file(sub.join("link")).unwrap();
+        crate::remove_dir(&sub).unwrap();
+        assert!(!sub.exists());
+
+        use std::io::Write;
+        crate::create_file(&file).unwrap().write_all(b"new").unwrap();
+        assert_eq!(crate::open_file(&file).unwrap().metadata().unwrap().len(), 3);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn std_fs_wrappers_errors()(dir) {
+        let missing = dir.join("missing");
+
+        match crate::read_to_string(&missing).unwrap_err() {
+            crate::Error::IoExt { path, operation, .. } => {
+                assert_eq!(path, missing);
+                assert!(matches!(operation, crate::Operation::ReadToString));
+            }
+            e => panic!("unexpected error {}", e),
+        }
+        match crate::rename(&missing, dir.join("to")).unwrap_err() {
+            crate::Error::IoExtMulti { from, operation, .. } => {
+                assert_eq!(from, missing);
+                assert!(matches!(operation, crate::Operation::Rename));
+            }
+            e => panic!("unexpected error {}", e),
+        }
+        assert!(crate::read_dir(&missing).is_err());
+        assert!(crate::open_file(&missing).is_err());
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn std_fs_symlink_wrappers()(dir) {
+        let (file, link) = join_all!(dir, "file", "link");
+        crate::write(&file, "hello").unwrap();
+
+        crate::symlink(&file, &link).unwrap();
+
+        assert_eq!(crate::read_link(&link).unwrap(), file);
+        assert!(crate::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(crate::canonicalize(&link).unwrap(), crate::canonicalize(&file).unwrap());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn file_wrapper()(dir) {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        let path = dir.join("file");
+        let mut file = crate::File::create(&path).unwrap();
+        file.write_all(b"hello world").unwrap();
+        file.set_len(5).unwrap();
+        file.sync_all().unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 5);
+        assert_eq!(file.path(), path);
+
+        let mut file = crate::File::open(&path).unwrap();
+        file.seek(SeekFrom::Start(1)).unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "ello");
+
+        let err = file.write_all(b"read only").unwrap_err();
+        let kind = err.kind();
+        let inner = err.into_inner().unwrap().downcast::<crate::Error>().unwrap();
+        match *inner {
+            crate::Error::IoExt { source, path: err_path, operation } => {
+                assert_eq!(source.kind(), kind);
+                assert_eq!(err_path, path);
+                assert_eq!(operation, crate::Operation::Write);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+
+        let err = crate::File::open(dir.join("missing")).unwrap_err();
+        assert_eq!(err.io_error_kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use test_dir::{fs_fn, join_all};
+
+use crate::{CopyOptions, Error, LockMode, LockOptions, LockStyle};
+
+fn try_once(mode: LockMode, style: LockStyle) -> LockOptions {
+    LockOptions::new()
+        .mode(mode)
+        .style(style)
+        .timeout(Duration::ZERO)
+        .clone()
+}
+
+fn assert_locked<T: std::fmt::Debug>(result: crate::Result<T>) {
+    match result {
+        Err(Error::Locked { .. }) => {}
+        result => panic!("expected the lock to be held, got {:?}", result),
+    }
+}
+
+fn assert_excludes(path: &std::path::Path, style: LockStyle) {
+    let exclusive = try_once(LockMode::Exclusive, style).lock(path).unwrap();
+    assert_locked(try_once(LockMode::Exclusive, style).lock(path));
+    assert_locked(try_once(LockMode::Shared, style).lock(path));
+    exclusive.unlock().unwrap();
+
+    let shared = try_once(LockMode::Shared, style).lock(path).unwrap();
+    let _also_shared = try_once(LockMode::Shared, style).lock(path).unwrap();
+    assert_locked(try_once(LockMode::Exclusive, style).lock(path));
+    drop(shared);
+}
+
+fs_fn! {
+    #[test]
+    fn lock_flock()(dir) {
+        assert_excludes(&dir.join("flock"), LockStyle::Flock);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fs_fn! {
+    #[test]
+    fn lock_ofd()(dir) {
+        assert_excludes(&dir.join("ofd"), LockStyle::Ofd);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn lock_waits_for_release()(dir) {
+        let path = dir.join("lock");
+        let held = LockOptions::new().lock(&path).unwrap();
+
+        let waiter = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                LockOptions::new()
+                    .timeout(Duration::from_secs(10))
+                    .lock(path)
+                    .map(drop)
+            })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        drop(held);
+
+        waiter.join().unwrap().unwrap();
+        LockOptions::new().lock(&path).unwrap();
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn pid_file()(dir) {
+        let path = dir.join("pid.lock");
+        let options = LockOptions::new().timeout(Duration::ZERO).clone();
+
+        let lock = options.pid_file(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap().trim(),
+            std::process::id().to_string()
+        );
+        assert_locked(options.pid_file(&path));
+
+        lock.release().unwrap();
+        assert!(!path.exists());
+        drop(options.pid_file(&path).unwrap());
+        assert!(!path.exists());
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn pid_file_stale()(dir) {
+        let path = dir.join("pid.lock");
+        let options = LockOptions::new().timeout(Duration::ZERO).clone();
+
+        // a process that already exited and was reaped
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, format!("{}\n", dead)).unwrap();
+
+        let _lock = options.pid_file(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap().trim(),
+            std::process::id().to_string()
+        );
+
+        // a lock file without an id is never taken over
+        let other = dir.join("other.lock");
+        std::fs::write(&other, "").unwrap();
+        assert_locked(options.pid_file(&other));
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_dir_all_with_lock()(dir) {
+        let (from, to, to_lock) = join_all!(dir, "from", "to", ".to.lock");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("sub/file"), "file").unwrap();
+
+        let mut options = CopyOptions::new();
+        options.lock(LockOptions::new().timeout(Duration::ZERO).clone());
+
+        let held = LockOptions::new().lock(&to_lock).unwrap();
+        assert_locked(crate::copy_dir_all_with(&from, &to, &options));
+        assert!(!to.exists());
+        drop(held);
+
+        // a shared lock on the source doesn't keep out a copy
+        let _reader = LockOptions::new()
+            .mode(LockMode::Shared)
+            .lock(dir.join(".from.lock"))
+            .unwrap();
+        crate::copy_dir_all_with(&from, &to, &options).unwrap();
+        assert_eq!(std::fs::read_to_string(to.join("sub/file")).unwrap(), "file");
+        assert!(to_lock.exists());
+
+        // but it keeps out a move and a removal
+        assert_locked(crate::move_dir_all_with(&from, dir.join("moved"), &options));
+        assert_locked(crate::remove_dir_all_with(&from, &options));
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn copy_dir_all_with_lock_missing_parent()(dir) {
+        let (from, to) = join_all!(dir, "from", "missing/to");
+        dir.mkdirp(&from);
+        std::fs::write(from.join("file"), "file").unwrap();
+
+        let mut options = CopyOptions::new();
+        options.lock(LockOptions::new().timeout(Duration::ZERO).clone());
+
+        crate::copy_dir_all_with(&from, &to, &options).unwrap();
+        assert!(dir.join("missing/.to.lock").exists());
+        assert_eq!(std::fs::read_to_string(to.join("file")).unwrap(), "file");
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn remove_dir_all_with_lock_missing_parent()(dir) {
+        let (path, to) = join_all!(dir, "typo/nested/path", "to");
+
+        let mut options = CopyOptions::new();
+        options.lock(LockOptions::new().timeout(Duration::ZERO).clone());
+
+        // only the destination of a copy or a move gets its parents created
+        for result in [
+            crate::remove_dir_all_with(&path, &options),
+            crate::move_dir_all_with(&path, &to, &options).map(drop),
+        ] {
+            let e = result.unwrap_err();
+            assert_eq!(e.io_error_kind(), std::io::ErrorKind::NotFound);
+        }
+        assert!(!dir.join("typo").exists());
+        assert!(!dir.join(".to.lock").exists());
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn move_dir_all_par_with_lock()(dir) {
+        let (from, to, moved) = join_all!(dir, "from", "to", "moved");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("sub/file"), "file").unwrap();
+
+        let mut options = CopyOptions::new();
+        options.lock(LockOptions::new().timeout(Duration::from_secs(10)).clone());
+
+        crate::copy_dir_all_par_with(&from, &to, &options).unwrap();
+        crate::move_dir_all_par_with(&from, &moved, &options).unwrap();
+        crate::remove_dir_all_par_with(&to, &options).unwrap();
+
+        assert!(!from.exists());
+        assert!(!to.exists());
+        assert_eq!(std::fs::read_to_string(moved.join("sub/file")).unwrap(), "file");
+    }
+}
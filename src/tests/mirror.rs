@@ -0,0 +1,157 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use test_dir::{fs_fn, join_all};
+
+use crate::{mirror_with, MirrorOptions};
+
+fn options() -> MirrorOptions {
+    MirrorOptions::new()
+        .debounce(Duration::from_millis(20))
+        .clone()
+}
+
+/// Waits until the mirror caught up, the events arrive on another thread
+fn wait_for(what: &str, condition: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "timed out waiting for {}",
+            what
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn contents(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+fs_fn! {
+    #[test]
+    fn mirror_applies_changes()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("sub/a.txt"), "a").unwrap();
+
+        let mirror = mirror_with(&from, &to, &options()).unwrap();
+        assert_eq!(mirror.initial().files, 1);
+        assert_eq!(contents(&to.join("sub/a.txt")).as_deref(), Some("a"));
+
+        std::fs::write(from.join("new.txt"), "new").unwrap();
+        wait_for("a created file", || contents(&to.join("new.txt")).as_deref() == Some("new"));
+
+        std::fs::write(from.join("sub/a.txt"), "changed").unwrap();
+        wait_for("a modified file", || {
+            contents(&to.join("sub/a.txt")).as_deref() == Some("changed")
+        });
+
+        std::fs::create_dir_all(from.join("deep/er")).unwrap();
+        std::fs::write(from.join("deep/er/file"), "deep").unwrap();
+        wait_for("a created tree", || contents(&to.join("deep/er/file")).as_deref() == Some("deep"));
+
+        std::fs::rename(from.join("sub"), from.join("renamed")).unwrap();
+        wait_for("a renamed directory", || {
+            !to.join("sub").exists()
+                && contents(&to.join("renamed/a.txt")).as_deref() == Some("changed")
+        });
+
+        std::fs::remove_file(from.join("new.txt")).unwrap();
+        std::fs::remove_dir_all(from.join("deep")).unwrap();
+        wait_for("removals", || !to.join("new.txt").exists() && !to.join("deep").exists());
+
+        assert!(mirror.is_running());
+        let report = mirror.stop().unwrap();
+        assert!(report.files >= 3);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn mirror_existing_destination()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(&from);
+        dir.mkdirp(to.join("stale"));
+        std::fs::write(from.join("file"), "file").unwrap();
+        std::fs::write(to.join("stale/old"), "old").unwrap();
+
+        let mirror = mirror_with(&from, &to, &options()).unwrap();
+
+        assert_eq!(contents(&to.join("file")).as_deref(), Some("file"));
+        assert!(!to.join("stale").exists());
+        // the unchanged file isn't copied again
+        drop(mirror);
+        let mirror = mirror_with(&from, &to, &options()).unwrap();
+        assert_eq!(mirror.initial().files, 0);
+        mirror.stop().unwrap();
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn mirror_stop_applies_pending_changes()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(&from);
+
+        let mirror = mirror_with(
+            &from,
+            &to,
+            MirrorOptions::new().debounce(Duration::from_secs(60)),
+        )
+        .unwrap();
+        std::fs::write(from.join("file"), "file").unwrap();
+        // give the watcher time to read the event
+        std::thread::sleep(Duration::from_millis(200));
+        mirror.stop().unwrap();
+
+        assert_eq!(contents(&to.join("file")).as_deref(), Some("file"));
+        std::fs::write(from.join("after"), "after").unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!to.join("after").exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn mirror_into_itself()(dir) {
+        let from = dir.join("from");
+        dir.mkdirp(&from);
+
+        assert!(matches!(
+            mirror_with(&from, from.join("inner"), &options()),
+            Err(crate::Error::DestinationInsideSource { .. })
+        ));
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn mirror_symlinks()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(from.join("target"));
+        std::fs::write(from.join("target/file"), "file").unwrap();
+        // an existing destination is compared with the whole source first
+        dir.mkdirp(&to);
+        std::os::unix::fs::symlink("target", from.join("dir_link")).unwrap();
+        std::os::unix::fs::symlink("missing", from.join("dangling")).unwrap();
+        std::os::unix::fs::symlink("target/file", from.join("file_link")).unwrap();
+
+        let mirror = mirror_with(&from, &to, &options()).unwrap();
+        assert_eq!(contents(&to.join("file_link")).as_deref(), Some("file"));
+        assert!(to.join("dir_link").symlink_metadata().is_err());
+        assert!(to.join("dangling").symlink_metadata().is_err());
+
+        std::os::unix::fs::symlink("target", from.join("new_dir_link")).unwrap();
+        std::os::unix::fs::symlink("missing", from.join("new_dangling")).unwrap();
+        // sorted after the symlinks, so they were handled once it arrives
+        std::fs::write(from.join("z"), "z").unwrap();
+        wait_for("a file after the symlinks", || contents(&to.join("z")).as_deref() == Some("z"));
+        assert!(to.join("new_dir_link").symlink_metadata().is_err());
+        assert!(to.join("new_dangling").symlink_metadata().is_err());
+
+        assert!(mirror.is_running());
+        mirror.stop().unwrap();
+    }
+}
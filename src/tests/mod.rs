@@ -1,2 +1,37 @@
+#[cfg(any(feature = "tar", feature = "zip"))]
+mod archive;
+#[cfg(feature = "tokio")]
+mod r#async;
+#[cfg(any(feature = "zstd", feature = "gzip"))]
+mod compress;
+#[cfg(feature = "dedupe")]
+mod dedupe;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "emscripten",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "solaris",
+    target_os = "illumos",
+    target_os = "haiku",
+    target_os = "aix",
+    target_os = "hurd",
+    target_os = "redox",
+    target_os = "cygwin"
+))]
+mod dir;
+#[cfg(feature = "rayon")]
+mod du;
+#[allow(unused_parens)]
 mod general;
+mod lock;
+#[cfg(feature = "notify")]
+mod mirror;
+#[cfg(feature = "tracing")]
+mod trace;
 mod utils;
+mod vfs;
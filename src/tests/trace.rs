@@ -0,0 +1,237 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use test_dir::{fs_fn, join_all};
+use tracing::Dispatch;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Collects everything the subscriber writes
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Captured {
+    type Writer = Captured;
+
+    fn make_writer(&'a self) -> Captured {
+        self.clone()
+    }
+}
+
+impl Captured {
+    fn dispatch(&self) -> Dispatch {
+        self.dispatch_with(LevelFilter::TRACE)
+    }
+
+    fn dispatch_with(&self, level: LevelFilter) -> Dispatch {
+        Dispatch::new(
+            tracing_subscriber::fmt()
+                .with_max_level(level)
+                .with_ansi(false)
+                .with_writer(self.clone())
+                .finish(),
+        )
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let output = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(str::to_owned).collect()
+    }
+}
+
+fn count(lines: &[String], pattern: &str) -> usize {
+    lines.iter().filter(|line| line.contains(pattern)).count()
+}
+
+fs_fn! {
+    #[test]
+    fn trace_copy_dir_all()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("sub/file"), "hello").unwrap();
+        let captured = Captured::default();
+
+        tracing::dispatcher::with_default(&captured.dispatch(), || {
+            crate::copy_dir_all(&from, &to).unwrap();
+        });
+
+        let lines = captured.lines();
+        let span = format!("copy_dir_all{{from={} to={}}}", from.display(), to.display());
+        assert!(lines.iter().all(|line| line.contains(&span)), "{:#?}", lines);
+        assert_eq!(count(&lines, ":copy: more_fs: mkdir"), 2);
+        assert_eq!(count(&lines, ":copy: more_fs: copy from="), 1);
+        assert_eq!(count(&lines, "bytes=5"), 1);
+        assert_eq!(count(&lines, "ERROR"), 0);
+    }
+}
+
+#[cfg(feature = "rayon")]
+fs_fn! {
+    #[test]
+    fn trace_copy_dir_all_par()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(from.join("a/b"));
+        std::fs::write(from.join("a/b/file"), "hello").unwrap();
+        let captured = Captured::default();
+        let dispatch = captured.dispatch();
+        let thread_dispatch = dispatch.clone();
+        // the threads of the pool need the subscriber as well
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .start_handler(move |_| {
+                std::mem::forget(tracing::dispatcher::set_default(&thread_dispatch))
+            })
+            .build()
+            .unwrap();
+        let mut options = crate::CopyOptions::new();
+        options.parallelism(crate::Parallelism::Pool(Arc::new(pool)));
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            crate::copy_dir_all_par_with(&from, &to, &options).unwrap();
+        });
+
+        let lines = captured.lines();
+        assert!(lines.iter().all(|line| line.contains("copy_dir_all_par{")), "{:#?}", lines);
+        assert_eq!(count(&lines, ":walk: more_fs::walk: walk"), 4);
+        assert_eq!(count(&lines, ":walk: more_fs: mkdir"), 3);
+        assert_eq!(count(&lines, ":copy: more_fs: copy from="), 1);
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn trace_error_is_emitted_once()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        let captured = Captured::default();
+
+        tracing::dispatcher::with_default(&captured.dispatch(), || {
+            crate::move_dir_all(&from, &to).unwrap_err();
+        });
+
+        let lines = captured.lines();
+        let errors: Vec<_> = lines.iter().filter(|line| line.contains("ERROR")).collect();
+        assert_eq!(errors.len(), 1, "{:#?}", lines);
+        assert!(errors[0].contains("move_dir_all{"));
+        assert!(errors[0].contains(&format!("path: {:?}", from)));
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn trace_error_is_emitted_once_without_spans()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(&from);
+        std::fs::write(from.join("file"), "file").unwrap();
+        dir.mkdirp(to.join("file"));
+        let captured = Captured::default();
+
+        // the spans are disabled, so an operation can't see its caller in them
+        let dispatch = captured.dispatch_with(LevelFilter::ERROR);
+        tracing::dispatcher::with_default(&dispatch, || {
+            crate::move_dir_all(&from, &to).unwrap_err();
+        });
+
+        let lines = captured.lines();
+        assert_eq!(count(&lines, "ERROR"), 1, "{:#?}", lines);
+
+        #[cfg(feature = "tokio")]
+        {
+            let thread_dispatch = dispatch.clone();
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .on_thread_start(move || {
+                    std::mem::forget(tracing::dispatcher::set_default(&thread_dispatch))
+                })
+                .build()
+                .unwrap();
+            tracing::dispatcher::with_default(&dispatch, || {
+                runtime.block_on(crate::r#async::move_dir_all(&from, &to)).unwrap_err();
+            });
+
+            let lines = captured.lines();
+            assert_eq!(count(&lines, "ERROR"), 2, "{:#?}", lines);
+        }
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn trace_dir_move_dir_all()(dir) {
+        dir.mkdirp(dir.join("from/sub"));
+        std::fs::write(dir.join("from/sub/file"), "hello").unwrap();
+        let handle = crate::Dir::open(dir.path()).unwrap();
+        let captured = Captured::default();
+
+        tracing::dispatcher::with_default(&captured.dispatch(), || {
+            handle.move_dir_all("from", "to").unwrap();
+            handle.copy("to/sub/file", "to/sub/file").unwrap_err();
+        });
+
+        let lines = captured.lines();
+        assert_eq!(count(&lines, "dir_move_dir_all{"), 2, "{:#?}", lines);
+        assert_eq!(count(&lines, "}:dir_copy_dir_all{"), 2);
+        assert_eq!(count(&lines, "more_fs::dir: copy path="), 2);
+        assert_eq!(count(&lines, "ERROR dir_copy{"), 1);
+    }
+}
+
+#[cfg(feature = "tar")]
+fs_fn! {
+    #[test]
+    fn trace_pack_unpack_dir()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("sub/file"), "hello").unwrap();
+        let captured = Captured::default();
+
+        tracing::dispatcher::with_default(&captured.dispatch(), || {
+            let archive = crate::pack_dir(&from, Vec::new()).unwrap();
+            crate::unpack_dir(archive.as_slice(), &to).unwrap();
+        });
+
+        let lines = captured.lines();
+        assert_eq!(count(&lines, "TRACE pack_dir{"), 2, "{:#?}", lines);
+        assert_eq!(count(&lines, "TRACE unpack_dir{"), 2);
+        assert_eq!(count(&lines, "more_fs::archive::tar: unpack path="), 2);
+    }
+}
+
+#[cfg(feature = "tokio")]
+fs_fn! {
+    #[test]
+    fn trace_async_copy_dir_all()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        dir.mkdirp(from.join("sub"));
+        std::fs::write(from.join("sub/file"), "hello").unwrap();
+        let captured = Captured::default();
+        let dispatch = captured.dispatch();
+        let thread_dispatch = dispatch.clone();
+        // the blocking threads need the subscriber as well
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .on_thread_start(move || {
+                std::mem::forget(tracing::dispatcher::set_default(&thread_dispatch))
+            })
+            .build()
+            .unwrap();
+
+        tracing::dispatcher::with_default(&dispatch, || {
+            runtime.block_on(crate::r#async::copy_dir_all(&from, &to)).unwrap();
+        });
+
+        let lines = captured.lines();
+        assert!(lines.iter().all(|line| line.contains("copy_dir_all{")), "{:#?}", lines);
+        assert_eq!(count(&lines, ":copy: more_fs::r#async: mkdir"), 2);
+        assert_eq!(count(&lines, ":copy: more_fs: copy from="), 1);
+    }
+}
@@ -1,8 +1,4 @@
-use std::{
-    path::{Path, PathBuf},
-    process::Command,
-    str,
-};
+use std::{path::Path, process::Command, str};
 
 pub fn clone_repo<P: AsRef<Path>>(url: &str, path: P) {
     let path = path.as_ref();
@@ -11,8 +7,16 @@ pub fn clone_repo<P: AsRef<Path>>(url: &str, path: P) {
     let status = Command::new("git")
         .arg("clone")
         .arg(url)
-        .arg(&path)
+        .arg(path)
         .status()
         .expect("Failed to get status");
     println!("Exit status {}", status);
 }
+
+#[cfg(unix)]
+pub fn make_fifo(path: &Path) {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o644) }, 0);
+}
@@ -0,0 +1,253 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::vfs::{DirEntry, FileSystem, FileType, MemoryFs, Metadata};
+use crate::{CopyOptions, Result};
+
+fn memory_tree() -> MemoryFs {
+    let fs = MemoryFs::new();
+    fs.create_dir_all(Path::new("from/b/c")).unwrap();
+    fs.write("from/hello.txt", "hello").unwrap();
+    fs.write("from/b/c/nested.txt", "nested").unwrap();
+    fs
+}
+
+#[test]
+fn memory_copy_dir_all() {
+    let fs = memory_tree();
+
+    let copied = crate::copy_dir_all_in(&fs, "from", "to", &CopyOptions::new()).unwrap();
+
+    assert_eq!(copied.bytes, 11);
+    assert_eq!(fs.read("to/hello.txt").unwrap(), b"hello");
+    assert_eq!(fs.read("to/b/c/nested.txt").unwrap(), b"nested");
+    assert!(fs.exists("from/b/c/nested.txt"));
+}
+
+/// Records the calls of the methods that have a default, everything else goes to a [`MemoryFs`]
+#[derive(Default)]
+struct Recording {
+    fs: MemoryFs,
+    calls: Mutex<Vec<&'static str>>,
+}
+
+impl FileSystem for Recording {
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        self.fs.metadata(path)
+    }
+
+    fn walk<'a>(&'a self, root: &Path) -> Box<dyn Iterator<Item = Result<DirEntry>> + 'a> {
+        self.fs.walk(root)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<OsString>> {
+        self.calls.lock().unwrap().push("read_dir");
+        self.fs.read_dir(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        self.fs.copy(from, to)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        self.fs.create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.fs.create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        self.fs.remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        self.fs.remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.calls.lock().unwrap().push("rename");
+        self.fs.rename(from, to)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        self.fs.canonicalize(path)
+    }
+
+    fn copy_permissions(&self, _from: &Path, _to: &Path) -> Result<()> {
+        self.calls.lock().unwrap().push("copy_permissions");
+        Ok(())
+    }
+
+    fn sync_dir(&self, _path: &Path) -> Result<()> {
+        self.calls.lock().unwrap().push("sync_dir");
+        Ok(())
+    }
+}
+
+#[test]
+fn memory_copy_dir_all_through_reference() {
+    let fs = memory_tree();
+    let by_ref = &fs;
+    crate::copy_dir_all_in(&by_ref, "from", "to", &CopyOptions::new()).unwrap();
+    assert_eq!(fs.read("to/b/c/nested.txt").unwrap(), b"nested");
+
+    let recording = Recording::default();
+    recording.fs.create_dir_all(Path::new("from/sub")).unwrap();
+    recording.fs.write("from/sub/file", "new").unwrap();
+    recording.fs.create_dir_all(Path::new("to/sub")).unwrap();
+    recording.fs.write("to/sub/file", "old").unwrap();
+    let mut options = CopyOptions::new();
+    options
+        .backup(crate::Backup::Numbered)
+        .on_conflict(|_, _, _| crate::ConflictAction::Overwrite)
+        .durability(crate::Durability::FilesAndDirs);
+
+    // every method has to reach the filesystem behind the reference instead of the defaults
+    let by_ref = &recording;
+    crate::copy_dir_all_in(&by_ref, "from", "to", &options).unwrap();
+
+    let calls = recording.calls.lock().unwrap();
+    for call in ["read_dir", "rename", "copy_permissions", "sync_dir"] {
+        assert!(calls.contains(&call), "{} in {:?}", call, calls);
+    }
+    assert_eq!(recording.fs.read("to/sub/file.~1~").unwrap(), b"old");
+}
+
+#[test]
+fn memory_move_dir_all() {
+    let fs = memory_tree();
+
+    crate::move_dir_all_in(&fs, "/from", "/to", &CopyOptions::new()).unwrap();
+
+    assert_eq!(fs.read("/to/b/c/nested.txt").unwrap(), b"nested");
+    assert!(!fs.exists("from"));
+    assert!(!fs.exists("from/hello.txt"));
+}
+
+#[test]
+fn memory_move_file() {
+    let fs = memory_tree();
+
+    crate::move_file_in(
+        &fs,
+        "from/hello.txt",
+        "to/a/b/moved.txt",
+        &CopyOptions::new(),
+    )
+    .unwrap();
+
+    assert_eq!(fs.read("to/a/b/moved.txt").unwrap(), b"hello");
+    assert!(!fs.exists("from/hello.txt"));
+}
+
+#[test]
+fn memory_copy_dir_all_not_directory() {
+    let fs = memory_tree();
+
+    let err = crate::copy_dir_all_in(&fs, "from/hello.txt", "to", &CopyOptions::new()).unwrap_err();
+
+    assert!(matches!(err, crate::Error::NotDirectory { .. }));
+}
+
+#[test]
+fn memory_copy_dir_all_not_found() {
+    let fs = MemoryFs::new();
+
+    let err = crate::copy_dir_all_in(&fs, "missing", "to", &CopyOptions::new()).unwrap_err();
+
+    assert_eq!(err.io_error_kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn memory_walk_order() {
+    let fs = memory_tree();
+    fs.write("from-sibling", "not inside").unwrap();
+
+    let entries: Vec<_> = fs
+        .walk(Path::new("from"))
+        .map(|entry| entry.unwrap())
+        .map(|entry| (entry.path().to_path_buf(), entry.file_type(), entry.depth()))
+        .collect();
+
+    assert_eq!(
+        entries,
+        vec![
+            (Path::new("from").to_path_buf(), FileType::Dir, 0),
+            (Path::new("from/b").to_path_buf(), FileType::Dir, 1),
+            (Path::new("from/b/c").to_path_buf(), FileType::Dir, 2),
+            (
+                Path::new("from/b/c/nested.txt").to_path_buf(),
+                FileType::File,
+                3
+            ),
+            (Path::new("from/hello.txt").to_path_buf(), FileType::File, 1),
+        ]
+    );
+}
+
+#[test]
+fn memory_create_dir_parent_missing() {
+    let fs = MemoryFs::new();
+
+    let err = fs.create_dir(Path::new("a/b")).unwrap_err();
+
+    assert_eq!(err.io_error_kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn memory_move_dir_all_into_itself() {
+    let fs = memory_tree();
+
+    let err =
+        crate::move_dir_all_in(&fs, "from", "/from/./b/../sub", &CopyOptions::new()).unwrap_err();
+
+    assert!(matches!(err, crate::Error::DestinationInsideSource { .. }));
+    assert!(fs.exists("from/b/c/nested.txt"));
+    assert!(!fs.exists("from/sub"));
+}
+
+#[test]
+fn memory_move_file_same_file() {
+    let fs = memory_tree();
+
+    let err = crate::move_file_in(
+        &fs,
+        "from/hello.txt",
+        "/from/b/../hello.txt",
+        &CopyOptions::new(),
+    )
+    .unwrap_err();
+
+    assert!(matches!(err, crate::Error::SameFile { .. }));
+    assert_eq!(fs.read("from/hello.txt").unwrap(), b"hello");
+}
+
+#[test]
+fn memory_move_file_numbered_backup() {
+    let fs = memory_tree();
+    fs.write("to.txt", "old").unwrap();
+    let mut options = CopyOptions::new();
+    options.backup(crate::Backup::Numbered);
+
+    crate::move_file_in(&fs, "from/hello.txt", "to.txt", &options).unwrap();
+
+    assert_eq!(fs.read("to.txt").unwrap(), b"hello");
+    assert_eq!(fs.read("to.txt.~1~").unwrap(), b"old");
+}
+
+#[test]
+fn memory_simple_backup_doesnt_read_dir() {
+    let recording = Recording::default();
+    recording.fs.write("from.txt", "new").unwrap();
+    recording.fs.write("to.txt", "old").unwrap();
+    let mut options = CopyOptions::new();
+    options.backup(crate::Backup::Simple);
+
+    crate::move_file_in(&recording, "from.txt", "to.txt", &options).unwrap();
+
+    assert_eq!(recording.fs.read("to.txt~").unwrap(), b"old");
+    assert!(!recording.calls.lock().unwrap().contains(&"read_dir"));
+}
@@ -0,0 +1,204 @@
+//! Spans and events for the `tracing` feature flag. Without the feature the spans are empty and
+//! the macros expand to nothing, so the rest of the crate doesn't need any `cfg` for them.
+//!
+//! Every public operation runs in an info span with its paths, its phases in debug spans below
+//! it and every entry it walks, creates, copies or removes is a trace event. An error is emitted
+//! once by the outermost operation it leaves. The wrappers of the standard library functions and
+//! the single entry methods of `Dir` are called for every entry, so they don't get a span.
+
+#[cfg(feature = "tracing")]
+use std::cell::Cell;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+
+use crate::error::Result;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+/// Stands in for [`tracing::Span`] without the `tracing` feature flag
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    #[cfg(any(feature = "rayon", feature = "tokio", feature = "notify"))]
+    pub(crate) fn current() -> Span {
+        Span
+    }
+
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+
+    pub(crate) fn entered(self) -> Span {
+        self
+    }
+}
+
+/// The span of a public operation, run it with [`traced`]
+#[cfg(feature = "tracing")]
+macro_rules! operation {
+    ($($args:tt)*) => {
+        tracing::info_span!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! operation {
+    ($($args:tt)*) => {
+        $crate::trace::Span
+    };
+}
+
+/// The span of a phase of an operation, like the walk or the removal of the source of a move
+#[cfg(feature = "tracing")]
+macro_rules! phase {
+    ($($args:tt)*) => {
+        tracing::debug_span!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! phase {
+    ($($args:tt)*) => {
+        $crate::trace::Span
+    };
+}
+
+/// An event for one entry of a tree
+#[cfg(feature = "tracing")]
+macro_rules! entry {
+    ($($args:tt)*) => {
+        tracing::trace!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! entry {
+    ($($args:tt)*) => {};
+}
+
+pub(crate) use {entry, operation, phase};
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    /// How many operations of this crate are running on this thread. Disabled spans have no
+    /// metadata, so the span of the caller can't tell whether it is an operation of ours
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Sets the depth of this thread until it is dropped
+#[cfg(feature = "tracing")]
+struct Depth(usize);
+
+#[cfg(feature = "tracing")]
+impl Depth {
+    fn set(depth: usize) -> Depth {
+        Depth(DEPTH.with(|current| current.replace(depth)))
+    }
+
+    fn enter() -> Depth {
+        Depth::set(depth() + 1)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Depth {
+    fn drop(&mut self) {
+        DEPTH.with(|current| current.set(self.0));
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn depth() -> usize {
+    DEPTH.with(Cell::get)
+}
+
+/// The span of the running operation and how deeply it is nested, for the threads that do part
+/// of its work
+#[cfg(any(feature = "rayon", feature = "tokio", feature = "notify"))]
+#[derive(Clone)]
+pub(crate) struct Scope {
+    span: Span,
+    #[cfg(feature = "tracing")]
+    depth: usize,
+}
+
+#[cfg(any(feature = "rayon", feature = "tokio", feature = "notify"))]
+impl Scope {
+    pub(crate) fn current() -> Scope {
+        Scope {
+            span: Span::current(),
+            #[cfg(feature = "tracing")]
+            depth: depth(),
+        }
+    }
+
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        let _depth = Depth::set(self.depth);
+        self.span.in_scope(f)
+    }
+}
+
+/// Runs an operation in its span and emits its error, unless it was called by another operation
+/// of this crate that will emit the same error
+pub(crate) fn traced<T>(span: Span, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    #[cfg(feature = "tracing")]
+    let nested = depth() > 0;
+    let result = span.in_scope(|| {
+        #[cfg(feature = "tracing")]
+        let _depth = Depth::enter();
+        f()
+    });
+    #[cfg(feature = "tracing")]
+    if !nested {
+        span.in_scope(|| emit(&result));
+    }
+    result
+}
+
+/// The same as [`traced`] for the operations of the [`async`](crate::async) module
+#[cfg(feature = "tokio")]
+pub(crate) async fn traced_async<T>(
+    span: Span,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    #[cfg(feature = "tracing")]
+    let nested = depth() > 0;
+    // the future may be polled on any thread, so the depth is only set while it is polled
+    #[cfg(feature = "tracing")]
+    let future = {
+        let mut future = Box::pin(future);
+        std::future::poll_fn(move |cx| {
+            let _depth = Depth::enter();
+            future.as_mut().poll(cx)
+        })
+    };
+    let result = instrument(span.clone(), future).await;
+    #[cfg(feature = "tracing")]
+    if !nested {
+        span.in_scope(|| emit(&result));
+    }
+    result
+}
+
+/// Polls the future inside of the span, a span that is entered with [`Span::entered`] can't be
+/// held across an await
+#[cfg(feature = "tokio")]
+pub(crate) async fn instrument<F: Future>(span: Span, future: F) -> F::Output {
+    #[cfg(feature = "tracing")]
+    let future = tracing::Instrument::instrument(future, span);
+    #[cfg(not(feature = "tracing"))]
+    let _ = span;
+    future.await
+}
+
+#[cfg(feature = "tracing")]
+fn emit<T>(result: &Result<T>) {
+    if let Err(e) = result {
+        tracing::error!(error = %e, details = ?e, "operation failed");
+    }
+}
@@ -2,18 +2,35 @@ use std::path::{Path, PathBuf};
 
 use crate::error::{Error, Result};
 
-fn try_in_same_dir(path1: impl AsRef<Path>, path2: impl AsRef<Path>) -> Option<bool> {
-    let path1 = path1.as_ref();
-    let path2 = path2.as_ref();
-
-    let parent1 = path1.parent()?;
-    let parent2 = path2.parent()?;
-
-    Some(parent1 == parent2)
+/// Returns `path` or its closest parent that exists
+pub fn closest_existing(path: &Path) -> PathBuf {
+    let mut path = path;
+    while path.symlink_metadata().is_err() && path != Path::new(".") {
+        path = parent_dir(path);
+    }
+    path.to_path_buf()
 }
 
-pub fn in_same_dir(path1: impl AsRef<Path>, path2: impl AsRef<Path>) -> bool {
-    try_in_same_dir(path1, path2).unwrap_or(false)
+/// Returns true if both paths refer to the same existing file. On unix the device and inode are
+/// compared, which also catches hard links. Other platforms compare the canonical paths
+pub fn same_file(path1: &Path, path2: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match (std::fs::metadata(path1), std::fs::metadata(path2)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        match (std::fs::canonicalize(path1), std::fs::canonicalize(path2)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 pub fn change_dir(
@@ -34,31 +51,11 @@ pub fn change_dir(
     Ok(new_path)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_same_dir() {
-        let path1 = "/home/person/dir/hello.txt";
-        let path2 = "/home/person/dir/goodbye.txt";
-
-        assert!(in_same_dir(path1, path2));
-    }
-
-    #[test]
-    fn in_same_dir_root_test() {
-        let path1 = "/";
-        let path2 = "/";
-
-        assert!(!in_same_dir(path1, path2));
-    }
-
-    #[test]
-    fn in_same_dir_same_test() {
-        let path1 = "/home/person";
-        let path2 = "/home/person";
-
-        assert!(in_same_dir(path1, path2));
+/// Returns the directory containing `path`. A relative path with a single component is inside of
+/// the current directory
+pub fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
     }
 }
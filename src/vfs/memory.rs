@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use super::{DirEntry, FileSystem, FileType, Metadata};
+use crate::error::{Error, Operation};
+use crate::Result;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Dir,
+    File {
+        contents: Vec<u8>,
+        modified: SystemTime,
+    },
+}
+
+impl Node {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Node::Dir => Metadata {
+                file_type: FileType::Dir,
+                len: 0,
+                modified: None,
+                readonly: false,
+            },
+            Node::File { contents, modified } => Metadata {
+                file_type: FileType::File,
+                len: contents.len() as u64,
+                modified: Some(*modified),
+                readonly: false,
+            },
+        }
+    }
+}
+
+/// A filesystem that only lives in memory.
+///
+/// Relative paths are resolved against the root directory `/`, which always exists. Paths are
+/// normalized lexically, so `a/./b` and `a/c/../b` refer to the same entry. Symlinks are not
+/// supported. The filesystem can be shared between threads.
+#[derive(Debug)]
+pub struct MemoryFs {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+impl Default for MemoryFs {
+    fn default() -> Self {
+        MemoryFs::new()
+    }
+}
+
+impl MemoryFs {
+    /// Creates a filesystem that only contains the root directory
+    pub fn new() -> MemoryFs {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), Node::Dir);
+        MemoryFs {
+            nodes: Mutex::new(nodes),
+        }
+    }
+
+    fn nodes(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        // a panic while holding the lock can't leave the map in an invalid state
+        self.nodes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Writes a file, replacing its contents if it already exists. The parent directory has to
+    /// exist, like with [`std::fs::write`]
+    pub fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+        let path = path.as_ref();
+        let key = normalize(path);
+        let mut nodes = self.nodes();
+
+        check_parent(&nodes, &key).map_err(|e| io_ext(e, path, Operation::Write))?;
+        if let Some(Node::Dir) = nodes.get(&key) {
+            return Err(io_ext(is_a_directory(), path, Operation::Write));
+        }
+
+        nodes.insert(
+            key,
+            Node::File {
+                contents: contents.as_ref().to_vec(),
+                modified: SystemTime::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Reads the contents of a file
+    pub fn read(&self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
+        let path = path.as_ref();
+
+        match self.nodes().get(&normalize(path)) {
+            Some(Node::File { contents, .. }) => Ok(contents.clone()),
+            Some(Node::Dir) => Err(io_ext(is_a_directory(), path, Operation::Read)),
+            None => Err(io_ext(not_found(), path, Operation::Read)),
+        }
+    }
+
+    /// Returns true if there is a file or directory at `path`
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        self.nodes().contains_key(&normalize(path.as_ref()))
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        self.nodes()
+            .get(&normalize(path))
+            .map(Node::metadata)
+            .ok_or_else(|| io_ext(not_found(), path, Operation::Metadata))
+    }
+
+    fn walk<'a>(&'a self, root: &Path) -> Box<dyn Iterator<Item = Result<DirEntry>> + 'a> {
+        let key = normalize(root);
+        let nodes = self.nodes();
+
+        if !nodes.contains_key(&key) {
+            let err = Error::IoExt {
+                source: not_found(),
+                path: root.to_path_buf(),
+                operation: Operation::Metadata,
+            };
+            return Box::new(std::iter::once(Err(err)));
+        }
+
+        // paths are ordered by their components, so a directory comes right before its
+        // contents and all of them are next to each other
+        let entries: Vec<_> = nodes
+            .range(key.clone()..)
+            .take_while(|(path, _)| path.starts_with(&key))
+            .map(|(path, node)| {
+                let relative = path.strip_prefix(&key).unwrap();
+                Ok(DirEntry {
+                    path: root.join(relative),
+                    file_type: node.metadata().file_type,
+                    depth: relative.components().count(),
+                })
+            })
+            .collect();
+
+        Box::new(entries.into_iter())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        let io_ext_multi = |source| Error::IoExtMulti {
+            source,
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            operation: Operation::Copy,
+        };
+        let to_key = normalize(to);
+        if normalize(from) == to_key {
+            return Err(Error::SameFile {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+            });
+        }
+        let mut nodes = self.nodes();
+
+        let contents = match nodes.get(&normalize(from)) {
+            Some(Node::File { contents, .. }) => contents.clone(),
+            Some(Node::Dir) => return Err(io_ext_multi(is_a_directory())),
+            None => return Err(io_ext_multi(not_found())),
+        };
+        check_parent(&nodes, &to_key).map_err(io_ext_multi)?;
+        if let Some(Node::Dir) = nodes.get(&to_key) {
+            return Err(io_ext_multi(is_a_directory()));
+        }
+
+        let len = contents.len() as u64;
+        nodes.insert(
+            to_key,
+            Node::File {
+                contents,
+                modified: SystemTime::now(),
+            },
+        );
+        Ok(len)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        let key = normalize(path);
+        let mut nodes = self.nodes();
+
+        if nodes.contains_key(&key) {
+            return Err(io_ext(already_exists(), path, Operation::Create));
+        }
+        check_parent(&nodes, &key).map_err(|e| io_ext(e, path, Operation::Create))?;
+
+        nodes.insert(key, Node::Dir);
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let key = normalize(path);
+        let mut nodes = self.nodes();
+
+        let mut ancestors: Vec<_> = key.ancestors().collect();
+        ancestors.reverse();
+        for ancestor in ancestors {
+            match nodes.get(ancestor) {
+                Some(Node::Dir) => (),
+                Some(Node::File { .. }) => {
+                    return Err(io_ext(already_exists(), path, Operation::CreatePathAll))
+                }
+                None => {
+                    nodes.insert(ancestor.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        let key = normalize(path);
+        let mut nodes = self.nodes();
+
+        match nodes.get(&key) {
+            Some(Node::File { .. }) => {
+                nodes.remove(&key);
+                Ok(())
+            }
+            Some(Node::Dir) => Err(io_ext(is_a_directory(), path, Operation::Remove)),
+            None => Err(io_ext(not_found(), path, Operation::Remove)),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let io_ext_multi = |source| Error::IoExtMulti {
+            source,
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            operation: Operation::Move,
+        };
+        let (from_key, to_key) = (normalize(from), normalize(to));
+        let mut nodes = self.nodes();
+
+        match nodes.get(&from_key) {
+            Some(Node::File { .. }) => (),
+            Some(Node::Dir) => return Err(io_ext_multi(is_a_directory())),
+            None => return Err(io_ext_multi(not_found())),
+        }
+        check_parent(&nodes, &to_key).map_err(io_ext_multi)?;
+        if let Some(Node::Dir) = nodes.get(&to_key) {
+            return Err(io_ext_multi(is_a_directory()));
+        }
+
+        let node = nodes.remove(&from_key).unwrap();
+        nodes.insert(to_key, node);
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        let key = normalize(path);
+        let mut nodes = self.nodes();
+
+        match nodes.get(&key) {
+            Some(Node::Dir) => (),
+            Some(Node::File { .. }) => {
+                return Err(io_ext(not_a_directory(), path, Operation::RemoveDirAll))
+            }
+            None => return Err(io_ext(not_found(), path, Operation::RemoveDirAll)),
+        }
+
+        // the root can be emptied but never removed
+        let removed: Vec<_> = nodes
+            .range(key.clone()..)
+            .map(|(path, _)| path)
+            .take_while(|path| path.starts_with(&key))
+            .filter(|path| path.parent().is_some())
+            .cloned()
+            .collect();
+        for path in removed {
+            nodes.remove(&path);
+        }
+        Ok(())
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        let key = normalize(path);
+
+        if self.nodes().contains_key(&key) {
+            Ok(key)
+        } else {
+            Err(io_ext(not_found(), path, Operation::Canonicalize))
+        }
+    }
+}
+
+/// Resolves `path` lexically against the root directory
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => normalized = PathBuf::from("/"),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(name) => normalized.push(name),
+        }
+    }
+    normalized
+}
+
+fn check_parent(nodes: &BTreeMap<PathBuf, Node>, key: &Path) -> io::Result<()> {
+    match key.parent().map(|parent| nodes.get(parent)) {
+        // the root is its own parent
+        None => Ok(()),
+        Some(Some(Node::Dir)) => Ok(()),
+        Some(Some(Node::File { .. })) => Err(not_a_directory()),
+        Some(None) => Err(not_found()),
+    }
+}
+
+fn io_ext(source: io::Error, path: &Path, operation: Operation) -> Error {
+    Error::IoExt {
+        source,
+        path: path.to_path_buf(),
+        operation,
+    }
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such file or directory")
+}
+
+fn already_exists() -> io::Error {
+    io::Error::new(io::ErrorKind::AlreadyExists, "file exists")
+}
+
+fn is_a_directory() -> io::Error {
+    io::Error::other("is a directory")
+}
+
+fn not_a_directory() -> io::Error {
+    io::Error::other("not a directory")
+}
@@ -0,0 +1,344 @@
+/*!
+An abstraction over the filesystem that the recursive functions of this crate run on.
+
+Every recursive function like [`copy_dir_all`](crate::copy_dir_all) has an `_in` variant like
+[`copy_dir_all_in`](crate::copy_dir_all_in) that is generic over [`FileSystem`]. The normal
+functions use [`RealFs`], which forwards to [`std::fs`]. [`MemoryFs`] keeps everything in memory,
+which makes it possible to test code built on top of this crate without touching the disk.
+
+```
+use more_fs::vfs::{FileSystem, MemoryFs};
+use more_fs::CopyOptions;
+
+let fs = MemoryFs::new();
+fs.create_dir_all("from/nested".as_ref()).unwrap();
+fs.write("from/nested/file.txt", "hello").unwrap();
+
+more_fs::move_dir_all_in(&fs, "from", "to", &CopyOptions::new()).unwrap();
+
+assert_eq!(fs.read("to/nested/file.txt").unwrap(), b"hello");
+assert!(!fs.exists("from"));
+```
+*/
+
+mod memory;
+mod real;
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::Result;
+
+pub use memory::MemoryFs;
+#[cfg(all(unix, feature = "tar"))]
+pub(crate) use real::make_special;
+pub use real::RealFs;
+
+/// The operations that the recursive functions need from a filesystem
+pub trait FileSystem {
+    /// Queries the metadata of a path, following symlinks
+    fn metadata(&self, path: &Path) -> Result<Metadata>;
+
+    /// Walks `root` recursively. Every directory has to be yielded before its contents and
+    /// `root` itself is the first entry.
+    fn walk<'a>(&'a self, root: &Path) -> Box<dyn Iterator<Item = Result<DirEntry>> + 'a>;
+
+    /// Lists the names of the entries of a directory. Keeps the entries of [`FileSystem::walk`]
+    /// that are directly inside of `path` by default
+    fn read_dir(&self, path: &Path) -> Result<Vec<OsString>> {
+        self.walk(path)
+            .filter(|entry| entry.as_ref().map_or(true, |entry| entry.depth == 1))
+            .map(|entry| entry.map(|entry| entry.path.file_name().unwrap_or_default().into()))
+            .collect()
+    }
+
+    /// Copies the contents of a file and returns the amount of bytes copied
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64>;
+
+    /// Creates a new directory, the parent has to exist
+    fn create_dir(&self, path: &Path) -> Result<()>;
+
+    /// Creates a directory and all of its missing parents
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Removes a file
+    fn remove_file(&self, path: &Path) -> Result<()>;
+
+    /// Removes a directory and all of its contents
+    fn remove_dir_all(&self, path: &Path) -> Result<()>;
+
+    /// Renames a file, replacing `to` if it exists. Copies the file and removes `from` by default
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        self.copy(from, to)?;
+        self.remove_file(from)
+    }
+
+    /// Returns the absolute form of an existing path with all symlinks resolved. Returns the path
+    /// unchanged by default, which is right for filesystems without symlinks that only use
+    /// absolute paths
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        Ok(path.to_path_buf())
+    }
+
+    /// Creates a fifo or device file at `to` that is like the one at `from`. Fails with
+    /// [`io::ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported) by default and for file
+    /// types that can't be recreated, like sockets
+    fn create_special(&self, from: &Path, to: &Path, file_type: FileType) -> Result<()> {
+        let _ = file_type;
+        Err(crate::Error::IoExtMulti {
+            source: std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "special files are not supported",
+            ),
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            operation: crate::error::Operation::Create,
+        })
+    }
+
+    /// Copies the permissions of `from` to `to`. Does nothing by default, which is right for
+    /// filesystems without permissions
+    fn copy_permissions(&self, _from: &Path, _to: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes a file to durable storage. Does nothing by default
+    fn sync_file(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes the entries of a directory to durable storage. Does nothing by default
+    fn sync_dir(&self, _path: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FileSystem + ?Sized> FileSystem for &F {
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        (**self).metadata(path)
+    }
+
+    fn walk<'a>(&'a self, root: &Path) -> Box<dyn Iterator<Item = Result<DirEntry>> + 'a> {
+        (**self).walk(root)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<OsString>> {
+        (**self).read_dir(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        (**self).copy(from, to)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        (**self).create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        (**self).create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        (**self).remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        (**self).remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        (**self).rename(from, to)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        (**self).canonicalize(path)
+    }
+
+    fn create_special(&self, from: &Path, to: &Path, file_type: FileType) -> Result<()> {
+        (**self).create_special(from, to, file_type)
+    }
+
+    fn copy_permissions(&self, from: &Path, to: &Path) -> Result<()> {
+        (**self).copy_permissions(from, to)
+    }
+
+    fn sync_file(&self, path: &Path) -> Result<()> {
+        (**self).sync_file(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> Result<()> {
+        (**self).sync_dir(path)
+    }
+}
+
+/// The type of an entry in a [`FileSystem`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    /// A named pipe
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+    /// Anything else the platform knows about
+    Other,
+}
+
+impl FileType {
+    pub fn is_file(self) -> bool {
+        self == FileType::File
+    }
+
+    pub fn is_dir(self) -> bool {
+        self == FileType::Dir
+    }
+
+    pub fn is_symlink(self) -> bool {
+        self == FileType::Symlink
+    }
+
+    /// Returns true for fifos, sockets, device files and other entries that have no contents
+    /// that could be copied
+    pub fn is_special(self) -> bool {
+        !matches!(self, FileType::File | FileType::Dir | FileType::Symlink)
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileType::File => write!(f, "file"),
+            FileType::Dir => write!(f, "directory"),
+            FileType::Symlink => write!(f, "symlink"),
+            FileType::Fifo => write!(f, "fifo"),
+            FileType::Socket => write!(f, "socket"),
+            FileType::CharDevice => write!(f, "character device"),
+            FileType::BlockDevice => write!(f, "block device"),
+            FileType::Other => write!(f, "special file"),
+        }
+    }
+}
+
+impl From<fs::FileType> for FileType {
+    fn from(file_type: fs::FileType) -> Self {
+        if file_type.is_dir() {
+            FileType::Dir
+        } else if file_type.is_file() {
+            FileType::File
+        } else if file_type.is_symlink() {
+            FileType::Symlink
+        } else {
+            special_file_type(file_type)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn special_file_type(file_type: fs::FileType) -> FileType {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        FileType::Fifo
+    } else if file_type.is_socket() {
+        FileType::Socket
+    } else if file_type.is_char_device() {
+        FileType::CharDevice
+    } else if file_type.is_block_device() {
+        FileType::BlockDevice
+    } else {
+        FileType::Other
+    }
+}
+
+#[cfg(not(unix))]
+fn special_file_type(_file_type: fs::FileType) -> FileType {
+    FileType::Other
+}
+
+/// Metadata about an entry in a [`FileSystem`]. This is a subset of [`std::fs::Metadata`] that
+/// can also be created by filesystems that are not backed by the disk
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub(crate) file_type: FileType,
+    pub(crate) len: u64,
+    pub(crate) modified: Option<SystemTime>,
+    pub(crate) readonly: bool,
+}
+
+impl Metadata {
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_file()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type.is_symlink()
+    }
+
+    /// The size of the file in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if the file has a length of zero
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The last modification time, if the filesystem supports it
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+}
+
+impl From<fs::Metadata> for Metadata {
+    fn from(metadata: fs::Metadata) -> Self {
+        Metadata {
+            file_type: metadata.file_type().into(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            readonly: metadata.permissions().readonly(),
+        }
+    }
+}
+
+/// An entry yielded by [`FileSystem::walk`]
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub(crate) path: PathBuf,
+    pub(crate) file_type: FileType,
+    pub(crate) depth: usize,
+}
+
+impl DirEntry {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The type of the entry, symlinks are not followed
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// How deep the entry is below the root of the walk. The root has a depth of zero
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
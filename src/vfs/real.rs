@@ -0,0 +1,140 @@
+use std::ffi::OsString;
+use std::fs;
+#[cfg(unix)]
+use std::io;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+#[cfg(unix)]
+use super::FileType;
+use super::{DirEntry, FileSystem, Metadata};
+use crate::error::{Error, Operation};
+use crate::{durability, Result};
+
+/// The filesystem on the disk. Every operation forwards to the wrapper functions of this crate,
+/// so the errors have the same context.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        crate::metadata(path).map(Metadata::from)
+    }
+
+    fn walk<'a>(&'a self, root: &Path) -> Box<dyn Iterator<Item = Result<DirEntry>> + 'a> {
+        Box::new(WalkDir::new(root).into_iter().map(|entry| {
+            let entry = entry?;
+            Ok(DirEntry {
+                file_type: entry.file_type().into(),
+                depth: entry.depth(),
+                path: entry.into_path(),
+            })
+        }))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<OsString>> {
+        crate::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect()
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<u64> {
+        crate::copy(from, to)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        crate::create_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        crate::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        crate::remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<()> {
+        crate::remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        crate::rename(from, to)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        crate::canonicalize(path)
+    }
+
+    #[cfg(unix)]
+    fn create_special(&self, from: &Path, to: &Path, file_type: FileType) -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let io_ext_multi = |source| Error::IoExtMulti {
+            source,
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+            operation: Operation::Create,
+        };
+        let metadata = fs::symlink_metadata(from).map_err(io_ext_multi)?;
+        make_special(to, file_type, metadata.mode() & 0o7777, metadata.rdev()).map_err(io_ext_multi)
+    }
+
+    fn copy_permissions(&self, from: &Path, to: &Path) -> Result<()> {
+        fs::metadata(from)
+            .and_then(|metadata| fs::set_permissions(to, metadata.permissions()))
+            .map_err(|e| Error::IoExtMulti {
+                source: e,
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+                operation: Operation::SetPermissions,
+            })
+    }
+
+    fn sync_file(&self, path: &Path) -> Result<()> {
+        durability::sync_file(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> Result<()> {
+        durability::sync_dir(path)
+    }
+}
+
+/// Creates a fifo with `mkfifo` or a device file with `mknod`
+#[cfg(unix)]
+pub(crate) fn make_special(
+    path: &Path,
+    file_type: FileType,
+    mode: u32,
+    device: u64,
+) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mode = mode as libc::mode_t;
+
+    // SAFETY: the path is a valid c string
+    let result = unsafe {
+        match file_type {
+            FileType::Fifo => libc::mkfifo(path.as_ptr(), mode),
+            FileType::CharDevice => {
+                libc::mknod(path.as_ptr(), libc::S_IFCHR | mode, device as libc::dev_t)
+            }
+            FileType::BlockDevice => {
+                libc::mknod(path.as_ptr(), libc::S_IFBLK | mode, device as libc::dev_t)
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("a {} can't be recreated", file_type),
+                ))
+            }
+        }
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
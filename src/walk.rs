@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use rayon::Scope;
+
+use crate::error::{Error, Operation, Result};
+use crate::trace;
+use crate::vfs::{DirEntry, FileType};
+
+/// How many entries of one directory are visited by a single task, so wide directories are
+/// still split between threads
+const CHUNK: usize = 64;
+
+/// Walks the tree below `root` in parallel. Every directory is read in its own rayon task, so idle
+/// threads steal whole subtrees and many directories are read at the same time. The tasks are
+/// spawned on a scope instead of nesting, so deep trees don't grow the stack.
+///
+/// `visit` is called for every entry, including the root, and returns whether a directory is
+/// entered. A directory is always visited before anything inside of it. Every task folds the
+/// entries it visits into its own `T` and the results are combined with `merge`. Symlinks are not
+/// followed, except when the root itself is one. The walk stops at the first error. The rayon
+/// tasks run in the span of the caller.
+pub(crate) fn walk_par<T, V, M>(root: &Path, visit: V, merge: M) -> Result<T>
+where
+    T: Default + Send,
+    V: Fn(&DirEntry, &mut T) -> Result<bool> + Sync,
+    M: Fn(T, T) -> T + Sync,
+{
+    let metadata = fs::metadata(root).map_err(|e| io_ext(e, root, Operation::Metadata))?;
+    let entry = DirEntry {
+        path: root.to_path_buf(),
+        file_type: metadata.file_type().into(),
+        depth: 0,
+    };
+
+    let walk = Walk {
+        visit: &visit,
+        merge: &merge,
+        trace: trace::Scope::current(),
+        result: Mutex::new(Ok(T::default())),
+        failed: AtomicBool::new(false),
+    };
+    rayon::scope(|scope| walk.entries(scope, vec![entry]));
+    walk.result.into_inner().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The state that the tasks of one walk share
+struct Walk<'a, T, V, M> {
+    visit: &'a V,
+    merge: &'a M,
+    trace: trace::Scope,
+    /// The merged results of all finished tasks or the first error
+    result: Mutex<Result<T>>,
+    /// Set with the error so the remaining tasks stop early
+    failed: AtomicBool,
+}
+
+impl<'a, T, V, M> Walk<'a, T, V, M>
+where
+    T: Default + Send,
+    V: Fn(&DirEntry, &mut T) -> Result<bool> + Sync,
+    M: Fn(T, T) -> T + Sync,
+{
+    /// Reads the directory that contains the entries at `depth` and spawns tasks to visit them
+    fn dir<'s>(&'s self, scope: &Scope<'s>, dir: &Path, depth: usize) {
+        let mut entries = match read_dir(dir, depth) {
+            Ok(entries) => entries,
+            Err(e) => return self.fail(e),
+        };
+
+        while !entries.is_empty() {
+            let chunk = entries.split_off(entries.len().saturating_sub(CHUNK));
+            scope.spawn(move |scope| self.trace.in_scope(|| self.entries(scope, chunk)));
+        }
+    }
+
+    fn entries<'s>(&'s self, scope: &Scope<'s>, entries: Vec<DirEntry>) {
+        let mut acc = T::default();
+        for entry in entries {
+            if self.failed.load(Ordering::Relaxed) {
+                return;
+            }
+
+            trace::entry!(path = %entry.path.display(), file_type = %entry.file_type, "walk");
+            match (self.visit)(&entry, &mut acc) {
+                Ok(true) if entry.file_type.is_dir() => scope.spawn(move |scope| {
+                    self.trace
+                        .in_scope(|| self.dir(scope, &entry.path, entry.depth + 1))
+                }),
+                Ok(_) => {}
+                Err(e) => return self.fail(e),
+            }
+        }
+
+        let mut result = self.result.lock().unwrap_or_else(|e| e.into_inner());
+        if let Ok(total) = &mut *result {
+            *total = (self.merge)(std::mem::take(total), acc);
+        }
+    }
+
+    fn fail(&self, e: Error) {
+        let mut result = self.result.lock().unwrap_or_else(|e| e.into_inner());
+        if result.is_ok() {
+            *result = Err(e);
+        }
+        self.failed.store(true, Ordering::Relaxed);
+    }
+}
+
+fn read_dir(dir: &Path, depth: usize) -> Result<Vec<DirEntry>> {
+    let read_dir = fs::read_dir(dir).map_err(|e| io_ext(e, dir, Operation::ReadDir))?;
+
+    read_dir
+        .map(|entry| {
+            let entry = entry.map_err(|e| io_ext(e, dir, Operation::ReadDir))?;
+            // the file type usually comes from the directory itself without another stat
+            let file_type = entry
+                .file_type()
+                .map_err(|e| io_ext(e, &entry.path(), Operation::Metadata))?;
+            Ok(DirEntry {
+                path: entry.path(),
+                file_type: FileType::from(file_type),
+                depth,
+            })
+        })
+        .collect()
+}
+
+fn io_ext(source: std::io::Error, path: &Path, operation: Operation) -> Error {
+    Error::IoExt {
+        source,
+        path: path.to_path_buf(),
+        operation,
+    }
+}
@@ -11,6 +11,12 @@ pub struct TestDir(TempDir);
 
 const RAND_BYTES: usize = 512;
 
+impl Default for TestDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TestDir {
     pub fn new() -> TestDir {
         let tempdir = tempfile::Builder::new()
@@ -55,7 +61,7 @@ impl TestDir {
     pub fn mkdirp<P: AsRef<Path>>(&self, path: P) {
         let path = path.as_ref();
 
-        fs::create_dir_all(&path)
+        fs::create_dir_all(path)
             .map_err(|e| err!("failed to create directory {}: {}", path.display(), e))
             .unwrap();
     }
@@ -65,7 +71,7 @@ impl TestDir {
     pub fn touch<P: AsRef<Path>>(&self, path: P) {
         as_ref_all!(path);
 
-        File::create(&path)
+        File::create(path)
             .map_err(|e| err!("failed to create file {}: {}", path.display(), e))
             .unwrap();
     }
@@ -85,7 +91,7 @@ impl TestDir {
         open_opt.create_new(true).write(true);
 
         let mut file = open_opt
-            .open(&path)
+            .open(path)
             .map_err(|e| err!("failed to create file {}: {}", path.display(), e))
             .unwrap();
 
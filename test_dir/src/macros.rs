@@ -84,6 +84,7 @@ macro_rules! assert_paths_exists {
     };
 }
 
+#[cfg(test)]
 macro_rules! assert_macro_testing {
     ($($boolean:expr),+) => {
         assert!(
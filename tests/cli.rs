@@ -0,0 +1,134 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use test_dir::{assert_file_contents_eq, fs_fn, join_all};
+
+fn more_fs(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_more-fs"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn make_tree(from: &Path) {
+    std::fs::create_dir_all(from.join("sub")).unwrap();
+    std::fs::write(from.join("a"), "hello").unwrap();
+    std::fs::write(from.join("sub/b"), "hi").unwrap();
+}
+
+fs_fn! {
+    #[test]
+    fn cli_cp_and_diff()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        make_tree(&from);
+
+        let dry_run = json(&more_fs(&["--json".as_ref(), "cp".as_ref(), "--dry-run".as_ref(), &from, &to]));
+        assert_eq!((dry_run["files"].as_u64(), dry_run["bytes"].as_u64()), (Some(2), Some(7)));
+        assert!(!to.exists());
+
+        let report = json(&more_fs(&["--json".as_ref(), "cp".as_ref(), &from, &to]));
+        assert_eq!(report["files"], dry_run["files"]);
+        assert_file_contents_eq!(from.join("sub/b"), to.join("sub/b"));
+        assert_eq!(more_fs(&["diff".as_ref(), &from, &to]).status.code(), Some(0));
+
+        std::fs::write(to.join("a"), "changed").unwrap();
+        std::fs::write(to.join("extra"), "extra").unwrap();
+        std::fs::remove_file(to.join("sub/b")).unwrap();
+        let output = more_fs(&["--json".as_ref(), "diff".as_ref(), &from, &to]);
+        assert_eq!(output.status.code(), Some(1));
+        let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(diff["only_left"], serde_json::json!(["sub/b"]));
+        assert_eq!(diff["only_right"], serde_json::json!(["extra"]));
+        assert_eq!(diff["changed"], serde_json::json!(["a"]));
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn cli_sync()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        make_tree(&from);
+        assert!(more_fs(&["cp".as_ref(), &from, &to]).status.success());
+        std::fs::write(from.join("a"), "changed").unwrap();
+        std::fs::write(from.join("new"), "new").unwrap();
+        std::fs::write(to.join("extra"), "extra").unwrap();
+
+        let args: [&Path; 6] = ["--json".as_ref(), "sync".as_ref(), "--delete".as_ref(), "--dry-run".as_ref(), &from, &to];
+        let planned = json(&more_fs(&args));
+        assert_eq!((planned["files"].as_u64(), planned["unchanged"].as_u64()), (Some(2), Some(1)));
+        assert!(to.join("extra").exists());
+
+        let synced = json(&more_fs(&[args[0], args[1], args[2], &from, &to]));
+        assert_eq!((synced["files"].as_u64(), synced["unchanged"].as_u64()), (Some(2), Some(1)));
+        assert_eq!(synced["removed"], serde_json::json!([to.join("extra")]));
+        assert_eq!(more_fs(&["diff".as_ref(), &from, &to]).status.code(), Some(0));
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn cli_mv_and_rm()(dir) {
+        let (from, to) = join_all!(dir, "from", "to");
+        make_tree(&from);
+
+        assert!(more_fs(&["mv".as_ref(), &from, &to]).status.success());
+        assert!(!from.exists());
+
+        let removed = json(&more_fs(&["--json".as_ref(), "rm".as_ref(), "--dry-run".as_ref(), &to]));
+        assert_eq!((removed["files"].as_u64(), removed["dirs"].as_u64()), (Some(2), Some(2)));
+        assert!(to.exists());
+        assert!(more_fs(&["rm".as_ref(), &to]).status.success());
+        assert!(!to.exists());
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn cli_exit_codes()(dir) {
+        let (from, missing) = join_all!(dir, "from", "missing");
+        make_tree(&from);
+
+        assert_eq!(more_fs(&["cp".as_ref(), &missing, &from]).status.code(), Some(66));
+        assert_eq!(more_fs(&["cp".as_ref(), &from, &from.join("sub/inner")]).status.code(), Some(64));
+        assert_eq!(more_fs(&["cp".as_ref()]).status.code(), Some(2));
+        let output = more_fs(&["rm".as_ref(), &missing]);
+        assert_eq!(output.status.code(), Some(66));
+        assert!(String::from_utf8(output.stderr).unwrap().starts_with("more-fs: "));
+    }
+}
+
+fs_fn! {
+    #[test]
+    fn cli_single_file_flags()(dir) {
+        let (a, b) = join_all!(dir, "a", "b");
+        std::fs::write(&a, "new").unwrap();
+        std::fs::write(&b, "old").unwrap();
+
+        let args: [&Path; 5] = ["cp".as_ref(), "--conflict".as_ref(), "abort".as_ref(), &a, &b];
+        assert_eq!(more_fs(&args).status.code(), Some(2));
+        let args: [&Path; 5] = ["mv".as_ref(), "--special-files".as_ref(), "skip".as_ref(), &a, &b];
+        assert_eq!(more_fs(&args).status.code(), Some(2));
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "old");
+        assert!(a.exists());
+    }
+}
+
+#[cfg(unix)]
+fs_fn! {
+    #[test]
+    fn cli_single_fifo()(dir) {
+        let (fifo, to) = join_all!(dir, "fifo", "to");
+        let path = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o644) }, 0);
+
+        assert_eq!(more_fs(&["cp".as_ref(), &fifo, &to]).status.code(), Some(65));
+        assert!(!to.exists());
+    }
+}